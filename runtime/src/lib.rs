@@ -50,3 +50,46 @@ pub extern fn malloc(size: usize) -> *mut u8 {
 
     res
 }
+
+// Memory for values which don't have a fixed location, e.g. the ones wrapped in interface values
+pub extern "C" fn photon_alloc(size: u64) -> *mut u8 {
+    unsafe { std::alloc::alloc(Layout::from_size_align((size as usize).max(1), align_of::<u64>()).unwrap()) }
+}
+
+// Lists are laid out as `{ u64 length, [T; length] }`, the compiled code only holds a pointer to them
+const LIST_HEADER_SIZE: usize = size_of::<u64>();
+
+pub extern "C" fn photon_list_new(length: u64, element_size: u64) -> *mut u8 {
+    let size = LIST_HEADER_SIZE + (length * element_size) as usize;
+
+    unsafe {
+        let list = std::alloc::alloc(Layout::from_size_align(size, align_of::<u64>()).unwrap());
+
+        (list as *mut u64).write(length);
+
+        list
+    }
+}
+
+pub extern "C" fn photon_list_check_index(index: i64, length: u64) {
+    if index < 0 || index as u64 >= length {
+        eprintln!("List index out of bounds: the length is {} but the index is {}", length, index);
+
+        std::process::abort()
+    }
+}
+
+// Division by zero and `i64::MIN / -1` are undefined behaviour in LLVM, so they're checked first
+pub extern "C" fn photon_int_check_div(a: i64, b: i64) {
+    if b == 0 {
+        eprintln!("Division by zero");
+
+        std::process::abort()
+    }
+
+    if a == i64::MIN && b == -1 {
+        eprintln!("Integer overflow: {} / {}", a, b);
+
+        std::process::abort()
+    }
+}
@@ -0,0 +1,79 @@
+use std::fmt::{Debug, Formatter};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+#[derive(Debug)]
+pub struct Arena<T: Sized> {
+    values: Vec<T>
+}
+
+pub struct ArenaRef<T: Sized> {
+    i: usize,
+    refers_to: PhantomData<T>
+}
+
+// These are implemented manually, deriving would require the same traits on T
+impl <T: Sized> Clone for ArenaRef<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl <T: Sized> Copy for ArenaRef<T> {}
+
+impl <T: Sized> PartialEq for ArenaRef<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.i == other.i
+    }
+}
+
+impl <T: Sized> Eq for ArenaRef<T> {}
+
+impl <T: Sized> Hash for ArenaRef<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.i.hash(state)
+    }
+}
+
+impl <T: Sized> Debug for ArenaRef<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ArenaRef({})", self.i)
+    }
+}
+
+impl <T: Sized> Arena<T> {
+    // TODO: Optimize by giving it capacity from the start
+    pub fn new() -> Self {
+        Self { values: Vec::new() }
+    }
+
+    pub fn allocate(&mut self, value: T) -> ArenaRef<T> {
+        let i = self.values.len();
+
+        self.values.push(value);
+
+        ArenaRef { i, refers_to: PhantomData }
+    }
+
+    pub fn get(&self, arena_ref: ArenaRef<T>) -> &T {
+        &self.values[arena_ref.i]
+    }
+
+    pub fn set(&mut self, arena_ref: ArenaRef<T>, value: T) {
+        self.values[arena_ref.i] = value
+    }
+
+    pub fn map<R>(self, mapper: fn(T) -> R) -> Arena<R> {
+        Arena { values: self.values.into_iter().map(mapper).collect() }
+    }
+}
+
+impl <T: Sized + PartialEq> Arena<T> {
+    // PERFORMANCE: This is a linear search, use a hash map if there are many values
+    pub fn intern(&mut self, value: T) -> ArenaRef<T> {
+        match self.values.iter().position(|existing| *existing == value) {
+            Some(i) => ArenaRef { i, refers_to: PhantomData },
+            None => self.allocate(value)
+        }
+    }
+}
@@ -6,10 +6,22 @@ pub struct AST {
     pub location: ast::Location
 }
 
+impl AST {
+    /// The `##` comments written right before a `val` or a function
+    pub fn doc_comment(&self) -> Option<&str> {
+        match &self.value {
+            Value::Let { doc_comment, .. } => doc_comment.as_deref(),
+            Value::Function(Function { doc_comment, .. }) => doc_comment.as_deref(),
+            _ => None
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Value {
     Literal(Literal),
     Block(Vec<AST>),
+    List(Vec<AST>),
 
     Function(Function),
     Call {
@@ -23,16 +35,35 @@ pub enum Value {
         name: Box<str>,
         value: Box<AST>,
         recursive: bool,
-        comptime: bool
+        comptime: bool,
+
+        // `var` instead of `val`
+        mutable: bool,
+        doc_comment: Option<Box<str>>
     },
     NameRef(Box<str>),
 
+    // `name = value`, compound assignments like `a += 1` are desugared to `a = a + 1`
+    Assign {
+        name: Box<str>,
+        value: Box<AST>
+    },
+
     If {
         condition: Box<AST>,
         on_true: Box<AST>,
         on_false: Option<Box<AST>>
     },
 
+    While {
+        condition: Box<AST>,
+        body: Box<AST>
+    },
+
+    // Only valid inside of the body of a `while`
+    Break,
+    Continue,
+
     FnType {
         params: Vec<TypeParam>,
         return_type: Box<AST>
@@ -43,14 +74,18 @@ pub enum Value {
         typ: Box<AST>
     },
 
-    CompileTimeExpr(Box<AST>)
+    CompileTimeExpr(Box<AST>),
+
+    // Code which could not be parsed, see `Parser::read_all_recovering`
+    Error
 }
 
 #[derive(Debug, Clone)]
 pub struct Function {
     pub params: Vec<Param>,
     pub body: Box<AST>,
-    pub return_type: Option<Box<AST>>
+    pub return_type: Option<Box<AST>>,
+    pub doc_comment: Option<Box<str>>
 }
 
 #[derive(Debug, Clone)]
@@ -24,7 +24,15 @@ impl Display for Value {
                 }
             }
 
-            Value::Function(Function { params, return_type, body }) => {
+            Value::List(values) => {
+                write!(f, "(list")?;
+                for ast in values {
+                    write!(f, " {}", ast)?;
+                }
+                write!(f, ")")
+            }
+
+            Value::Function(Function { params, return_type, body, .. }) => {
                 write!(f, "(fn [")?;
 
                 for (i, param) in params.iter().enumerate() {
@@ -65,7 +73,7 @@ impl Display for Value {
                 write!(f, ")")
             }
 
-            Value::Let { name, value, recursive, comptime } => {
+            Value::Let { name, value, recursive, comptime, mutable, .. } => {
                 write!(f, "(")?;
 
                 if *comptime {
@@ -74,6 +82,8 @@ impl Display for Value {
 
                 if *recursive {
                     write!(f, "let-rec {} {})", name, value)
+                } else if *mutable {
+                    write!(f, "let-var {} {})", name, value)
                 } else {
                     write!(f, "let {} {})", name, value)
                 }
@@ -81,6 +91,8 @@ impl Display for Value {
 
             Value::NameRef(name) => write!(f, "{}", name),
 
+            Value::Assign { name, value } => write!(f, "(= {} {})", name, value),
+
             Value::If { condition, on_true, on_false } => {
                 write!(f, "(if {} {}", condition, on_true)?;
 
@@ -91,6 +103,11 @@ impl Display for Value {
                 write!(f, ")")
             },
 
+            Value::While { condition, body } => write!(f, "(while {} {})", condition, body),
+
+            Value::Break => write!(f, "(break)"),
+            Value::Continue => write!(f, "(continue)"),
+
             Value::FnType { params, return_type } => {
                 write!(f, "(fn-type [")?;
 
@@ -108,6 +125,8 @@ impl Display for Value {
             Value::TypeAssert { value, typ } => write!(f, "(type-assert {} {})", value, typ),
 
             Value::CompileTimeExpr(ast) => write!(f, "@{}", ast),
+
+            Value::Error => write!(f, "(error)"),
         }
     }
 }
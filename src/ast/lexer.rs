@@ -1,12 +1,16 @@
-use std::rc::Rc;
-use crate::ast::location::{Location, Position};
+use std::collections::VecDeque;
+use crate::ast::location::{FileId, Location, Position};
+use crate::diagnostics::Diagnostic;
 use crate::ast::TokenValue::*;
 
 #[derive(Debug, Clone)]
 pub struct Token {
     pub value: TokenValue,
     pub location: Location,
-    pub whitespace_before: bool
+    pub whitespace_before: bool,
+
+    // The `##` comments right before this token
+    pub doc_comment: Option<Box<str>>
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -14,23 +18,105 @@ pub enum TokenValue {
     EOF, NewLine,
     OpenParen, CloseParen, OpenBrace, CloseBrace, OpenBracket, CloseBracket,
     Comma, Dot, At, Colon,
-    Val, Recursive, If, Else, Then,
+    Val, Var, Recursive, If, Else, Then, While, Break, Continue,
     Equal, Plus, Minus, Asterisk, Slash, LessThan, GreaterThan,
     EqualEqual, PlusEqual, MinusEqual, AsteriskEqual, SlashEqual, LessThanEqual, GreaterThanEqual, NotEqual,
     Not, And, Or,
-    Name(Box<str>), IntLiteral(Box<str>), DecimalLiteral(Box<str>), StringLiteral(Box<str>), BoolLiteral(bool)
+    Name(Box<str>), IntLiteral(Box<str>), DecimalLiteral(Box<str>), StringLiteral(Box<str>), BoolLiteral(bool),
+
+    // Interpolated strings, `"a ${b} c ${d} e"` is lexed as
+    // StringStart("a "), <tokens of b>, StringMiddle(" c "), <tokens of d>, StringEnd(" e")
+    StringStart(Box<str>), StringMiddle(Box<str>), StringEnd(Box<str>)
 }
 
 #[derive(Debug)]
 pub enum LexerError {
     UnclosedStringLiteral(Position),
-    UnexpectedToken(char, Position)
+    InvalidNumberLiteral(Position),
+    InvalidEscapeSequence(Position),
+    UnclosedBlockComment(Position),
+    UnexpectedToken(char, Position),
+    MixedIndentation(Position)
+}
+
+impl TokenValue {
+    /// How the token is called in error messages
+    pub fn describe(&self) -> String {
+        let symbol = match self {
+            TokenValue::EOF => return "end of file".into(),
+            NewLine => return "new line".into(),
+            Name(name) => return format!("name `{}`", name),
+            IntLiteral(value) | DecimalLiteral(value) => return format!("number `{}`", value),
+            StringLiteral(_) | StringStart(_) | StringMiddle(_) | StringEnd(_) => return "string".into(),
+            BoolLiteral(value) => return format!("`{}`", value),
+
+            OpenParen => "(", CloseParen => ")", OpenBrace => "{", CloseBrace => "}",
+            OpenBracket => "[", CloseBracket => "]",
+            Comma => ",", Dot => ".", At => "@", Colon => ":",
+            Val => "val", Var => "var", Recursive => "rec", If => "if", Else => "else", Then => "then",
+            While => "while", Break => "break", Continue => "continue",
+            Equal => "=", Plus => "+", Minus => "-", Asterisk => "*", Slash => "/",
+            LessThan => "<", GreaterThan => ">",
+            EqualEqual => "==", PlusEqual => "+=", MinusEqual => "-=", AsteriskEqual => "*=",
+            SlashEqual => "/=", LessThanEqual => "<=", GreaterThanEqual => ">=", NotEqual => "!=",
+            Not => "!", And => "and", Or => "or"
+        };
+
+        format!("`{}`", symbol)
+    }
+}
+
+impl LexerError {
+    pub fn to_diagnostic(&self, file: FileId) -> Diagnostic {
+        let at = |position: &Position| Location { file, from: *position, to: *position };
+
+        match self {
+            LexerError::UnclosedStringLiteral(position) =>
+                Diagnostic::error("E0001", "Unclosed string literal")
+                    .with_primary(at(position), "the string starts here")
+                    .with_help("add a closing `\"`"),
+
+            LexerError::InvalidNumberLiteral(position) =>
+                Diagnostic::error("E0002", "Invalid number literal")
+                    .with_primary(at(position), "unexpected character in the number"),
+
+            LexerError::InvalidEscapeSequence(position) =>
+                Diagnostic::error("E0003", "Invalid escape sequence")
+                    .with_primary(at(position), "unknown escape")
+                    .with_note("supported escapes are \\r \\n \\t \\0 \\\\ \\\" \\$ \\xHH and \\u{HHHHHH}"),
+
+            LexerError::UnclosedBlockComment(position) =>
+                Diagnostic::error("E0004", "Unclosed block comment")
+                    .with_primary(at(position), "the comment starts here")
+                    .with_help("add a closing `]#`"),
+
+            LexerError::MixedIndentation(position) =>
+                Diagnostic::error("E0006", "Mixed indentation in a multi-line string")
+                    .with_primary(at(position), "this is indented differently than the other lines")
+                    .with_help("indent all lines of the string with the same spaces or tabs"),
+
+            LexerError::UnexpectedToken(c, position) => {
+                let location = Location {
+                    file,
+                    from: *position,
+                    to: Position {
+                        line: position.line,
+                        column: position.column + 1,
+                        offset: position.offset + c.len_utf8() as u32
+                    }
+                };
+
+                Diagnostic::error("E0005", format!("Unexpected character `{}`", c))
+                    .with_primary(location, "")
+            }
+        }
+    }
 }
 
 pub struct Lexer<I: Iterator<Item = char>> {
     chars: I,
 
-    pub file: Rc<str>,
+    pub file: FileId,
 
     c: char,
     pub position: Position,
@@ -40,42 +126,115 @@ pub struct Lexer<I: Iterator<Item = char>> {
 
     at_start: bool,
     had_newline: bool,
+
+    // Interpolated strings are read as a whole, so their tokens are queued here
+    pending: VecDeque<Token>,
+
+    // Doc comments read since the last token, they get attached to the next non-newline token
+    doc_comment: Option<String>,
+
+    // Only kept in lossless mode, everything needed to build a `SyntaxNode` tree
+    recording: Option<Recording>
+}
+
+pub struct Recording {
+    pub source: String,
+    pub tokens: Vec<Token>
 }
 
 const EOF: char = '\0';
 
+// The lexer treats `\0` as the end of the input, so it can never be part of the text of a string
+// and is used to mark where the interpolated expressions are while the string is being processed
+const INTERPOLATION: char = EOF;
+
+struct Interpolation {
+    // Right after `${`
+    from: Position,
+
+    // At the closing `}`
+    to: Position,
+
+    tokens: Vec<Token>
+}
+
 impl <I: Iterator<Item = char>> Lexer<I> {
-    pub fn new(file: &str, chars: I) -> Self {
+    pub fn new(file: FileId, chars: I) -> Self {
         Lexer {
             chars,
 
-            file: Rc::from(file),
+            file,
 
             c: EOF,
-            position: Position { line: 0, column: -1 },
+            position: Position { line: 0, column: -1, offset: 0 },
 
             next_c: EOF,
-            next_position: Position { line: 0, column: 0 },
+            next_position: Position { line: 0, column: 0, offset: 0 },
 
             at_start: true,
             had_newline: false,
+
+            pending: VecDeque::new(),
+
+            doc_comment: None,
+
+            recording: None
         }
     }
 
+    /// A lexer which also remembers the source and all tokens it produced, so that comments and
+    /// whitespace can be recovered. See `Parser::read_all_as_syntax_tree`.
+    pub fn new_lossless(file: FileId, chars: I) -> Self {
+        let mut lexer = Self::new(file, chars);
+
+        lexer.recording = Some(Recording { source: String::new(), tokens: Vec::new() });
+
+        lexer
+    }
+
+    pub fn take_recording(&mut self) -> Option<Recording> {
+        self.recording.take()
+    }
+
     pub fn next(&mut self) -> Result<Token, LexerError> {
+        let token = self.read_token()?;
+
+        if let Some(recording) = &mut self.recording {
+            recording.tokens.push(token.clone());
+        }
+
+        Ok(token)
+    }
+
+    fn read_token(&mut self) -> Result<Token, LexerError> {
         if self.at_start {
             self.init_read();
             self.advance();
         }
 
-        let had_whitespace = self.skip_whitespace_and_comments() || self.had_newline;
+        if let Some(token) = self.pending.pop_front() {
+            return Ok(token);
+        }
+
+        let had_whitespace = self.skip_whitespace_and_comments()? || self.had_newline;
         self.had_newline = false;
 
+        let mut token = self.next_unqueued(had_whitespace)?;
+
+        if token.value != NewLine {
+            token.doc_comment = self.doc_comment.take().map(String::into_boxed_str);
+        }
+
+        Ok(token)
+    }
+
+    fn next_unqueued(&mut self, had_whitespace: bool) -> Result<Token, LexerError> {
         if self.c == EOF {
             return Ok(Token {
                 value: TokenValue::EOF,
-                location: Location { file: self.file.clone(), from: self.position, to: self.position },
-                whitespace_before: had_whitespace
+                location: Location { file: self.file, from: self.position, to: self.position },
+                whitespace_before: had_whitespace,
+                doc_comment: None
             })
         }
 
@@ -115,78 +274,302 @@ impl <I: Iterator<Item = char>> Lexer<I> {
             ('>', _) => self.one_char_token(GreaterThan, had_whitespace),
             ('!', _) => self.one_char_token(Not, had_whitespace),
 
-            ('"', _) => self.read_string(had_whitespace),
+            ('"', _) |
+            ('r', '"') |
+            ('r', '#') => self.read_string(had_whitespace),
 
             (c, _) if c.is_digit(10) => self.read_number(had_whitespace),
             (c, _) if is_start_of_name(c) => self.read_atom(had_whitespace),
 
-            (c, _) => Err(LexerError::UnexpectedToken(c, self.position))
+            (c, _) => {
+                let position = self.position;
+
+                // Skip it so that the parser can continue after the error
+                self.advance();
+
+                Err(LexerError::UnexpectedToken(c, position))
+            }
         }
     }
 
     fn read_string(&mut self, had_whitespace: bool) -> Result<Token, LexerError> {
-        let mut string = String::new();
         let from = self.position;
 
+        // r"..." and r#"..."# disable escapes, the hashes allow `"` inside the string
+        let raw = self.c == 'r';
+        let mut hashes = 0;
+        if raw {
+            self.advance(); // r
+
+            while self.c == '#' {
+                hashes += 1;
+                self.advance(); // #
+            }
+
+            if self.c != '"' {
+                return Err(LexerError::UnexpectedToken(self.c, self.position));
+            }
+        }
+
         self.advance(); // "
 
-        let mut in_escape_sequence = false;
+        // `""` is an empty string, `"""` starts a multi-line one
+        let multiline = self.c == '"' && self.next_c == '"';
+        if multiline {
+            self.advance(); // "
+            self.advance(); // "
+        }
+
+        let mut chars = Vec::new();
+        let mut interpolations = Vec::new();
 
-        while in_escape_sequence || self.c != '"' {
+        loop {
             if self.c == EOF {
                 return Err(LexerError::UnclosedStringLiteral(from));
-            } else if in_escape_sequence {
-                string.push(match self.c {
-                    'r' => '\r',
-                    'n' => '\n',
-                    't' => '\t',
-                    '0' => '\0',
-                    c => c
-                });
-                in_escape_sequence = false;
-            } else if self.c == '\\' {
-                in_escape_sequence = true;
+            }
+
+            if !raw && self.c == '\\' {
+                // Escapes are decoded after the whole string is read, we only need to make sure
+                // that `\"` does not end the string
+                let position = self.position;
+                chars.push((self.advance(), position));
+
+                if self.c == EOF {
+                    return Err(LexerError::UnclosedStringLiteral(from));
+                }
+            } else if !raw && self.c == '$' && self.next_c == '{' {
+                chars.push((INTERPOLATION, self.position));
+
+                self.advance(); // $
+                self.advance(); // {
+
+                interpolations.push(self.read_interpolation(from)?);
+
+                self.advance(); // }
+
+                continue;
+            } else if self.c == '"' {
+                if self.read_string_end(multiline, hashes, &mut chars) {
+                    break;
+                }
+
+                // Only part of the closing sequence, `read_string_end` kept it as content
+                continue;
+            }
+
+            let position = self.position;
+            chars.push((self.advance(), position));
+        }
+
+        let chars = if multiline { strip_indentation(chars)? } else { chars };
+
+        if raw {
+            return Ok(Token {
+                value: StringLiteral(chars.into_iter().map(|(c, _)| c).collect::<String>().into()),
+                location: Location { file: self.file, from, to: self.position },
+                whitespace_before: had_whitespace,
+                doc_comment: None
+            })
+        }
+
+        let mut segments = Vec::with_capacity(interpolations.len() + 1);
+        for segment in chars.split(|(c, _)| *c == INTERPOLATION) {
+            segments.push(decode_escapes(segment)?);
+        }
+
+        if interpolations.is_empty() {
+            return Ok(Token {
+                value: StringLiteral(segments.pop().unwrap().into()),
+                location: Location { file: self.file, from, to: self.position },
+                whitespace_before: had_whitespace,
+                doc_comment: None
+            })
+        }
+
+        let last = interpolations.len();
+        let mut segment_from = from;
+        let mut tokens = Vec::new();
+
+        for (i, segment) in segments.into_iter().enumerate() {
+            let (value, segment_to) = if i == 0 {
+                (StringStart(segment.into()), interpolations[i].from)
+            } else if i == last {
+                (StringEnd(segment.into()), self.position)
             } else {
-                string.push(self.c);
+                (StringMiddle(segment.into()), interpolations[i].from)
+            };
+
+            tokens.push(Token {
+                value,
+                location: Location { file: self.file, from: segment_from, to: segment_to },
+                whitespace_before: i == 0 && had_whitespace,
+                doc_comment: None
+            });
+
+            if i != last {
+                let interpolation = &mut interpolations[i];
+
+                segment_from = interpolation.to;
+                tokens.append(&mut interpolation.tokens);
             }
+        }
 
-            self.advance();
+        let mut tokens = VecDeque::from(tokens);
+        let first = tokens.pop_front().unwrap();
+
+        self.pending.append(&mut tokens);
+
+        Ok(first)
+    }
+
+    // Lexes the expression inside `${...}` up to (but not including) the matching `}`
+    fn read_interpolation(&mut self, string_from: Position) -> Result<Interpolation, LexerError> {
+        let from = self.position;
+        let mut tokens = Vec::new();
+        let mut nested_brace_level = 0;
+
+        loop {
+            // Strings inside the interpolation queue their tokens too, so these have to go first
+            let token = match self.pending.pop_front() {
+                Some(token) => token,
+                None => {
+                    let had_whitespace = self.skip_whitespace_and_comments()? || self.had_newline;
+                    self.had_newline = false;
+
+                    if self.c == '}' && nested_brace_level == 0 {
+                        return Ok(Interpolation { from, to: self.position, tokens });
+                    }
+
+                    self.next_unqueued(had_whitespace)?
+                }
+            };
+
+            match token.value {
+                TokenValue::EOF => return Err(LexerError::UnclosedStringLiteral(string_from)),
+                OpenBrace => nested_brace_level += 1,
+                CloseBrace => nested_brace_level -= 1,
+                _ => {}
+            }
+
+            tokens.push(token);
         }
+    }
 
-        self.advance(); // "
+    fn read_string_end(&mut self, multiline: bool, hashes: usize, chars: &mut Vec<(char, Position)>) -> bool {
+        let mut consumed = Vec::new();
 
-        Ok(Token {
-            value: StringLiteral(string.into()),
-            location: Location { file: self.file.clone(), from, to: self.next_position },
-            whitespace_before: had_whitespace
-        })
+        let quotes = if multiline { 3 } else { 1 };
+        for _ in 0..quotes {
+            if self.c != '"' {
+                chars.append(&mut consumed);
+                return false;
+            }
+
+            let position = self.position;
+            consumed.push((self.advance(), position)); // "
+        }
+
+        for _ in 0..hashes {
+            if self.c != '#' {
+                chars.append(&mut consumed);
+                return false;
+            }
+
+            let position = self.position;
+            consumed.push((self.advance(), position)); // #
+        }
+
+        true
     }
 
     fn read_number(&mut self, had_whitespace: bool) -> Result<Token, LexerError> {
         let from = self.position;
         let mut string = String::new();
+
+        let radix = match (self.c, self.next_c) {
+            ('0', 'x' | 'X') => 16,
+            ('0', 'o' | 'O') => 8,
+            ('0', 'b' | 'B') => 2,
+            _ => 10
+        };
+
+        if radix != 10 {
+            string.push(self.advance()); // 0
+            string.push(self.advance()); // x / o / b
+
+            self.read_digits(&mut string, radix)?;
+
+            // `0b102` or `0xFG` should not silently become two tokens
+            if is_part_of_name(self.c) {
+                return Err(LexerError::InvalidNumberLiteral(self.position));
+            }
+
+            return Ok(Token {
+                value: IntLiteral(string.into()),
+                location: Location { file: self.file, from, to: self.position },
+                whitespace_before: had_whitespace,
+                doc_comment: None
+            })
+        }
+
         let mut is_decimal = false;
 
-        while self.c.is_digit(10) || self.c == '.' {
-            if self.c == '.' {
-                if is_decimal || !self.next_c.is_digit(10) {
-                    break
-                }
+        self.read_digits(&mut string, 10)?;
+
+        if self.c == '.' && self.next_c.is_digit(10) {
+            is_decimal = true;
 
-                is_decimal = true;
+            string.push(self.advance()); // .
+            self.read_digits(&mut string, 10)?;
+        }
+
+        let has_exponent = (self.c == 'e' || self.c == 'E') && (
+            self.next_c.is_digit(10) ||
+            // 1e+5 / 1e-5, we can only look one character ahead so the sign is checked below
+            self.next_c == '+' || self.next_c == '-'
+        );
+
+        if has_exponent {
+            is_decimal = true;
+
+            string.push(self.advance()); // e
+
+            if self.c == '+' || self.c == '-' {
+                string.push(self.advance()); // + / -
             }
 
-            string.push(self.c);
-            self.advance();
+            self.read_digits(&mut string, 10)?;
         }
 
         Ok(Token {
             value: if is_decimal { DecimalLiteral(string.into()) } else { IntLiteral(string.into()) },
-            location: Location { file: self.file.clone(), from, to: self.next_position },
-            whitespace_before: had_whitespace
+            location: Location { file: self.file, from, to: self.position },
+            whitespace_before: had_whitespace,
+            doc_comment: None
         })
     }
 
+    // Reads a non-empty run of digits, allowing single `_` separators between them
+    fn read_digits(&mut self, string: &mut String, radix: u32) -> Result<(), LexerError> {
+        if !self.c.is_digit(radix) {
+            return Err(LexerError::InvalidNumberLiteral(self.position));
+        }
+
+        loop {
+            if self.c == '_' {
+                if !self.next_c.is_digit(radix) {
+                    return Err(LexerError::InvalidNumberLiteral(self.position));
+                }
+            } else if !self.c.is_digit(radix) {
+                break;
+            }
+
+            string.push(self.advance());
+        }
+
+        Ok(())
+    }
+
     fn read_atom(&mut self, had_whitespace: bool) -> Result<Token, LexerError> {
         let from = self.position;
         let mut string = String::new();
@@ -207,10 +590,14 @@ impl <I: Iterator<Item = char>> Lexer<I> {
 
         let value = match string.as_str() {
             "val" => Val,
+            "var" => Var,
             "rec" => Recursive,
             "if" => If,
             "else" => Else,
             "then" => Then,
+            "while" => While,
+            "break" => Break,
+            "continue" => Continue,
             "and" => And,
             "or" => Or,
             "true" => BoolLiteral(true),
@@ -220,18 +607,22 @@ impl <I: Iterator<Item = char>> Lexer<I> {
 
         Ok(Token {
             value,
-            location: Location { file: self.file.clone(), from, to: self.next_position },
-            whitespace_before: had_whitespace
+            location: Location { file: self.file, from, to: self.position },
+            whitespace_before: had_whitespace,
+            doc_comment: None
         })
     }
 
     fn one_char_token(&mut self, value: TokenValue, had_whitespace: bool) -> Result<Token, LexerError> {
+        let from = self.position;
+
         self.advance();
 
         Ok(Token {
             value,
-            location: Location { file: self.file.clone(), from: self.position, to: self.next_position },
-            whitespace_before: had_whitespace
+            location: Location { file: self.file, from, to: self.position },
+            whitespace_before: had_whitespace,
+            doc_comment: None
         })
     }
 
@@ -243,76 +634,272 @@ impl <I: Iterator<Item = char>> Lexer<I> {
 
         Ok(Token {
             value,
-            location: Location { file: self.file.clone(), from, to: self.next_position },
-            whitespace_before: had_whitespace
+            location: Location { file: self.file, from, to: self.position },
+            whitespace_before: had_whitespace,
+            doc_comment: None
         })
     }
 
-    fn skip_whitespace_and_comments(&mut self) -> bool {
-        let mut in_comment = false;
+    fn skip_whitespace_and_comments(&mut self) -> Result<bool, LexerError> {
         let mut had_whitespace = false;
 
         loop {
-            if self.c == EOF {
-                break;
-            } else if in_comment {
-                if self.c == '\n' {
-                    in_comment = false;
+            if self.c == '#' && self.next_c == '[' {
+                self.skip_block_comment()?;
+            } else if self.c == '#' && self.next_c == '#' {
+                self.read_doc_comment();
+            } else if self.c == '#' {
+                // The newline is not part of the comment, it still ends the expression before it
+                while self.c != '\n' && self.c != EOF {
+                    self.advance();
                 }
-            } else if self.c == '#' && self.next_c.is_whitespace() {
-                in_comment = true;
-            } else if !self.c.is_whitespace() || self.c == '\n' {
+            } else if self.c == EOF || !self.c.is_whitespace() || self.c == '\n' {
                 break;
+            } else {
+                self.advance();
             }
 
-            self.advance();
             had_whitespace = true
         }
 
-        had_whitespace
+        Ok(had_whitespace)
     }
 
-    fn init_read(&mut self) {
-        self.at_start = false;
+    // #[ ... ]#, these can be nested
+    fn skip_block_comment(&mut self) -> Result<(), LexerError> {
+        let from = self.position;
+        let mut nested_comment_level = 0;
 
-        match self.chars.next() {
-            None => (),
-            Some(c) => {
-                self.next_c = c;
+        loop {
+            match (self.c, self.next_c) {
+                (EOF, _) => return Err(LexerError::UnclosedBlockComment(from)),
+
+                ('#', '[') => nested_comment_level += 1,
+                (']', '#') => nested_comment_level -= 1,
+
+                _ => {
+                    self.advance();
+                    continue;
+                }
+            }
+
+            self.advance();
+            self.advance();
+
+            if nested_comment_level == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    // ## Documentation for the definition below
+    fn read_doc_comment(&mut self) {
+        self.advance(); // #
+        self.advance(); // #
+
+        if self.c == ' ' {
+            self.advance();
+        }
+
+        let mut line = String::new();
+        while self.c != '\n' && self.c != EOF {
+            line.push(self.advance());
+        }
+
+        match &mut self.doc_comment {
+            None => self.doc_comment = Some(line),
+            Some(doc_comment) => {
+                doc_comment.push('\n');
+                doc_comment.push_str(&line);
             }
         }
     }
 
+    fn init_read(&mut self) {
+        self.at_start = false;
+
+        self.next_c = self.read_char();
+    }
+
     fn advance(&mut self) -> char {
         let old_c = self.c;
 
+        self.c = self.next_c;
+        self.position = self.next_position;
+
+        // EOF is not part of the source, so it has no length
+        let offset = if self.c == EOF { 0 } else { self.c.len_utf8() as u32 };
+
+        if self.c == '\n' {
+            self.next_position = Position {
+                line: self.next_position.line + 1,
+                column: 0,
+                offset: self.next_position.offset + offset
+            }
+        } else {
+            self.next_position = Position {
+                line: self.next_position.line,
+                column: self.next_position.column + 1,
+                offset: self.next_position.offset + offset
+            }
+        }
+
+        self.next_c = self.read_char();
+
+        old_c
+    }
+
+    fn read_char(&mut self) -> char {
         match self.chars.next() {
-            None => {
-                self.c = self.next_c;
-                self.next_c = EOF;
-            },
+            None => EOF,
             Some(c) => {
-                self.c = self.next_c;
-                self.position = self.next_position;
+                if let Some(recording) = &mut self.recording {
+                    recording.source.push(c);
+                }
+
+                c
+            }
+        }
+    }
+}
 
-                self.next_c = c;
+impl Lexer<std::iter::Empty<char>> {
+    /// Replays already lexed tokens, e.g. the ones in a `SyntaxNode` tree
+    pub fn from_tokens(file: FileId, tokens: Vec<Token>) -> Self {
+        let mut lexer = Self::new(file, std::iter::empty());
 
-                if c == '\n' {
-                    self.next_position = Position {
-                        line: self.next_position.line + 1,
-                        column: 0
-                    }
-                } else {
-                    self.next_position = Position {
-                        line: self.next_position.line,
-                        column: self.next_position.column + 1
+        lexer.pending = VecDeque::from(tokens);
+
+        lexer
+    }
+}
+
+fn decode_escapes(chars: &[(char, Position)]) -> Result<String, LexerError> {
+    let mut string = String::with_capacity(chars.len());
+    let mut chars = chars.iter().copied();
+
+    while let Some((c, position)) = chars.next() {
+        if c != '\\' {
+            string.push(c);
+            continue;
+        }
+
+        let invalid = || LexerError::InvalidEscapeSequence(position);
+
+        let escaped = match chars.next() {
+            None => return Err(invalid()),
+            Some((c, _)) => c
+        };
+
+        string.push(match escaped {
+            'r' => '\r',
+            'n' => '\n',
+            't' => '\t',
+            '0' => '\0',
+            '\\' => '\\',
+            '"' => '"',
+            '$' => '$',
+
+            // \x41, only ASCII since anything above is not a full character on its own
+            'x' => {
+                let mut value = 0;
+                for _ in 0..2 {
+                    let digit = chars.next().and_then(|(c, _)| c.to_digit(16)).ok_or_else(invalid)?;
+
+                    value = value * 16 + digit;
+                }
+
+                if value > 0x7F {
+                    return Err(invalid());
+                }
+
+                char::from(value as u8)
+            }
+
+            // \u{1F600}
+            'u' => {
+                if !matches!(chars.next(), Some(('{', _))) {
+                    return Err(invalid());
+                }
+
+                let mut value: u32 = 0;
+                let mut digit_count = 0;
+                loop {
+                    match chars.next() {
+                        Some(('}', _)) if digit_count > 0 => break,
+                        Some((c, _)) if digit_count < 6 && c.is_ascii_hexdigit() => {
+                            value = value * 16 + c.to_digit(16).unwrap();
+                            digit_count += 1;
+                        }
+                        _ => return Err(invalid())
                     }
                 }
+
+                char::from_u32(value).ok_or_else(invalid)?
+            }
+
+            _ => return Err(invalid())
+        });
+    }
+
+    Ok(string)
+}
+
+// Removes the line breaks right after the opening and right before the closing `"""`, as well as
+// the indentation common to all non-blank lines. It has to be the same characters on all of them.
+fn strip_indentation(chars: Vec<(char, Position)>) -> Result<Vec<(char, Position)>, LexerError> {
+    let is_indent = |c: char| c == ' ' || c == '\t';
+    let is_blank = |line: &[(char, Position)]| line.iter().all(|(c, _)| is_indent(*c) || *c == '\n');
+
+    let mut lines: Vec<&[(char, Position)]> = chars.split_inclusive(|(c, _)| *c == '\n').collect();
+
+    // The closing quotes are at the start of a line, `split_inclusive` has no empty chunk for it
+    if chars.last().is_some_and(|(c, _)| *c == '\n') {
+        lines.push(&[]);
+    }
+
+    if lines.len() > 1 && is_blank(lines[0]) {
+        lines.remove(0);
+    }
+
+    if lines.len() > 1 && is_blank(lines[lines.len() - 1]) {
+        lines.pop();
+
+        // The line before the closing quotes shouldn't end with a newline
+        let last = lines.len() - 1;
+        lines[last] = &lines[last][..lines[last].len() - 1];
+    }
+
+    let indented_lines: Vec<&[(char, Position)]> = lines.iter()
+        .filter(|line| !is_blank(line))
+        .copied()
+        .collect();
+
+    let indentation = indented_lines.iter()
+        .map(|line| line.iter().take_while(|(c, _)| is_indent(*c)).count())
+        .min()
+        .unwrap_or(0);
+
+    if let Some(first) = indented_lines.first() {
+        for line in &indented_lines[1..] {
+            for i in 0..indentation {
+                if line[i].0 != first[i].0 {
+                    return Err(LexerError::MixedIndentation(line[i].1));
+                }
             }
         }
+    }
 
-        old_c
+    let mut result = Vec::with_capacity(chars.len());
+    for line in lines {
+        if is_blank(line) {
+            result.extend(line.iter().filter(|(c, _)| *c == '\n'));
+        } else {
+            result.extend_from_slice(&line[indentation..]);
+        }
     }
+
+    Ok(result)
 }
 
 fn is_start_of_name(c: char) -> bool {
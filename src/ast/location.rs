@@ -1,25 +1,57 @@
-use std::rc::Rc;
-
 #[derive(Debug, Clone)]
 pub struct Location {
-    pub file: Rc<str>,
+    pub file: FileId,
     pub from: Position,
     pub to: Position
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Position {
     pub line: i32,
-    pub column: i32
+    pub column: i32,
+
+    // In bytes from the start of the file
+    pub offset: u32
 }
 
+/// Index of a file in its `SourceMap`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct FileId(pub u32);
+
 impl Location {
+    // Where the parser is before it reads the first token, it's not in the source
+    pub fn null(file: FileId) -> Self {
+        let position = Position { line: -1, column: -1, offset: 0 };
+
+        Location { file, from: position, to: position }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.from.line < 0
+    }
+
+    // The null location is ignored, it would otherwise extend the result to the start of the file
     pub fn extend(&self, loc: &Location) -> Self {
+        if loc.is_null() {
+            return self.clone();
+        }
+
+        if self.is_null() {
+            return Location { file: self.file, from: loc.from, to: loc.to };
+        }
+
         Location {
-            file: self.file.clone(),
-            // TODO: Do we need to make this min of the two, and the other the max of the two?
-            from: self.from,
-            to: loc.to
+            file: self.file,
+            from: self.from.min(loc.from),
+            to: self.to.max(loc.to)
         }
     }
-}
\ No newline at end of file
+
+    pub fn len(&self) -> usize {
+        (self.to.offset - self.from.offset) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
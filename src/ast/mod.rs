@@ -5,9 +5,13 @@ mod ast;
 mod pattern;
 mod lookahead_token_iterator;
 mod inspect;
+mod syntax_tree;
+mod source_map;
 
 pub use lexer::*;
 pub use ast::*;
 pub use pattern::*;
 pub use parser::*;
 pub use location::*;
+pub use syntax_tree::*;
+pub use source_map::*;
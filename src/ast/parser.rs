@@ -3,6 +3,7 @@ use crate::ast::*;
 use crate::ast::lookahead_token_iterator::{LookaheadIteratorIterator, LookaheadTokenIterator};
 use crate::ast::ParseError::UnexpectedPattern;
 use crate::ast::TokenValue::*;
+use crate::diagnostics::Diagnostic;
 
 #[derive(Debug)]
 pub enum ParseError {
@@ -12,6 +13,27 @@ pub enum ParseError {
     LexerError(LexerError)
 }
 
+impl ParseError {
+    pub fn to_diagnostic(&self, file: FileId) -> Diagnostic {
+        match self {
+            ParseError::UnexpectedToken(message, token) =>
+                Diagnostic::error("E0010", message.as_ref())
+                    .with_primary(token.location.clone(), format!("unexpected {}", token.value.describe())),
+
+            ParseError::UnexpectedPattern(pattern) =>
+                Diagnostic::error("E0011", "Patterns are not allowed here")
+                    .with_primary(pattern.location.clone(), "expected a value, not a pattern"),
+
+            ParseError::CouldNotParseNumber(location) =>
+                Diagnostic::error("E0012", "Number literal is out of range")
+                    .with_primary(location.clone(), "")
+                    .with_note("integers are 64-bit signed and decimals must be finite"),
+
+            ParseError::LexerError(error) => error.to_diagnostic(file)
+        }
+    }
+}
+
 enum ASTOrPattern {
     AST(AST),
     Pattern(Pattern)
@@ -23,16 +45,16 @@ pub struct Parser<I: Iterator<Item = char>> {
     at_start: bool,
     newline: bool,
     t: Token,
-    last_location: Location
+    last_location: Location,
+
+    // When set, blocks skip over expressions with errors instead of failing, see `read_all_recovering`
+    recovering: bool,
+    errors: Vec<ParseError>
 }
 
 impl <I: Iterator<Item = char>> Parser<I> {
     pub fn new(lexer: Lexer<I>) -> Self {
-        let null_location = Location {
-            file: "".into(),
-            from: Position { line: -1, column: -1 },
-            to: Position { line: -1, column: -1 }
-        };
+        let null_location = Location::null(lexer.file);
 
         Parser {
             lexer: LookaheadTokenIterator::new(lexer),
@@ -42,8 +64,11 @@ impl <I: Iterator<Item = char>> Parser<I> {
                 value: EOF,
                 location: null_location.clone(),
                 whitespace_before: false,
+                doc_comment: None
             },
-            last_location: null_location
+            last_location: null_location,
+            recovering: false,
+            errors: Vec::new()
         }
     }
 
@@ -56,17 +81,103 @@ impl <I: Iterator<Item = char>> Parser<I> {
             asts.push(ast);
         }
 
+        Ok(self.module_block(asts))
+    }
+
+    /// Reads everything like `read_all_as_block`, but does not stop at the first error. Each
+    /// expression which could not be parsed is replaced with a `Value::Error` and the parser
+    /// continues from the next line (or the closing `}` of the block).
+    pub fn read_all_recovering(&mut self) -> (AST, Vec<ParseError>) {
+        self.recovering = true;
+
+        let mut asts = Vec::new();
+
+        loop {
+            match self.has_next() {
+                Ok(true) => {},
+                Ok(false) => break,
+
+                // The first token could not be read, the lexer has skipped it so try again
+                Err(error) => {
+                    self.errors.push(error);
+                    continue;
+                }
+            }
+
+            let start_location = self.t.location.clone();
+
+            match self.next(false) {
+                Ok(ast) => asts.push(ast),
+                Err(error) => asts.push(self.recover(error, start_location, false))
+            }
+        }
+
+        self.recovering = false;
+
+        (self.module_block(asts), std::mem::take(&mut self.errors))
+    }
+
+    fn module_block(&self, asts: Vec<AST>) -> AST {
         let location =
-            if asts.len() > 0 {
+            if !asts.is_empty() {
                 asts[0].location.extend(&asts[asts.len() - 1].location)
             } else {
-                panic!("Nothing to read")
+                // Nothing to read, the parser is at EOF
+                Location { file: self.t.location.file, from: self.t.location.from, to: self.t.location.from }
             };
 
-        Ok(AST {
+        AST {
             value: Value::Block(asts),
             location
-        })
+        }
+    }
+
+    // Records the error and skips to where the next expression is likely to start
+    fn recover(&mut self, error: ParseError, start_location: Location, in_block: bool) -> AST {
+        self.errors.push(error);
+
+        // The failed expression may not have read anything, it needs to be skipped over
+        let mut must_skip = self.t.location.from == start_location.from;
+        let mut depth = 0;
+
+        loop {
+            if self.t.value == EOF {
+                break;
+            }
+
+            let at_boundary = self.newline || (in_block && self.t.value == CloseBrace);
+            if depth == 0 && !must_skip && at_boundary {
+                break;
+            }
+
+            match self.t.value {
+                OpenParen | OpenBrace | OpenBracket => depth += 1,
+                CloseParen | CloseBrace | CloseBracket if depth > 0 => depth -= 1,
+                _ => {}
+            }
+
+            must_skip = false;
+
+            if let Err(error) = self.read() {
+                self.errors.push(error);
+            }
+        }
+
+        AST {
+            value: Value::Error,
+            location: start_location.extend(&self.last_location)
+        }
+    }
+
+    /// Like `read_all_as_block`, but keeps all tokens, whitespace and comments.
+    /// The parser must be created with `Lexer::new_lossless`.
+    pub fn read_all_as_syntax_tree(&mut self) -> Result<SyntaxNode, ParseError> {
+        let ast = self.read_all_as_block()?;
+
+        let recording = self.lexer.lexer.take_recording()
+            .expect("read_all_as_syntax_tree requires a lossless lexer");
+
+        Ok(SyntaxNode::from_recording(&ast, recording))
     }
 
     pub fn has_next(&mut self) -> Result<bool, ParseError> {
@@ -108,10 +219,17 @@ impl <I: Iterator<Item = char>> Parser<I> {
 
             let operator = self.read()?; // <operator>
 
-            let right = self.parse_expression(precedence + 1, require_call_parens, has_lower_priority_target)?;
+            // Assignments are right-associative, so `a = b = 1` assigns to `b` first
+            let right_precedence = if Self::is_assignment_operator(&operator.value) { precedence } else { precedence + 1 };
+            let right = self.parse_expression(right_precedence, require_call_parens, has_lower_priority_target)?;
 
             let left_ast = Self::assert_ast(left)?;
 
+            if Self::is_assignment_operator(&operator.value) {
+                left = ASTOrPattern::AST(Self::build_assignment(left_ast, operator, Self::assert_ast(right)?)?);
+                continue;
+            }
+
             left = if operator.value == Colon {
                 let right = Self::assert_ast(right)?;
                 let location = left_ast.location.extend(&right.location);
@@ -158,17 +276,18 @@ impl <I: Iterator<Item = char>> Parser<I> {
 
     fn parse_primary(&mut self, require_call_parens: bool, has_lower_priority_target: bool) -> Result<ASTOrPattern, ParseError> {
         // TODO: Check if this allows to have `-val a = 42` which should be invalid
-        if self.t.value == Recursive || self.t.value == Val {
+        if self.t.value == Recursive || self.t.value == Val || self.t.value == Var {
             let start_loc = self.t.location.clone();
+            let doc_comment = self.t.doc_comment.clone();
 
-            return self.parse_val(start_loc, false, require_call_parens, has_lower_priority_target);
+            return self.parse_val(start_loc, doc_comment, false, require_call_parens, has_lower_priority_target);
         }
 
         if self.t.value == At {
             let at = self.read()?; // @
 
-            if self.t.value == Recursive || self.t.value == Val {
-                return self.parse_val(at.location, true, require_call_parens, has_lower_priority_target);
+            if self.t.value == Recursive || self.t.value == Val || self.t.value == Var {
+                return self.parse_val(at.location, at.doc_comment, true, require_call_parens, has_lower_priority_target);
             }
 
             return self.parse_compile_time_expression(at.location, require_call_parens, has_lower_priority_target);
@@ -182,6 +301,19 @@ impl <I: Iterator<Item = char>> Parser<I> {
             return self.parse_if(require_call_parens, has_lower_priority_target);
         }
 
+        if self.t.value == While {
+            return self.parse_while();
+        }
+
+        if self.t.value == Break || self.t.value == Continue {
+            let token = self.read()?; // break or continue
+
+            return Ok(ASTOrPattern::AST(AST {
+                value: if token.value == Break { Value::Break } else { Value::Continue },
+                location: token.location
+            }));
+        }
+
         let mut target = self.parse_call_target(require_call_parens, has_lower_priority_target)?;
 
         loop {
@@ -195,7 +327,7 @@ impl <I: Iterator<Item = char>> Parser<I> {
         }
     }
 
-    fn parse_val(&mut self, start_loc: Location, comptime: bool, require_call_parens: bool, has_lower_priority_target: bool) -> Result<ASTOrPattern, ParseError> {
+    fn parse_val(&mut self, start_loc: Location, doc_comment: Option<Box<str>>, comptime: bool, require_call_parens: bool, has_lower_priority_target: bool) -> Result<ASTOrPattern, ParseError> {
         let recursive = self.t.value == Recursive;
         if recursive {
             self.read()?; // rec
@@ -203,7 +335,7 @@ impl <I: Iterator<Item = char>> Parser<I> {
             self.expect_token(Val, "rec must be followed by val")?;
         }
 
-        self.read()?; // val
+        let mutable = self.read()?.value == Var; // val or var
 
         let (name, name_loc) = self.read_name("val must be followed by a name")?;
 
@@ -213,6 +345,10 @@ impl <I: Iterator<Item = char>> Parser<I> {
                 return Err(ParseError::UnexpectedToken("rec vals must be assignments".into(), self.read().unwrap()))
             }
 
+            if mutable {
+                return Err(ParseError::UnexpectedToken("vars must be assignments".into(), self.read().unwrap()))
+            }
+
             return Ok(ASTOrPattern::Pattern(Pattern {
                 value: PatternValue::Binding(name),
                 location: start_loc.extend(&name_loc)
@@ -222,7 +358,8 @@ impl <I: Iterator<Item = char>> Parser<I> {
         let type_ast = if self.t.value == Colon {
             self.read()?; // :
 
-            let expr = self.parse_expression(0, true, false)?;
+            // Above the precedence of assignments, so that the `=` after the type isn't one
+            let expr = self.parse_expression(2, true, false)?;
 
             Some(Self::assert_ast(expr)?)
         } else { None };
@@ -241,7 +378,7 @@ impl <I: Iterator<Item = char>> Parser<I> {
         let value_with_type = match type_ast {
             None => value_ast,
             Some(typ) => {
-                let location = typ.location.clone();
+                let location = typ.location.extend(&value_ast.location);
 
                 AST {
                     value: Value::TypeAssert { value: Box::new(value_ast), typ: Box::new(typ) },
@@ -255,7 +392,9 @@ impl <I: Iterator<Item = char>> Parser<I> {
                 name,
                 value: Box::new(value_with_type),
                 recursive,
-                comptime
+                comptime,
+                mutable,
+                doc_comment
             },
             location: start_loc.extend(&self.last_location)
         }))
@@ -290,6 +429,34 @@ impl <I: Iterator<Item = char>> Parser<I> {
         }))
     }
 
+    fn parse_while(&mut self) -> Result<ASTOrPattern, ParseError> {
+        let while_token = self.read()?; // while
+
+        let condition = Self::assert_ast(
+            self.parse_expression(0, true, false)?
+        )?;
+
+        if self.t.value != OpenBrace {
+            return Err(ParseError::UnexpectedToken("Expected '{'".into(), self.t.clone()));
+        }
+        self.read()?; // {
+
+        let body = self.parse_block()?;
+
+        if self.t.value != CloseBrace {
+            return Err(ParseError::UnexpectedToken("Expected '}'".into(), self.t.clone()));
+        }
+        self.read()?; // }
+
+        Ok(ASTOrPattern::AST(AST {
+            value: Value::While {
+                condition: Box::new(condition),
+                body: Box::new(body)
+            },
+            location: while_token.location.extend(&self.last_location)
+        }))
+    }
+
     fn parse_if_body_block(&mut self, require_call_parens: bool, has_lower_priority_target: bool, for_else: bool) -> Result<AST, ParseError> {
         let has_braces = self.t.value == OpenBrace;
         if has_braces || !for_else {
@@ -319,6 +486,7 @@ impl <I: Iterator<Item = char>> Parser<I> {
             &IntLiteral(_) => self.parse_int(),
             &DecimalLiteral(_) => self.parse_float(),
             &StringLiteral(_) => self.parse_string(),
+            &StringStart(_) => self.parse_interpolated_string(),
             &Name(_) => self.parse_name(),
 
             &OpenBrace => self.parse_lambda_or_lambda_type(has_lower_priority_target),
@@ -331,6 +499,7 @@ impl <I: Iterator<Item = char>> Parser<I> {
             &Not => self.parse_unary_operator(require_call_parens, has_lower_priority_target),
 
             &OpenParen => self.parse_expression_starting_with_open_paren(has_lower_priority_target),
+            &OpenBracket => self.parse_list(),
 
             _ => Err(ParseError::UnexpectedToken("Unexpected token".into(), self.t.clone()))
         }
@@ -370,8 +539,8 @@ impl <I: Iterator<Item = char>> Parser<I> {
             IntLiteral(value) => value,
             _ => panic!("Logic error - expected int literal")
         };
-        let value = value.parse::<i64>()
-            .map_err(|_| ParseError::CouldNotParseNumber(t.location.clone()))?;
+        let value = Self::int_literal_value(&value)
+            .ok_or_else(|| ParseError::CouldNotParseNumber(t.location.clone()))?;
 
         Ok(ASTOrPattern::AST(AST {
             value: Value::Literal(Literal::Int(value)),
@@ -385,8 +554,8 @@ impl <I: Iterator<Item = char>> Parser<I> {
             DecimalLiteral(value) => value,
             _ => panic!("Logic error - expected decimal literal")
         };
-        let value = value.parse::<f64>()
-            .map_err(|_| ParseError::CouldNotParseNumber(t.location.clone()))?;
+        let value = Self::decimal_literal_value(&value)
+            .ok_or_else(|| ParseError::CouldNotParseNumber(t.location.clone()))?;
 
         Ok(ASTOrPattern::AST(AST {
             value: Value::Literal(Literal::Float(value)),
@@ -394,6 +563,26 @@ impl <I: Iterator<Item = char>> Parser<I> {
         }))
     }
 
+    fn int_literal_value(literal: &str) -> Option<i64> {
+        let digits: String = literal.chars().filter(|c| *c != '_').collect();
+
+        let (radix, digits) = match digits.get(0..2) {
+            Some("0x" | "0X") => (16, &digits[2..]),
+            Some("0o" | "0O") => (8, &digits[2..]),
+            Some("0b" | "0B") => (2, &digits[2..]),
+            _ => (10, digits.as_str())
+        };
+
+        i64::from_str_radix(digits, radix).ok()
+    }
+
+    fn decimal_literal_value(literal: &str) -> Option<f64> {
+        let digits: String = literal.chars().filter(|c| *c != '_').collect();
+
+        // Rust happily parses `1e999` as infinity, but that's not a number anyone meant to write
+        digits.parse::<f64>().ok().filter(|value| value.is_finite())
+    }
+
     fn parse_string(&mut self) -> Result<ASTOrPattern, ParseError> {
         let t = self.read()?; // "string"
         let value = match t.value {
@@ -407,6 +596,80 @@ impl <I: Iterator<Item = char>> Parser<I> {
         }))
     }
 
+    // "a ${b} c" is desugared to ("a " + b.to_s) + " c"
+    fn parse_interpolated_string(&mut self) -> Result<ASTOrPattern, ParseError> {
+        let t = self.read()?; // "...${
+        let value = match t.value {
+            StringStart(value) => value,
+            _ => panic!("Logic error - expected string start")
+        };
+
+        let mut result = Self::string_segment_ast(None, value, t.location);
+
+        loop {
+            let expr = Self::assert_ast(self.parse_expression(0, false, false)?)?;
+            let location = expr.location.clone();
+
+            let to_s = AST {
+                value: Value::Call {
+                    target: Some(Box::new(expr)),
+                    name: "to_s".into(),
+                    args: Vec::new()
+                },
+                location
+            };
+
+            result = Some(match result {
+                None => to_s,
+                Some(left) => Self::string_concat_ast(left, to_s)
+            });
+
+            let t = self.read()?; // }...${ or }..."
+            match t.value {
+                StringMiddle(value) => result = Self::string_segment_ast(result, value, t.location),
+                StringEnd(value) => {
+                    let result = Self::string_segment_ast(result, value, t.location);
+
+                    return Ok(ASTOrPattern::AST(result.expect("Logic error - interpolated string without parts")))
+                }
+                value => return Err(ParseError::UnexpectedToken(
+                    "Expected '}' to end the string interpolation".into(),
+                    Token { value, ..t }
+                ))
+            }
+        }
+    }
+
+    fn string_segment_ast(left: Option<AST>, value: Box<str>, location: Location) -> Option<AST> {
+        // Empty segments (e.g. the one before `${` in "${a}") only add noise to the result
+        if value.is_empty() {
+            return left;
+        }
+
+        let segment = AST {
+            value: Value::Literal(Literal::String(value)),
+            location
+        };
+
+        Some(match left {
+            None => segment,
+            Some(left) => Self::string_concat_ast(left, segment)
+        })
+    }
+
+    fn string_concat_ast(left: AST, right: AST) -> AST {
+        let location = left.location.extend(&right.location);
+
+        AST {
+            value: Value::Call {
+                target: Some(Box::new(left)),
+                name: "+".into(),
+                args: vec![right]
+            },
+            location
+        }
+    }
+
     fn parse_name(&mut self) -> Result<ASTOrPattern, ParseError> {
         let t = self.read()?; // name
         let value = match t.value {
@@ -481,6 +744,57 @@ impl <I: Iterator<Item = char>> Parser<I> {
         }
     }
 
+    fn parse_list(&mut self) -> Result<ASTOrPattern, ParseError> {
+        let start_token = self.read()?; // [
+
+        let values = self.parse_comma_separated_until_close_bracket()?;
+
+        Ok(ASTOrPattern::AST(AST {
+            value: Value::List(values),
+            location: start_token.location.extend(&self.last_location)
+        }))
+    }
+
+    // list[index] is desugared to list.[](index)
+    fn parse_index(&mut self, target: AST) -> Result<ASTOrPattern, ParseError> {
+        self.read()?; // [
+
+        let args = self.parse_comma_separated_until_close_bracket()?;
+        let location = target.location.extend(&self.last_location);
+
+        Ok(ASTOrPattern::AST(AST {
+            value: Value::Call {
+                target: Some(Box::new(target)),
+                name: "[]".into(),
+                args
+            },
+            location
+        }))
+    }
+
+    fn parse_comma_separated_until_close_bracket(&mut self) -> Result<Vec<AST>, ParseError> {
+        let mut values = Vec::new();
+
+        while self.t.value != CloseBracket {
+            let expr = Self::assert_ast(self.parse_expression(0, false, false)?)?;
+            values.push(expr);
+
+            if self.t.value != Comma {
+                break
+            }
+
+            self.read()?; // ,
+        }
+
+        if self.t.value != CloseBracket {
+            return Err(ParseError::UnexpectedToken("Expected ']'".into(), self.t.clone()));
+        }
+
+        self.read()?; // ]
+
+        Ok(values)
+    }
+
     fn try_to_parse_call(&mut self, target: ASTOrPattern, require_call_parens: bool, has_lower_priority_target: bool) -> Result<(ASTOrPattern, bool), ParseError> {
         // target.call
         if self.t.value == Dot {
@@ -494,6 +808,14 @@ impl <I: Iterator<Item = char>> Parser<I> {
             return Ok((self.parse_call_with_explicit_target(target, require_call_parens)?, true));
         }
 
+        // list[0]
+        // Has to be checked before `name a`, otherwise `list [0]` would be a call with a list argument
+        if self.is_at_index_access() {
+            let target = Self::assert_ast(target)?;
+
+            return Ok((self.parse_index(target)?, true));
+        }
+
         // name a
         // name(a)
         if matches!(target, ASTOrPattern::AST(AST { value: Value::NameRef(_), ..})) {
@@ -587,6 +909,11 @@ impl <I: Iterator<Item = char>> Parser<I> {
             return Ok(args);
         }
 
+        // a.b[0] is an index on the result of `b`, not a call with a list argument
+        if !with_parens && self.is_at_index_access() {
+            return Ok(args);
+        }
+
         if !with_parens && require_parens {
             return Ok(args);
         }
@@ -623,7 +950,8 @@ impl <I: Iterator<Item = char>> Parser<I> {
         // Since there was a newline before, but we don't care
         self.newline = false;
 
-        let start_location = self.last_location.clone();
+        let start_location = self.t.location.clone();
+        let doc_comment = self.t.doc_comment.clone();
 
         let has_param_parens = self.t.value == OpenParen;
         let params = if has_param_parens {
@@ -636,7 +964,7 @@ impl <I: Iterator<Item = char>> Parser<I> {
         let return_type = if has_return_type {
             self.read()?; // :
 
-            Some(self.parse_expression(0, true, false)?)
+            Some(self.parse_expression(2, true, false)?)
         } else {
             None
         };
@@ -673,7 +1001,8 @@ impl <I: Iterator<Item = char>> Parser<I> {
                 value: Value::Function(Function {
                     params,
                     body: Box::new(body),
-                    return_type
+                    return_type,
+                    doc_comment
                 }),
                 location: start_location.extend(&self.last_location)
             }))
@@ -685,7 +1014,7 @@ impl <I: Iterator<Item = char>> Parser<I> {
                 Some(typ) => typ
             };
 
-            let location = start_location.extend(&self.t.location);
+            let location = start_location.extend(&self.last_location);
 
             let mut has_pattern_params = false;
             for param in &params {
@@ -748,6 +1077,8 @@ impl <I: Iterator<Item = char>> Parser<I> {
         let has_params = self.t.value != CloseParen;
         if has_params {
             loop {
+                let start_location = self.t.location.clone();
+
                 let comptime = if self.t.value == At {
                     // A comptime parameter
                     self.read()?; // @
@@ -755,12 +1086,12 @@ impl <I: Iterator<Item = char>> Parser<I> {
                     true
                 } else { false };
 
-                let (name, name_location) = self.read_name("Expected parameter name")?;
+                let (name, _) = self.read_name("Expected parameter name")?;
 
                 let typ = if self.t.value == Colon {
                     self.read()?; // :
 
-                    let expr = self.parse_expression(0, true, false)?;
+                    let expr = self.parse_expression(2, true, false)?;
 
                     Some(Self::coerce_to_pattern(expr))
                 } else {
@@ -771,7 +1102,7 @@ impl <I: Iterator<Item = char>> Parser<I> {
                     name,
                     comptime,
                     typ,
-                    location: name_location.extend(&self.last_location)
+                    location: start_location.extend(&self.last_location)
                 });
 
                 if self.t.value != Comma { break }
@@ -792,6 +1123,21 @@ impl <I: Iterator<Item = char>> Parser<I> {
         let start_location = self.t.location.clone();
 
         while self.t.value != CloseBrace {
+            if self.recovering {
+                if self.t.value == EOF {
+                    return Err(ParseError::UnexpectedToken("Expected }".into(), self.t.clone()));
+                }
+
+                let start_location = self.t.location.clone();
+
+                match self.parse_expression(0, false, false).and_then(Self::assert_ast) {
+                    Ok(ast) => values.push(ast),
+                    Err(error) => values.push(self.recover(error, start_location, true))
+                }
+
+                continue;
+            }
+
             let expr = self.parse_expression(0, false, false)?;
             let ast = Self::assert_ast(expr)?;
 
@@ -800,14 +1146,23 @@ impl <I: Iterator<Item = char>> Parser<I> {
 
         if values.len() == 1 {
             Ok(values.into_iter().next().unwrap())
+        } else if values.is_empty() {
+            Ok(AST {
+                value: Value::Block(values),
+                location: Location { file: start_location.file, from: start_location.from, to: start_location.from }
+            })
         } else {
             Ok(AST {
                 value: Value::Block(values),
-                location: start_location.extend(&self.t.location)
+                location: start_location.extend(&self.last_location)
             })
         }
     }
 
+    fn is_at_index_access(&self) -> bool {
+        self.t.value == OpenBracket && !self.t.whitespace_before && !self.newline
+    }
+
     fn current_expression_may_end(&self) -> bool {
         self.newline || match &self.t.value {
             EOF => true,
@@ -819,12 +1174,18 @@ impl <I: Iterator<Item = char>> Parser<I> {
 
             Colon | Comma | CloseParen | Dot | CloseBracket | CloseBrace => true,
 
+            // End of an expression inside a string interpolation
+            StringMiddle(_) | StringEnd(_) => true,
+
             // This is because of lambda types
             Equal => true,
 
             // Ifs
             If | Then | Else => true,
 
+            // Loops
+            While | Break | Continue => true,
+
             _ => false
         }
     }
@@ -874,10 +1235,14 @@ impl <I: Iterator<Item = char>> Parser<I> {
             Colon => true,
 
             Val => false,
+            Var => false,
             Recursive => false,
             If => true,
             Else => false,
             Then => false,
+            While => true,
+            Break => true,
+            Continue => true,
 
             // Binary operators - these suggest that the parens were for an expression, not a lambda
             Plus => false,
@@ -905,6 +1270,10 @@ impl <I: Iterator<Item = char>> Parser<I> {
             DecimalLiteral(_) => true,
             StringLiteral(_) => true,
             BoolLiteral(_) => true,
+
+            StringStart(_) => true,
+            StringMiddle(_) => false,
+            StringEnd(_) => false,
         })
     }
 
@@ -945,10 +1314,49 @@ impl <I: Iterator<Item = char>> Parser<I> {
         Self::binary_operator_method_name(token).or_else(|| Self::unary_operator_method_name(token))
     }
 
+    fn is_assignment_operator(token: &TokenValue) -> bool {
+        matches!(token, Equal | PlusEqual | MinusEqual | AsteriskEqual | SlashEqual)
+    }
+
+    // `a += b` is desugared to `a = a + b`
+    fn build_assignment(target: AST, operator: Token, value: AST) -> Result<AST, ParseError> {
+        let name = match target.value {
+            Value::NameRef(name) => name,
+            _ => return Err(ParseError::UnexpectedToken("Only names can be assigned to".into(), operator))
+        };
+
+        let location = target.location.extend(&value.location);
+
+        let method_name = match operator.value {
+            Equal => None,
+            PlusEqual => Some("+"),
+            MinusEqual => Some("-"),
+            AsteriskEqual => Some("*"),
+            SlashEqual => Some("/"),
+            _ => panic!("Not an assignment operator: {:?}", operator.value)
+        };
+
+        let value = match method_name {
+            None => value,
+            Some(method_name) => AST {
+                value: Value::Call {
+                    target: Some(Box::new(AST { value: Value::NameRef(name.clone()), location: target.location })),
+                    name: method_name.into(),
+                    args: vec![value]
+                },
+                location: location.clone()
+            }
+        };
+
+        Ok(AST {
+            value: Value::Assign { name, value: Box::new(value) },
+            location
+        })
+    }
+
     fn operator_precedence(token: &TokenValue) -> Option<i8> {
-        // TODO: Handle *=, /=, +=, -=
         match token {
-            // Equal => Some(1),
+            Equal | PlusEqual | MinusEqual | AsteriskEqual | SlashEqual => Some(1),
             Or => Some(2),
             And => Some(3),
             EqualEqual | LessThan | GreaterThan | LessThanEqual | GreaterThanEqual | NotEqual => Some(4),
@@ -1019,7 +1427,7 @@ impl <I: Iterator<Item = char>> Parser<I> {
         if self.at_start {
             self.at_start = false;
             self.last_location = Location {
-                file: self.lexer.lexer.file.clone(),
+                file: self.lexer.lexer.file,
                 from: self.lexer.lexer.position,
                 to: self.lexer.lexer.position
             };
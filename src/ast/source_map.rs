@@ -0,0 +1,85 @@
+use std::str::Chars;
+use crate::ast::{FileId, Lexer, Location, Position};
+
+/// Owns the source of all loaded files, so that locations can be mapped back to the code
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>
+}
+
+#[derive(Debug)]
+pub struct SourceFile {
+    pub id: FileId,
+    pub name: Box<str>,
+    pub source: Box<str>,
+
+    // Byte offset of the start of each line
+    line_starts: Vec<usize>
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        SourceMap { files: Vec::new() }
+    }
+
+    pub fn add_file(&mut self, name: &str, source: &str) -> FileId {
+        let id = FileId(self.files.len() as u32);
+
+        let mut line_starts = vec![0];
+        line_starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+
+        self.files.push(SourceFile {
+            id,
+            name: name.into(),
+            source: source.into(),
+            line_starts
+        });
+
+        id
+    }
+
+    pub fn file(&self, id: FileId) -> &SourceFile {
+        &self.files[id.0 as usize]
+    }
+
+    pub fn lexer(&self, id: FileId) -> Lexer<Chars<'_>> {
+        Lexer::new(id, self.file(id).source.chars())
+    }
+
+    pub fn lossless_lexer(&self, id: FileId) -> Lexer<Chars<'_>> {
+        Lexer::new_lossless(id, self.file(id).source.chars())
+    }
+
+    /// The exact source code of the location
+    pub fn text(&self, location: &Location) -> &str {
+        self.file(location.file).text(location.from.offset as usize, location.to.offset as usize)
+    }
+}
+
+impl SourceFile {
+    pub fn text(&self, from: usize, to: usize) -> &str {
+        let to = to.min(self.source.len());
+
+        &self.source[from.min(to)..to]
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The text of the line, without the line break
+    pub fn line(&self, line: usize) -> &str {
+        let from = self.line_starts[line];
+        let to = self.line_starts.get(line + 1).copied().unwrap_or(self.source.len());
+
+        self.source[from..to].trim_end_matches(['\n', '\r'])
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.source.len());
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let column = self.source[self.line_starts[line]..offset].chars().count();
+
+        Position { line: line as i32, column: column as i32, offset: offset as u32 }
+    }
+}
@@ -0,0 +1,424 @@
+use std::cmp::max;
+use std::iter::Peekable;
+use std::vec;
+use crate::ast::*;
+
+/// A lossless tree of the source code. Every token is kept together with the whitespace and
+/// comments around it, so `text()` always returns the exact source that was parsed.
+#[derive(Debug, Clone)]
+pub struct SyntaxNode {
+    pub kind: SyntaxKind,
+    pub location: Location,
+    pub children: Vec<SyntaxElement>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    Module,
+
+    Literal,
+    Block,
+    List,
+    Function,
+    Param,
+    Call,
+    Let,
+    NameRef,
+    Assign,
+    If,
+    While,
+    Break,
+    Continue,
+    FnType,
+    TypeParam,
+    TypeAssert,
+    CompileTimeExpr,
+    Error,
+
+    Pattern
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken)
+}
+
+#[derive(Debug, Clone)]
+pub struct SyntaxToken {
+    pub token: Token,
+
+    // Exactly as written in the source, e.g. including the quotes and escapes of strings
+    pub text: Box<str>,
+
+    // Trivia on the lines before the token. Trivia after the last token on a line is trailing
+    // trivia of that token instead.
+    pub leading_trivia: Vec<Trivia>,
+    pub trailing_trivia: Vec<Trivia>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: Box<str>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    Comment,
+    BlockComment,
+    DocComment
+}
+
+impl SyntaxNode {
+    pub fn from_recording(ast: &AST, recording: Recording) -> Self {
+        let tokens = SyntaxTreeBuilder::syntax_tokens(recording);
+
+        let mut builder = SyntaxTreeBuilder {
+            tokens: tokens.into_iter().peekable()
+        };
+
+        builder.build_module(ast)
+    }
+
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+
+        for token in self.tokens() {
+            token.write_text(&mut text);
+        }
+
+        text
+    }
+
+    pub fn tokens(&self) -> Vec<&SyntaxToken> {
+        let mut tokens = Vec::new();
+
+        self.collect_tokens(&mut tokens);
+
+        tokens
+    }
+
+    /// Parses the tokens of this node again. The root `Module` is read as a block, everything else
+    /// as a single expression.
+    pub fn to_ast(&self) -> Result<AST, ParseError> {
+        let mut tokens: Vec<Token> = self.tokens().into_iter()
+            .map(|token| token.token.clone())
+            .collect();
+
+        let file = self.location.file;
+
+        if !tokens.last().is_some_and(|token| token.value == TokenValue::EOF) {
+            tokens.push(Token {
+                value: TokenValue::EOF,
+                location: Location { file, from: self.location.to, to: self.location.to },
+                whitespace_before: false,
+                doc_comment: None
+            });
+        }
+
+        let mut parser = Parser::new(Lexer::from_tokens(file, tokens));
+
+        match self.kind {
+            SyntaxKind::Module => parser.read_all_as_block(),
+            _ => parser.next(false)
+        }
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a SyntaxToken>) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.collect_tokens(tokens),
+                SyntaxElement::Token(token) => tokens.push(token)
+            }
+        }
+    }
+}
+
+impl SyntaxToken {
+    fn write_text(&self, text: &mut String) {
+        for trivia in &self.leading_trivia {
+            text.push_str(&trivia.text);
+        }
+
+        text.push_str(&self.text);
+
+        for trivia in &self.trailing_trivia {
+            text.push_str(&trivia.text);
+        }
+    }
+}
+
+// Borrowed view of everything in the AST which has a location of its own
+#[derive(Clone, Copy)]
+enum SyntaxSource<'a> {
+    AST(&'a AST),
+    Pattern(&'a Pattern),
+    Param(&'a Param),
+    TypeParam(&'a TypeParam),
+    PatternParam(&'a PatternParam)
+}
+
+impl <'a> SyntaxSource<'a> {
+    fn location(&self) -> &'a Location {
+        match self {
+            SyntaxSource::AST(ast) => &ast.location,
+            SyntaxSource::Pattern(pattern) => &pattern.location,
+            SyntaxSource::Param(param) => &param.location,
+            SyntaxSource::TypeParam(param) => &param.location,
+            SyntaxSource::PatternParam(param) => &param.location
+        }
+    }
+
+    fn kind(&self) -> SyntaxKind {
+        match self {
+            SyntaxSource::AST(ast) => match &ast.value {
+                Value::Literal(_) => SyntaxKind::Literal,
+                Value::Block(_) => SyntaxKind::Block,
+                Value::List(_) => SyntaxKind::List,
+                Value::Function(_) => SyntaxKind::Function,
+                Value::Call { .. } => SyntaxKind::Call,
+                Value::Let { .. } => SyntaxKind::Let,
+                Value::NameRef(_) => SyntaxKind::NameRef,
+                Value::Assign { .. } => SyntaxKind::Assign,
+                Value::If { .. } => SyntaxKind::If,
+                Value::While { .. } => SyntaxKind::While,
+                Value::Break => SyntaxKind::Break,
+                Value::Continue => SyntaxKind::Continue,
+                Value::FnType { .. } => SyntaxKind::FnType,
+                Value::TypeAssert { .. } => SyntaxKind::TypeAssert,
+                Value::CompileTimeExpr(_) => SyntaxKind::CompileTimeExpr,
+                Value::Error => SyntaxKind::Error
+            },
+            SyntaxSource::Pattern(_) => SyntaxKind::Pattern,
+            SyntaxSource::Param(_) => SyntaxKind::Param,
+            SyntaxSource::TypeParam(_) => SyntaxKind::TypeParam,
+            SyntaxSource::PatternParam(_) => SyntaxKind::Param
+        }
+    }
+
+    fn children(&self) -> Vec<SyntaxSource<'a>> {
+        let mut children = Vec::new();
+
+        match self {
+            SyntaxSource::AST(ast) => match &ast.value {
+                Value::Literal(_) | Value::NameRef(_) | Value::Break | Value::Continue | Value::Error => {},
+                Value::Block(asts) | Value::List(asts) => children.extend(asts.iter().map(SyntaxSource::AST)),
+                Value::Function(function) => {
+                    children.extend(function.params.iter().map(SyntaxSource::Param));
+                    children.extend(function.return_type.as_deref().map(SyntaxSource::AST));
+                    children.push(SyntaxSource::AST(&function.body));
+                },
+                Value::Call { target, args, .. } => {
+                    children.extend(target.as_deref().map(SyntaxSource::AST));
+                    children.extend(args.iter().map(SyntaxSource::AST));
+                },
+                Value::Let { value, .. } | Value::Assign { value, .. } => children.push(SyntaxSource::AST(value)),
+                Value::If { condition, on_true, on_false } => {
+                    children.push(SyntaxSource::AST(condition));
+                    children.push(SyntaxSource::AST(on_true));
+                    children.extend(on_false.as_deref().map(SyntaxSource::AST));
+                },
+                Value::While { condition, body } => {
+                    children.push(SyntaxSource::AST(condition));
+                    children.push(SyntaxSource::AST(body));
+                },
+                Value::FnType { params, return_type } => {
+                    children.extend(params.iter().map(SyntaxSource::TypeParam));
+                    children.push(SyntaxSource::AST(return_type));
+                },
+                Value::TypeAssert { value, typ } => {
+                    children.push(SyntaxSource::AST(value));
+                    children.push(SyntaxSource::AST(typ));
+                },
+                Value::CompileTimeExpr(ast) => children.push(SyntaxSource::AST(ast))
+            },
+
+            SyntaxSource::Pattern(pattern) => match &pattern.value {
+                PatternValue::SpecificValue(ast) => children.push(SyntaxSource::AST(ast)),
+                PatternValue::Binding(_) => {},
+                PatternValue::Call { target, args, .. } => {
+                    children.extend(target.as_deref().map(SyntaxSource::AST));
+                    children.extend(args.iter().map(SyntaxSource::Pattern));
+                },
+                PatternValue::FunctionType { params, return_type } => {
+                    children.extend(params.iter().map(SyntaxSource::PatternParam));
+                    children.push(SyntaxSource::Pattern(return_type));
+                }
+            },
+
+            SyntaxSource::Param(param) => children.extend(param.typ.as_ref().map(SyntaxSource::Pattern)),
+            SyntaxSource::TypeParam(param) => children.push(SyntaxSource::AST(&param.typ)),
+            SyntaxSource::PatternParam(param) => children.push(SyntaxSource::Pattern(&param.typ))
+        }
+
+        // Desugared nodes (e.g. operators) don't necessarily keep the source order
+        children.sort_by_key(|child| child.location().from);
+
+        children
+    }
+}
+
+struct SyntaxTreeBuilder {
+    tokens: Peekable<vec::IntoIter<SyntaxToken>>
+}
+
+impl SyntaxTreeBuilder {
+    fn build_module(&mut self, ast: &AST) -> SyntaxNode {
+        let sources = match &ast.value {
+            Value::Block(asts) => asts.iter().map(SyntaxSource::AST).collect(),
+            _ => vec![SyntaxSource::AST(ast)]
+        };
+
+        let mut children = Vec::new();
+        for source in sources {
+            self.add_tokens_before(source.location().from, &mut children);
+            children.push(SyntaxElement::Node(self.build(source)));
+        }
+
+        // Including the EOF token, which keeps the trivia at the end of the file
+        children.extend(self.tokens.by_ref().map(SyntaxElement::Token));
+
+        let location = Self::location_of(&ast.location, &children);
+
+        SyntaxNode { kind: SyntaxKind::Module, location, children }
+    }
+
+    fn build(&mut self, source: SyntaxSource) -> SyntaxNode {
+        let mut children = Vec::new();
+        let mut to = source.location().to;
+
+        for child in source.children() {
+            self.add_tokens_before(child.location().from, &mut children);
+
+            let node = self.build(child);
+            to = max(to, node.location.to);
+
+            children.push(SyntaxElement::Node(node));
+        }
+
+        while let Some(token) = self.tokens.next_if(|token| {
+            token.token.value != TokenValue::EOF && token.token.location.to <= to
+        }) {
+            children.push(SyntaxElement::Token(token));
+        }
+
+        let location = Self::location_of(source.location(), &children);
+
+        SyntaxNode { kind: source.kind(), location, children }
+    }
+
+    fn add_tokens_before(&mut self, position: Position, children: &mut Vec<SyntaxElement>) {
+        while let Some(token) = self.tokens.next_if(|token| {
+            token.token.value != TokenValue::EOF && token.token.location.from < position
+        }) {
+            children.push(SyntaxElement::Token(token));
+        }
+    }
+
+    fn location_of(location: &Location, children: &[SyntaxElement]) -> Location {
+        let mut from = location.from;
+        let mut to = location.to;
+
+        for child in children {
+            let child_location = match child {
+                SyntaxElement::Node(node) => &node.location,
+                SyntaxElement::Token(token) => &token.token.location
+            };
+
+            from = from.min(child_location.from);
+            to = to.max(child_location.to);
+        }
+
+        Location { file: location.file, from, to }
+    }
+
+    fn syntax_tokens(recording: Recording) -> Vec<SyntaxToken> {
+        let source = recording.source;
+
+        let mut syntax_tokens: Vec<SyntaxToken> = Vec::with_capacity(recording.tokens.len());
+        let mut offset = 0;
+
+        for token in recording.tokens {
+            let from = (token.location.from.offset as usize).max(offset);
+            let to = (token.location.to.offset as usize).max(from);
+
+            let mut leading_trivia = Self::trivia(&source[offset..from]);
+
+            // Trivia up to the end of the line belongs to the token before it
+            if let Some(last) = syntax_tokens.last_mut() {
+                if last.token.value != TokenValue::NewLine {
+                    last.trailing_trivia = std::mem::take(&mut leading_trivia);
+                }
+            }
+
+            syntax_tokens.push(SyntaxToken {
+                token,
+                text: source[from..to].into(),
+                leading_trivia,
+                trailing_trivia: Vec::new()
+            });
+
+            offset = to;
+        }
+
+        // Anything after EOF can only be trivia which the lexer did not need to look at again
+        if offset < source.len() {
+            if let Some(last) = syntax_tokens.last_mut() {
+                last.trailing_trivia.extend(Self::trivia(&source[offset..]));
+            }
+        }
+
+        syntax_tokens
+    }
+
+    fn trivia(text: &str) -> Vec<Trivia> {
+        let mut trivia = Vec::new();
+        let mut rest = text;
+
+        while !rest.is_empty() {
+            let (kind, length) = if rest.starts_with("#[") {
+                (TriviaKind::BlockComment, Self::block_comment_length(rest))
+            } else if rest.starts_with("##") {
+                (TriviaKind::DocComment, rest.find('\n').unwrap_or(rest.len()))
+            } else if rest.starts_with('#') {
+                (TriviaKind::Comment, rest.find('\n').unwrap_or(rest.len()))
+            } else {
+                (TriviaKind::Whitespace, rest.find('#').unwrap_or(rest.len()))
+            };
+
+            let (text, remaining) = rest.split_at(length);
+
+            trivia.push(Trivia { kind, text: text.into() });
+            rest = remaining;
+        }
+
+        trivia
+    }
+
+    fn block_comment_length(text: &str) -> usize {
+        let mut depth = 0;
+        let mut i = 0;
+
+        while i < text.len() {
+            let rest = &text[i..];
+
+            if rest.starts_with("#[") {
+                depth += 1;
+                i += 2;
+            } else if rest.starts_with("]#") {
+                depth -= 1;
+                i += 2;
+
+                if depth == 0 {
+                    return i;
+                }
+            } else {
+                i += rest.chars().next().unwrap().len_utf8();
+            }
+        }
+
+        text.len()
+    }
+}
@@ -2,8 +2,9 @@ use std::ffi::{c_uint, CString};
 use llvm_sys::core::*;
 use llvm_sys::LLVMLinkage;
 use llvm_sys::prelude::*;
+use crate::compiler::c_str;
 use crate::compiler::function_builder::FunctionBuilder;
-use crate::lir;
+use crate::{ir, lir};
 use crate::ir::Type;
 
 pub struct Compiler<'a> {
@@ -11,17 +12,34 @@ pub struct Compiler<'a> {
     llvm_module: LLVMModuleRef,
 
     lir_module: &'a lir::Module,
-    function_declarations: Vec<FunctionDeclaration>
+    function_declarations: Vec<FunctionDeclaration>,
+    vtable_declarations: Vec<VTableDeclaration>,
+    global_declarations: Vec<GlobalDeclaration>
 }
 
 pub struct FunctionDeclaration {
-    // pub capture_types: Vec<LLVMTypeRef>,
     pub param_types: Vec<LLVMTypeRef>,
 
+    // The struct with the captured values, passed after the parameters
+    pub closure_type: Option<LLVMTypeRef>,
+
     pub type_ref: LLVMTypeRef,
     pub func_ref: LLVMValueRef
 }
 
+pub struct GlobalDeclaration {
+    pub type_ref: LLVMTypeRef,
+    pub global_ref: LLVMValueRef
+}
+
+pub struct VTableDeclaration {
+    pub concrete: Type,
+    pub interface: Type,
+
+    // A constant struct of pointers to the methods, see `llvm_vtable_type`
+    pub global_ref: LLVMValueRef
+}
+
 impl <'a> Compiler<'a> {
     pub fn compile(
         llvm_context: LLVMContextRef,
@@ -33,7 +51,9 @@ impl <'a> Compiler<'a> {
                 llvm_context,
                 llvm_module,
                 lir_module,
-                function_declarations: Vec::with_capacity(lir_module.functions.len())
+                function_declarations: Vec::with_capacity(lir_module.functions.len()),
+                vtable_declarations: Vec::with_capacity(lir_module.vtables.len()),
+                global_declarations: Vec::with_capacity(lir_module.globals.len())
             };
 
             compiler.compile_module();
@@ -41,6 +61,12 @@ impl <'a> Compiler<'a> {
     }
 
     unsafe fn compile_module(&mut self) {
+        for (i, global) in self.lir_module.globals.iter().enumerate() {
+            let decl = self.define_global(i, global);
+
+            self.global_declarations.push(decl);
+        }
+
         // TODO: Make sure we're not trying to compile functions only used during compile-time
         for (i, func) in self.lir_module.functions.iter().enumerate() {
             let name = format!("func_{}", i);
@@ -49,35 +75,127 @@ impl <'a> Compiler<'a> {
             self.function_declarations.push(decl);
         }
 
+        for (i, vtable) in self.lir_module.vtables.iter().enumerate() {
+            let decl = self.define_vtable(i, vtable);
+
+            self.vtable_declarations.push(decl);
+        }
+
         let lir_main = &self.lir_module.main;
         let main_decl = self.declare_function(lir_main, "main", true);
 
-        FunctionBuilder::build(self.llvm_context, self.llvm_module, &main_decl, lir_main);
+        FunctionBuilder::build(self.llvm_context, self.llvm_module, &self.lir_module.types, &self.function_declarations, &self.vtable_declarations, &self.global_declarations, &main_decl, lir_main);
 
         // TODO: Make sure we're not trying to compile functions only used during compile-time
         for (i, func) in self.lir_module.functions.iter().enumerate() {
             let decl = &self.function_declarations[i];
 
-            FunctionBuilder::build(self.llvm_context, self.llvm_module, decl, func);
+            FunctionBuilder::build(self.llvm_context, self.llvm_module, &self.lir_module.types, &self.function_declarations, &self.vtable_declarations, &self.global_declarations, decl, func);
         }
     }
 
+    unsafe fn define_global(&mut self, i: usize, global: &lir::Global) -> GlobalDeclaration {
+        let type_ref = self.llvm_type_of(global.typ);
+
+        let name = CString::new(format!("global_{}", i)).unwrap();
+        let global_ref = LLVMAddGlobal(self.llvm_module, type_ref, name.as_ptr());
+
+        LLVMSetInitializer(global_ref, llvm_const_value(self.llvm_context, &global.value));
+        LLVMSetLinkage(global_ref, LLVMLinkage::LLVMInternalLinkage);
+
+        GlobalDeclaration { type_ref, global_ref }
+    }
+
+    unsafe fn define_vtable(&mut self, i: usize, vtable: &lir::VTable) -> VTableDeclaration {
+        let lir_module = self.lir_module;
+        let types = &lir_module.types;
+        let methods = match vtable.interface {
+            Type::Interface(interface_ref) => &types.interface_type(interface_ref).methods,
+            typ => panic!("Expected an interface type, got {:?}", typ)
+        };
+
+        let mut adapter_refs = Vec::with_capacity(methods.len());
+        for (method, func_ref) in methods.iter().zip(&vtable.methods) {
+            let name = format!("vtable_{}_{}", i, method.name);
+
+            adapter_refs.push(self.define_method_adapter(&name, vtable.concrete, &method.signature, *func_ref));
+        }
+
+        let vtable_type = llvm_vtable_type(self.llvm_context, types, vtable.interface);
+
+        let name = CString::new(format!("vtable_{}", i)).unwrap();
+        let global_ref = LLVMAddGlobal(self.llvm_module, vtable_type, name.as_ptr());
+        let initializer = LLVMConstStructInContext(self.llvm_context, adapter_refs.as_mut_ptr(), adapter_refs.len() as c_uint, 0);
+
+        LLVMSetInitializer(global_ref, initializer);
+        LLVMSetGlobalConstant(global_ref, 1);
+        LLVMSetLinkage(global_ref, LLVMLinkage::LLVMInternalLinkage);
+
+        VTableDeclaration {
+            concrete: vtable.concrete,
+            interface: vtable.interface,
+            global_ref
+        }
+    }
+
+    // The functions in the vtables get a pointer to the concrete value, this loads it and calls
+    // the actual implementation
+    unsafe fn define_method_adapter(
+        &mut self,
+        name: &str,
+        concrete: Type,
+        signature: &ir::FunctionSignature,
+        func_ref: lir::FunctionRef
+    ) -> LLVMValueRef {
+        let fn_type = llvm_method_type(self.llvm_context, &self.lir_module.types, signature);
+
+        let fn_name = CString::new(name).unwrap();
+        let fn_ref = LLVMAddFunction(self.llvm_module, fn_name.as_ptr(), fn_type);
+        LLVMSetLinkage(fn_ref, LLVMLinkage::LLVMInternalLinkage);
+
+        let basic_block = LLVMAppendBasicBlockInContext(self.llvm_context, fn_ref, c_str!("entry"));
+        let builder = LLVMCreateBuilderInContext(self.llvm_context);
+        LLVMPositionBuilderAtEnd(builder, basic_block);
+
+        let concrete_type = self.llvm_type_of(concrete);
+        let data_ref = LLVMBuildPointerCast(builder, LLVMGetParam(fn_ref, 0), LLVMPointerType(concrete_type, 0), c_str!("data"));
+
+        let mut args = Vec::with_capacity(signature.params.len() + 1);
+        args.push(LLVMBuildLoad2(builder, concrete_type, data_ref, c_str!("self")));
+        for i in 0..signature.params.len() {
+            args.push(LLVMGetParam(fn_ref, (i + 1) as c_uint));
+        }
+
+        let decl = &self.function_declarations[func_ref.i];
+        let result_ref = LLVMBuildCall2(builder, decl.type_ref, decl.func_ref, args.as_mut_ptr(), args.len() as c_uint, c_str!("result"));
+
+        LLVMBuildRet(builder, result_ref);
+        LLVMDisposeBuilder(builder);
+
+        fn_ref
+    }
+
     unsafe fn declare_function(&mut self, func: &lir::Function, name: &str, exported: bool) -> FunctionDeclaration {
         let mut param_types = Vec::with_capacity(func.param_types.len());
         for lir_param in func.param_types.iter() {
             param_types.push(self.llvm_type_of(*lir_param));
         }
 
-        if func.capture_types.len() > 0 {
-            todo!("Support closures");
-        }
+        let closure_type = if func.capture_types.is_empty() {
+            None
+        } else {
+            Some(llvm_closure_struct_type(self.llvm_context, &self.lir_module.types, &func.capture_types))
+        };
 
         let llvm_return_type = self.llvm_type_of(func.return_type);
 
+        let mut llvm_param_types = param_types.clone();
+        llvm_param_types.extend(closure_type);
+
         let type_ref = LLVMFunctionType(
             llvm_return_type,
-            param_types.as_mut_ptr(),
-            param_types.len() as c_uint,
+            llvm_param_types.as_mut_ptr(),
+            llvm_param_types.len() as c_uint,
             0
         );
 
@@ -90,6 +208,7 @@ impl <'a> Compiler<'a> {
 
         FunctionDeclaration {
             param_types,
+            closure_type,
 
             type_ref,
             func_ref
@@ -97,23 +216,129 @@ impl <'a> Compiler<'a> {
     }
 
     pub unsafe fn llvm_type_of(&mut self, typ: Type) -> LLVMTypeRef {
-        match typ {
-            Type::Any => panic!("Cannot represent Any type in runtime-compiled code"),
-
-            // TODO: Represent this using `void`
-            Type::None => LLVMInt8TypeInContext(self.llvm_context),
-            Type::Bool => LLVMInt8TypeInContext(self.llvm_context),
-            Type::Int => LLVMInt64TypeInContext(self.llvm_context),
-            Type::Float => LLVMDoubleTypeInContext(self.llvm_context),
-            Type::Type => panic!("Cannot represent Type type in runtime-compiled code"),
-
-            Type::Closure(_) => todo!("Support closures"),
-
-            // TODO: We can't use self.function_declarations here since it may not yet be initialized,
-            //       since we're using llvm_type_of during initialization
-            // Type::Closure(func_ref) => self.function_declarations[func_ref.i].closure_struct_type
-            //    // TODO: Support passing non-closure functions by value, maybe another value of type FunctionPtr?
-            //    .expect("Function referred to by a closure type did not have a closure struct type")
+        llvm_type_of(self.llvm_context, &self.lir_module.types, typ)
+    }
+}
+
+pub unsafe fn llvm_type_of(llvm_context: LLVMContextRef, types: &ir::Types, typ: Type) -> LLVMTypeRef {
+    match typ {
+        Type::Any => panic!("Cannot represent Any type in runtime-compiled code"),
+
+        // TODO: Represent this using `void`
+        Type::None => LLVMInt8TypeInContext(llvm_context),
+        Type::Bool => LLVMInt8TypeInContext(llvm_context),
+        Type::Int => LLVMInt64TypeInContext(llvm_context),
+        Type::Float => LLVMDoubleTypeInContext(llvm_context),
+        Type::Type => panic!("Cannot represent Type type in runtime-compiled code"),
+        Type::Constructor(_) => panic!("Cannot represent type constructors in runtime-compiled code"),
+
+        // Only the runtime captures are stored, the function is known from the type
+        Type::Closure(closure_ref) => llvm_closure_struct_type(llvm_context, types, &types.closure_type(closure_ref).runtime_capture_types()),
+
+        Type::List(list_ref) => LLVMPointerType(llvm_list_struct_type(llvm_context, types, types.list_type(list_ref).element), 0),
+
+        // { i8* function, i8* env }, see `llvm_function_value_fn_type`
+        Type::Function(_) => {
+            let pointer_type = LLVMPointerType(LLVMInt8TypeInContext(llvm_context), 0);
+            let mut field_types = [pointer_type, pointer_type];
+
+            LLVMStructTypeInContext(llvm_context, field_types.as_mut_ptr(), field_types.len() as c_uint, 0)
+        }
+
+        // { i8* data, i8* vtable }, the data is a copy of the concrete value
+        Type::Interface(_) => {
+            let pointer_type = LLVMPointerType(LLVMInt8TypeInContext(llvm_context), 0);
+            let mut field_types = [pointer_type, pointer_type];
+
+            LLVMStructTypeInContext(llvm_context, field_types.as_mut_ptr(), field_types.len() as c_uint, 0)
         }
+
+        // Structs are passed by value
+        Type::Struct(struct_ref) => {
+            let mut field_types: Vec<LLVMTypeRef> = types.struct_type(struct_ref).fields.iter()
+                .map(|field| llvm_type_of(llvm_context, types, field.typ))
+                .collect();
+
+            LLVMStructTypeInContext(llvm_context, field_types.as_mut_ptr(), field_types.len() as c_uint, 0)
+        }
+
+        // A pointer to the heap-allocated value
+        Type::Cell(cell_ref) => LLVMPointerType(llvm_type_of(llvm_context, types, types.cell_type(cell_ref).element), 0)
+    }
+}
+
+// Only the values of `lir::ValueRef` constants and runtime globals are constants in the compiled
+// code, see `ir::Globals::define`. The others are created by instructions.
+pub unsafe fn llvm_const_value(llvm_context: LLVMContextRef, value: &ir::Value) -> LLVMValueRef {
+    match value {
+        // TODO: Better `void` type
+        ir::Value::None => LLVMConstInt(LLVMInt8TypeInContext(llvm_context), 0, 0),
+        ir::Value::Bool(value) => LLVMConstInt(LLVMInt8TypeInContext(llvm_context), if *value { 1 } else { 0 }, 0),
+
+        // TODO: Should this be a transmute? Test with negative numbers
+        ir::Value::Int(value) => LLVMConstInt(LLVMInt64TypeInContext(llvm_context), *value as u64, 1),
+        ir::Value::Float(value) => LLVMConstReal(LLVMDoubleTypeInContext(llvm_context), *value),
+
+        value => unreachable!("{:?} is not a constant in the compiled code", value)
     }
-}
\ No newline at end of file
+}
+
+// The captured values, in the order of the captures
+pub unsafe fn llvm_closure_struct_type(llvm_context: LLVMContextRef, types: &ir::Types, capture_types: &[Type]) -> LLVMTypeRef {
+    let mut field_types: Vec<LLVMTypeRef> = capture_types.iter()
+        .map(|typ| llvm_type_of(llvm_context, types, *typ))
+        .collect();
+
+    LLVMStructTypeInContext(llvm_context, field_types.as_mut_ptr(), field_types.len() as c_uint, 0)
+}
+
+// { i64 length, [0 x T] elements }, allocated by the runtime
+pub unsafe fn llvm_list_struct_type(llvm_context: LLVMContextRef, types: &ir::Types, element: Type) -> LLVMTypeRef {
+    let mut field_types = [
+        LLVMInt64TypeInContext(llvm_context),
+        LLVMArrayType2(llvm_type_of(llvm_context, types, element), 0)
+    ];
+
+    LLVMStructTypeInContext(llvm_context, field_types.as_mut_ptr(), field_types.len() as c_uint, 0)
+}
+
+// The methods in vtables take a pointer to the value instead of the value itself
+pub unsafe fn llvm_method_type(llvm_context: LLVMContextRef, types: &ir::Types, signature: &ir::FunctionSignature) -> LLVMTypeRef {
+    let mut param_types = Vec::with_capacity(signature.params.len() + 1);
+    param_types.push(LLVMPointerType(LLVMInt8TypeInContext(llvm_context), 0));
+    for param in &signature.params {
+        param_types.push(llvm_type_of(llvm_context, types, *param));
+    }
+
+    let return_type = llvm_type_of(llvm_context, types, signature.returns);
+
+    LLVMFunctionType(return_type, param_types.as_mut_ptr(), param_types.len() as c_uint, 0)
+}
+
+// A struct with a function pointer for each method of the interface, in the same order
+pub unsafe fn llvm_vtable_type(llvm_context: LLVMContextRef, types: &ir::Types, interface: Type) -> LLVMTypeRef {
+    let methods = match interface {
+        Type::Interface(interface_ref) => &types.interface_type(interface_ref).methods,
+        typ => panic!("Expected an interface type, got {:?}", typ)
+    };
+
+    let mut field_types: Vec<LLVMTypeRef> = methods.iter()
+        .map(|method| LLVMPointerType(llvm_method_type(llvm_context, types, &method.signature), 0))
+        .collect();
+
+    LLVMStructTypeInContext(llvm_context, field_types.as_mut_ptr(), field_types.len() as c_uint, 0)
+}
+
+// The functions which function values point to take the environment of the function as a last
+// parameter, so that calling them doesn't depend on what they capture
+pub unsafe fn llvm_function_value_fn_type(llvm_context: LLVMContextRef, types: &ir::Types, signature: &ir::FunctionSignature) -> LLVMTypeRef {
+    let mut param_types = Vec::with_capacity(signature.params.len() + 1);
+    for param in &signature.params {
+        param_types.push(llvm_type_of(llvm_context, types, *param));
+    }
+    param_types.push(LLVMPointerType(LLVMInt8TypeInContext(llvm_context), 0));
+
+    let return_type = llvm_type_of(llvm_context, types, signature.returns);
+
+    LLVMFunctionType(return_type, param_types.as_mut_ptr(), param_types.len() as c_uint, 0)
+}
@@ -1,7 +1,10 @@
 use std::ffi::{c_uint, CString};
 use llvm_sys::core::*;
 use llvm_sys::prelude::*;
-use crate::compiler::compiler::FunctionDeclaration;
+use llvm_sys::{LLVMIntPredicate, LLVMLinkage, LLVMRealPredicate};
+use crate::compiler::compiler::{llvm_const_value, llvm_function_value_fn_type, llvm_list_struct_type, llvm_method_type, llvm_type_of, llvm_vtable_type, FunctionDeclaration, GlobalDeclaration, VTableDeclaration};
+use crate::compiler::c_str;
+use crate::compiler::runtime_fns;
 use crate::compiler::symbol_name_counter::SymbolNameCounter;
 use crate::{ir, lir};
 use crate::vec_map::VecMap;
@@ -10,11 +13,22 @@ pub struct FunctionBuilder<'a> {
     llvm_context: LLVMContextRef,
     llvm_module: LLVMModuleRef,
 
+    types: &'a ir::Types,
+    functions: &'a [FunctionDeclaration],
+    vtables: &'a [VTableDeclaration],
+    globals: &'a [GlobalDeclaration],
+
     decl: &'a FunctionDeclaration,
     func: &'a lir::Function,
 
     local_refs: Vec<Option<LLVMValueRef>>,
 
+    // The stack slots of the mutable locals, see `lir::Function::mutable_locals`
+    variables: Vec<Option<(LLVMTypeRef, LLVMValueRef)>>,
+
+    // The start and end blocks of the loops around the code being compiled, innermost last
+    loops: Vec<(LLVMBasicBlockRef, LLVMBasicBlockRef)>,
+
     stmt_name_gen: SymbolNameCounter
 }
 
@@ -24,20 +38,35 @@ impl <'a> FunctionBuilder<'a> {
         llvm_context: LLVMContextRef,
         llvm_module: LLVMModuleRef,
 
+        types: &ir::Types,
+        functions: &[FunctionDeclaration],
+        vtables: &[VTableDeclaration],
+        globals: &[GlobalDeclaration],
+
         decl: &FunctionDeclaration,
         func: &lir::Function
     ) {
         let mut local_refs = Vec::new();
         local_refs.resize(func.local_count, None);
 
+        let mut variables = Vec::new();
+        variables.resize(func.local_count, None);
+
         let mut fb = FunctionBuilder {
             llvm_context,
             llvm_module,
 
+            types,
+            functions,
+            vtables,
+            globals,
+
             decl,
             func,
 
             local_refs,
+            variables,
+            loops: Vec::new(),
 
             stmt_name_gen: SymbolNameCounter::new()
         };
@@ -46,29 +75,118 @@ impl <'a> FunctionBuilder<'a> {
     }
 
     unsafe fn compile(&mut self) {
-        self.compile_basic_block(&self.func.body, "entry");
+        let basic_block = LLVMAppendBasicBlockInContext(self.llvm_context, self.decl.func_ref, c_str!("entry"));
+
+        self.compile_basic_block(&self.func.body, basic_block);
     }
 
-    unsafe fn compile_basic_block(&mut self, block: &lir::BasicBlock, name: &str) -> LLVMBasicBlockRef {
-        let name = CString::new(name).unwrap();
-        let basic_block = LLVMAppendBasicBlockInContext(self.llvm_context, self.decl.func_ref, name.as_ptr());
+    // Returns the LLVM block in which the code ends, it's not `basic_block` if the code branches
+    unsafe fn compile_basic_block(&mut self, block: &lir::BasicBlock, basic_block: LLVMBasicBlockRef) -> LLVMBasicBlockRef {
         let builder = LLVMCreateBuilderInContext(self.llvm_context);
         LLVMPositionBuilderAtEnd(builder, basic_block);
 
+        let mut current_block = basic_block;
+
         for instruction in &block.code {
             match instruction {
-                lir::Instruction::LocalSet(local_ref, value_ref, _) => {
+                lir::Instruction::LocalSet(local_ref, value_ref, typ) => {
                     let value_ref = self.llvm_value_ref_of(builder, *value_ref);
 
-                    self.local_refs[local_ref.i] = Some(value_ref);
+                    if self.func.mutable_locals.contains(local_ref) {
+                        let (_, variable_ref) = self.variable(*local_ref, *typ);
+
+                        LLVMBuildStore(builder, value_ref, variable_ref);
+                    } else {
+                        self.local_refs[local_ref.i] = Some(value_ref);
+                    }
                 }
                 lir::Instruction::CallIntrinsic(local_ref, intrinsic_fn, arg_refs) => {
                     let args = self.llvm_value_refs_of(builder, arg_refs);
+                    let result_ref = self.build_call_intrinsic(builder, *intrinsic_fn, &args);
+
+                    self.local_refs[local_ref.i] = Some(result_ref);
+                }
+                lir::Instruction::CreateList(local_ref, list_type, value_refs) => {
+                    let values = self.llvm_value_refs_of(builder, value_refs);
+                    let list_ref = self.build_create_list(builder, *list_type, &values);
+
+                    self.local_refs[local_ref.i] = Some(list_ref);
+                }
+                lir::Instruction::CreateStruct(local_ref, struct_type, value_refs) => {
+                    let values = self.llvm_value_refs_of(builder, value_refs);
+                    let mut struct_ref = LLVMGetUndef(llvm_type_of(self.llvm_context, self.types, *struct_type));
+
+                    for (i, value) in values.into_iter().enumerate() {
+                        let name = self.stmt_name_gen.next("struct");
+
+                        struct_ref = LLVMBuildInsertValue(builder, struct_ref, value, i as c_uint, name.as_ptr());
+                    }
+
+                    self.local_refs[local_ref.i] = Some(struct_ref);
+                }
+                lir::Instruction::CreateClosure(local_ref, closure_type, value_refs) => {
+                    let values = self.llvm_value_refs_of(builder, value_refs);
+                    let mut closure_ref = LLVMGetUndef(llvm_type_of(self.llvm_context, self.types, *closure_type));
+
+                    for (i, value) in values.into_iter().enumerate() {
+                        let name = self.stmt_name_gen.next("closure");
+
+                        closure_ref = LLVMBuildInsertValue(builder, closure_ref, value, i as c_uint, name.as_ptr());
+                    }
+
+                    self.local_refs[local_ref.i] = Some(closure_ref);
+                }
+                lir::Instruction::CreateCell(local_ref, cell_type, value_ref) => {
+                    let value = self.llvm_value_ref_of(builder, *value_ref);
+                    let cell_ref = self.build_create_cell(builder, *cell_type, value);
+
+                    self.local_refs[local_ref.i] = Some(cell_ref);
+                }
+                lir::Instruction::CellGet(local_ref, cell_type, cell_ref) => {
+                    let cell_ref = self.llvm_value_ref_of(builder, *cell_ref);
+                    let element_type = llvm_type_of(self.llvm_context, self.types, self.cell_element_type(*cell_type));
+
+                    let name = self.stmt_name_gen.next("var");
+                    let value_ref = LLVMBuildLoad2(builder, element_type, cell_ref, name.as_ptr());
+
+                    self.local_refs[local_ref.i] = Some(value_ref);
+                }
+                lir::Instruction::CellSet(_, cell_ref, value_ref) => {
+                    let cell_ref = self.llvm_value_ref_of(builder, *cell_ref);
+                    let value_ref = self.llvm_value_ref_of(builder, *value_ref);
+
+                    LLVMBuildStore(builder, value_ref, cell_ref);
+                }
+                lir::Instruction::CreateInterfaceValue(local_ref, vtable_ref, value_ref) => {
+                    let value = self.llvm_value_ref_of(builder, *value_ref);
+                    let interface_ref = self.build_create_interface_value(builder, &self.vtables[vtable_ref.i], value);
+
+                    self.local_refs[local_ref.i] = Some(interface_ref);
+                }
+                lir::Instruction::CreateFunctionValue(local_ref, _, func_ref, closure_ref) => {
+                    let closure_ref = self.llvm_value_ref_of(builder, *closure_ref);
+                    let function_value_ref = self.build_create_function_value(builder, *func_ref, closure_ref);
+
+                    self.local_refs[local_ref.i] = Some(function_value_ref);
+                }
+                lir::Instruction::CallFunctionValue(local_ref, function_type, arg_refs) => {
+                    let args = self.llvm_value_refs_of(builder, arg_refs);
+                    let result_ref = self.build_call_function_value(builder, *function_type, &args);
+
+                    self.local_refs[local_ref.i] = Some(result_ref);
+                }
+                lir::Instruction::Call(local_ref, func_ref, arg_refs) => {
+                    let mut args = self.llvm_value_refs_of(builder, arg_refs);
+                    let decl = &self.functions[func_ref.i];
+
                     let name = self.stmt_name_gen.next("result");
+                    let result_ref = LLVMBuildCall2(builder, decl.type_ref, decl.func_ref, args.as_mut_ptr(), args.len() as c_uint, name.as_ptr());
 
-                    let result_ref = match intrinsic_fn {
-                        ir::IntrinsicFn::AddInt => LLVMBuildAdd(builder, args[0], args[1], name.as_ptr())
-                    };
+                    self.local_refs[local_ref.i] = Some(result_ref);
+                }
+                lir::Instruction::CallMethod(local_ref, interface, i, arg_refs) => {
+                    let args = self.llvm_value_refs_of(builder, arg_refs);
+                    let result_ref = self.build_call_method(builder, *interface, *i, &args);
 
                     self.local_refs[local_ref.i] = Some(result_ref);
                 }
@@ -77,13 +195,464 @@ impl <'a> FunctionBuilder<'a> {
 
                     LLVMBuildRet(builder, value_ref);
                 }
-                lir::Instruction::If(_, _, _, _, _) => todo!("Support compiling ifs")
+                lir::Instruction::If(local_ref, cond_ref, then_block, else_block, typ) => {
+                    current_block = self.build_if(builder, *local_ref, *cond_ref, then_block, else_block, *typ);
+                }
+                lir::Instruction::Loop(body) => {
+                    current_block = self.build_loop(builder, body);
+                }
+                lir::Instruction::Break => {
+                    let (_, end_basic_block) = *self.loops.last().expect("Break outside of a loop");
+
+                    current_block = self.build_jump(builder, end_basic_block);
+                }
+                lir::Instruction::Continue => {
+                    let (start_basic_block, _) = *self.loops.last().expect("Continue outside of a loop");
+
+                    current_block = self.build_jump(builder, start_basic_block);
+                }
             }
             }
 
         LLVMDisposeBuilder(builder);
 
-        basic_block
+        current_block
+    }
+
+    // Leaves the builder at the end of the block after the `if`
+    unsafe fn build_if(
+        &mut self,
+        builder: LLVMBuilderRef,
+        local_ref: lir::LocalRef,
+        cond_ref: lir::ValueRef,
+        then_block: &lir::BasicBlock,
+        else_block: &lir::BasicBlock,
+        typ: ir::Type
+    ) -> LLVMBasicBlockRef {
+        let cond_ref = self.llvm_value_ref_of(builder, cond_ref);
+        let name = self.stmt_name_gen.next("cond");
+        let cond_ref = LLVMBuildICmp(builder, LLVMIntPredicate::LLVMIntNE, cond_ref, self.const_u8(0), name.as_ptr());
+
+        let then_basic_block = LLVMAppendBasicBlockInContext(self.llvm_context, self.decl.func_ref, c_str!("then"));
+        let else_basic_block = LLVMAppendBasicBlockInContext(self.llvm_context, self.decl.func_ref, c_str!("else"));
+        let end_basic_block = LLVMAppendBasicBlockInContext(self.llvm_context, self.decl.func_ref, c_str!("if_end"));
+
+        LLVMBuildCondBr(builder, cond_ref, then_basic_block, else_basic_block);
+
+        let then_end_block = self.compile_basic_block(then_block, then_basic_block);
+        let then_value_ref = self.local_refs[local_ref.i].expect("The then block did not set the result");
+        LLVMPositionBuilderAtEnd(builder, then_end_block);
+        LLVMBuildBr(builder, end_basic_block);
+
+        let else_end_block = self.compile_basic_block(else_block, else_basic_block);
+        let else_value_ref = self.local_refs[local_ref.i].expect("The else block did not set the result");
+        LLVMPositionBuilderAtEnd(builder, else_end_block);
+        LLVMBuildBr(builder, end_basic_block);
+
+        LLVMPositionBuilderAtEnd(builder, end_basic_block);
+
+        let name = self.stmt_name_gen.next("if_result");
+        let phi_ref = LLVMBuildPhi(builder, llvm_type_of(self.llvm_context, self.types, typ), name.as_ptr());
+
+        let mut incoming_values = [then_value_ref, else_value_ref];
+        let mut incoming_blocks = [then_end_block, else_end_block];
+        LLVMAddIncoming(phi_ref, incoming_values.as_mut_ptr(), incoming_blocks.as_mut_ptr(), 2);
+
+        self.local_refs[local_ref.i] = Some(phi_ref);
+
+        end_basic_block
+    }
+
+    // Leaves the builder at the end of the block after the loop
+    unsafe fn build_loop(&mut self, builder: LLVMBuilderRef, body: &lir::BasicBlock) -> LLVMBasicBlockRef {
+        let start_basic_block = LLVMAppendBasicBlockInContext(self.llvm_context, self.decl.func_ref, c_str!("loop"));
+        let end_basic_block = LLVMAppendBasicBlockInContext(self.llvm_context, self.decl.func_ref, c_str!("loop_end"));
+
+        LLVMBuildBr(builder, start_basic_block);
+
+        self.loops.push((start_basic_block, end_basic_block));
+        let body_end_block = self.compile_basic_block(body, start_basic_block);
+        self.loops.pop();
+
+        LLVMPositionBuilderAtEnd(builder, body_end_block);
+        LLVMBuildBr(builder, start_basic_block);
+
+        LLVMPositionBuilderAtEnd(builder, end_basic_block);
+
+        end_basic_block
+    }
+
+    // The code after the jump can't run, but it's still compiled into a block of its own
+    unsafe fn build_jump(&mut self, builder: LLVMBuilderRef, target: LLVMBasicBlockRef) -> LLVMBasicBlockRef {
+        LLVMBuildBr(builder, target);
+
+        let unreachable_basic_block = LLVMAppendBasicBlockInContext(self.llvm_context, self.decl.func_ref, c_str!("unreachable"));
+        LLVMPositionBuilderAtEnd(builder, unreachable_basic_block);
+
+        unreachable_basic_block
+    }
+
+    unsafe fn build_call_intrinsic(&mut self, builder: LLVMBuilderRef, intrinsic_fn: ir::IntrinsicFn, args: &[LLVMValueRef]) -> LLVMValueRef {
+        use ir::IntrinsicFn::*;
+        use LLVMIntPredicate::*;
+        use LLVMRealPredicate::*;
+
+        let name = self.stmt_name_gen.next("result");
+
+        match intrinsic_fn {
+            AddInt => LLVMBuildAdd(builder, args[0], args[1], name.as_ptr()),
+            SubInt => LLVMBuildSub(builder, args[0], args[1], name.as_ptr()),
+            MulInt => LLVMBuildMul(builder, args[0], args[1], name.as_ptr()),
+            DivInt => self.build_div_int(builder, args),
+            NegInt => LLVMBuildNeg(builder, args[0], name.as_ptr()),
+            EqInt => self.build_icmp(builder, LLVMIntEQ, args),
+            NotEqInt => self.build_icmp(builder, LLVMIntNE, args),
+            LessInt => self.build_icmp(builder, LLVMIntSLT, args),
+            LessEqInt => self.build_icmp(builder, LLVMIntSLE, args),
+            GreaterInt => self.build_icmp(builder, LLVMIntSGT, args),
+            GreaterEqInt => self.build_icmp(builder, LLVMIntSGE, args),
+
+            AddFloat => LLVMBuildFAdd(builder, args[0], args[1], name.as_ptr()),
+            SubFloat => LLVMBuildFSub(builder, args[0], args[1], name.as_ptr()),
+            MulFloat => LLVMBuildFMul(builder, args[0], args[1], name.as_ptr()),
+            DivFloat => LLVMBuildFDiv(builder, args[0], args[1], name.as_ptr()),
+            NegFloat => LLVMBuildFNeg(builder, args[0], name.as_ptr()),
+            EqFloat => self.build_fcmp(builder, LLVMRealOEQ, args),
+            NotEqFloat => self.build_fcmp(builder, LLVMRealUNE, args),
+            LessFloat => self.build_fcmp(builder, LLVMRealOLT, args),
+            LessEqFloat => self.build_fcmp(builder, LLVMRealOLE, args),
+            GreaterFloat => self.build_fcmp(builder, LLVMRealOGT, args),
+            GreaterEqFloat => self.build_fcmp(builder, LLVMRealOGE, args),
+
+            // Bools are 0 or 1, so bitwise operations work on them
+            NotBool => LLVMBuildXor(builder, args[0], self.const_u8(1), name.as_ptr()),
+            EqBool => self.build_icmp(builder, LLVMIntEQ, args),
+            NotEqBool => self.build_icmp(builder, LLVMIntNE, args),
+
+            ListGet(element) => self.build_list_get(builder, element, args[0], args[1]),
+            StructGet(i, _) => LLVMBuildExtractValue(builder, args[0], i as c_uint, name.as_ptr())
+        }
+    }
+
+    // Comparisons result in an i1, it's extended to the representation of Bool
+    unsafe fn build_icmp(&mut self, builder: LLVMBuilderRef, predicate: LLVMIntPredicate, args: &[LLVMValueRef]) -> LLVMValueRef {
+        let name = self.stmt_name_gen.next("cmp");
+        let cmp_ref = LLVMBuildICmp(builder, predicate, args[0], args[1], name.as_ptr());
+
+        let name = self.stmt_name_gen.next("result");
+        LLVMBuildZExt(builder, cmp_ref, LLVMInt8TypeInContext(self.llvm_context), name.as_ptr())
+    }
+
+    unsafe fn build_fcmp(&mut self, builder: LLVMBuilderRef, predicate: LLVMRealPredicate, args: &[LLVMValueRef]) -> LLVMValueRef {
+        let name = self.stmt_name_gen.next("cmp");
+        let cmp_ref = LLVMBuildFCmp(builder, predicate, args[0], args[1], name.as_ptr());
+
+        let name = self.stmt_name_gen.next("result");
+        LLVMBuildZExt(builder, cmp_ref, LLVMInt8TypeInContext(self.llvm_context), name.as_ptr())
+    }
+
+    unsafe fn build_create_list(&mut self, builder: LLVMBuilderRef, list_type: ir::Type, values: &[LLVMValueRef]) -> LLVMValueRef {
+        let element_type = match list_type {
+            ir::Type::List(list_ref) => self.types.list_type(list_ref).element,
+            typ => panic!("Expected a list type, got {:?}", typ)
+        };
+        let llvm_element_type = llvm_type_of(self.llvm_context, self.types, element_type);
+        let llvm_list_type = llvm_list_struct_type(self.llvm_context, self.types, element_type);
+
+        let (list_new_type, list_new_ref) = self.runtime_fn(
+            runtime_fns::LIST_NEW,
+            &mut [LLVMInt64TypeInContext(self.llvm_context), LLVMInt64TypeInContext(self.llvm_context)],
+            LLVMPointerType(LLVMInt8TypeInContext(self.llvm_context), 0)
+        );
+
+        let mut args = [self.const_u64(values.len() as u64), LLVMSizeOf(llvm_element_type)];
+        let name = self.stmt_name_gen.next("list_alloc");
+        let list_ref = LLVMBuildCall2(builder, list_new_type, list_new_ref, args.as_mut_ptr(), args.len() as c_uint, name.as_ptr());
+
+        let name = self.stmt_name_gen.next("list");
+        let list_ref = LLVMBuildPointerCast(builder, list_ref, LLVMPointerType(llvm_list_type, 0), name.as_ptr());
+
+        for (i, value) in values.iter().enumerate() {
+            let mut indices = [self.const_i32(0), self.const_i32(1), self.const_u64(i as u64)];
+            let name = self.stmt_name_gen.next("element_ptr");
+            let element_ptr = LLVMBuildGEP2(builder, llvm_list_type, list_ref, indices.as_mut_ptr(), indices.len() as c_uint, name.as_ptr());
+
+            LLVMBuildStore(builder, *value, element_ptr);
+        }
+
+        list_ref
+    }
+
+    unsafe fn build_list_get(&mut self, builder: LLVMBuilderRef, element_type: ir::Type, list_ref: LLVMValueRef, index_ref: LLVMValueRef) -> LLVMValueRef {
+        let llvm_element_type = llvm_type_of(self.llvm_context, self.types, element_type);
+        let llvm_list_type = llvm_list_struct_type(self.llvm_context, self.types, element_type);
+
+        let mut indices = [self.const_i32(0), self.const_i32(0)];
+        let name = self.stmt_name_gen.next("length_ptr");
+        let length_ptr = LLVMBuildGEP2(builder, llvm_list_type, list_ref, indices.as_mut_ptr(), indices.len() as c_uint, name.as_ptr());
+
+        let name = self.stmt_name_gen.next("length");
+        let length = LLVMBuildLoad2(builder, LLVMInt64TypeInContext(self.llvm_context), length_ptr, name.as_ptr());
+
+        let (check_index_type, check_index_ref) = self.runtime_fn(
+            runtime_fns::LIST_CHECK_INDEX,
+            &mut [LLVMInt64TypeInContext(self.llvm_context), LLVMInt64TypeInContext(self.llvm_context)],
+            LLVMVoidTypeInContext(self.llvm_context)
+        );
+
+        let mut args = [index_ref, length];
+        LLVMBuildCall2(builder, check_index_type, check_index_ref, args.as_mut_ptr(), args.len() as c_uint, c"".as_ptr());
+
+        let mut indices = [self.const_i32(0), self.const_i32(1), index_ref];
+        let name = self.stmt_name_gen.next("element_ptr");
+        let element_ptr = LLVMBuildGEP2(builder, llvm_list_type, list_ref, indices.as_mut_ptr(), indices.len() as c_uint, name.as_ptr());
+
+        let name = self.stmt_name_gen.next("element");
+        LLVMBuildLoad2(builder, llvm_element_type, element_ptr, name.as_ptr())
+    }
+
+    // The runtime aborts on the divisions which are undefined behaviour in LLVM
+    unsafe fn build_div_int(&mut self, builder: LLVMBuilderRef, args: &[LLVMValueRef]) -> LLVMValueRef {
+        let (check_div_type, check_div_ref) = self.runtime_fn(
+            runtime_fns::INT_CHECK_DIV,
+            &mut [LLVMInt64TypeInContext(self.llvm_context), LLVMInt64TypeInContext(self.llvm_context)],
+            LLVMVoidTypeInContext(self.llvm_context)
+        );
+
+        let mut check_args = [args[0], args[1]];
+        LLVMBuildCall2(builder, check_div_type, check_div_ref, check_args.as_mut_ptr(), check_args.len() as c_uint, c"".as_ptr());
+
+        let name = self.stmt_name_gen.next("result");
+        LLVMBuildSDiv(builder, args[0], args[1], name.as_ptr())
+    }
+
+    unsafe fn build_create_cell(&mut self, builder: LLVMBuilderRef, cell_type: ir::Type, value: LLVMValueRef) -> LLVMValueRef {
+        let pointer_type = LLVMPointerType(LLVMInt8TypeInContext(self.llvm_context), 0);
+        let element_type = llvm_type_of(self.llvm_context, self.types, self.cell_element_type(cell_type));
+
+        let (alloc_type, alloc_ref) = self.runtime_fn(
+            runtime_fns::ALLOC,
+            &mut [LLVMInt64TypeInContext(self.llvm_context)],
+            pointer_type
+        );
+
+        let mut args = [LLVMSizeOf(element_type)];
+        let name = self.stmt_name_gen.next("cell");
+        let cell_ref = LLVMBuildCall2(builder, alloc_type, alloc_ref, args.as_mut_ptr(), args.len() as c_uint, name.as_ptr());
+
+        let name = self.stmt_name_gen.next("cell");
+        let cell_ref = LLVMBuildPointerCast(builder, cell_ref, LLVMPointerType(element_type, 0), name.as_ptr());
+        LLVMBuildStore(builder, value, cell_ref);
+
+        cell_ref
+    }
+
+    fn cell_element_type(&self, cell_type: ir::Type) -> ir::Type {
+        match cell_type {
+            ir::Type::Cell(cell_ref) => self.types.cell_type(cell_ref).element,
+            typ => panic!("Expected a cell type, got {:?}", typ)
+        }
+    }
+
+    // The stack slot of a mutable local, it's allocated in the entry block the first time the
+    // local is set
+    unsafe fn variable(&mut self, local_ref: lir::LocalRef, typ: ir::Type) -> (LLVMTypeRef, LLVMValueRef) {
+        if let Some(variable) = self.variables[local_ref.i] {
+            return variable;
+        }
+
+        let llvm_type = llvm_type_of(self.llvm_context, self.types, typ);
+
+        let entry_block = LLVMGetEntryBasicBlock(self.decl.func_ref);
+        let builder = LLVMCreateBuilderInContext(self.llvm_context);
+        let first_instruction = LLVMGetFirstInstruction(entry_block);
+        if first_instruction.is_null() {
+            LLVMPositionBuilderAtEnd(builder, entry_block);
+        } else {
+            LLVMPositionBuilderBefore(builder, first_instruction);
+        }
+
+        let name = self.stmt_name_gen.next("var");
+        let variable_ref = LLVMBuildAlloca(builder, llvm_type, name.as_ptr());
+        LLVMDisposeBuilder(builder);
+
+        self.variables[local_ref.i] = Some((llvm_type, variable_ref));
+
+        (llvm_type, variable_ref)
+    }
+
+    unsafe fn build_create_interface_value(&mut self, builder: LLVMBuilderRef, vtable: &VTableDeclaration, value: LLVMValueRef) -> LLVMValueRef {
+        let pointer_type = LLVMPointerType(LLVMInt8TypeInContext(self.llvm_context), 0);
+        let concrete_type = llvm_type_of(self.llvm_context, self.types, vtable.concrete);
+
+        let (alloc_type, alloc_ref) = self.runtime_fn(
+            runtime_fns::ALLOC,
+            &mut [LLVMInt64TypeInContext(self.llvm_context)],
+            pointer_type
+        );
+
+        let mut args = [LLVMSizeOf(concrete_type)];
+        let name = self.stmt_name_gen.next("data");
+        let data_ref = LLVMBuildCall2(builder, alloc_type, alloc_ref, args.as_mut_ptr(), args.len() as c_uint, name.as_ptr());
+
+        let name = self.stmt_name_gen.next("data_ptr");
+        let typed_data_ref = LLVMBuildPointerCast(builder, data_ref, LLVMPointerType(concrete_type, 0), name.as_ptr());
+        LLVMBuildStore(builder, value, typed_data_ref);
+
+        let name = self.stmt_name_gen.next("vtable");
+        let vtable_ref = LLVMBuildPointerCast(builder, vtable.global_ref, pointer_type, name.as_ptr());
+
+        let interface_type = llvm_type_of(self.llvm_context, self.types, vtable.interface);
+
+        let name = self.stmt_name_gen.next("interface");
+        let result_ref = LLVMBuildInsertValue(builder, LLVMGetUndef(interface_type), data_ref, 0, name.as_ptr());
+        let name = self.stmt_name_gen.next("interface");
+        LLVMBuildInsertValue(builder, result_ref, vtable_ref, 1, name.as_ptr())
+    }
+
+    unsafe fn build_call_method(&mut self, builder: LLVMBuilderRef, interface: ir::Type, i: usize, args: &[LLVMValueRef]) -> LLVMValueRef {
+        let signature = match interface {
+            ir::Type::Interface(interface_ref) => &self.types.interface_type(interface_ref).methods[i].signature,
+            typ => panic!("Expected an interface type, got {:?}", typ)
+        };
+        let method_type = llvm_method_type(self.llvm_context, self.types, signature);
+        let vtable_type = llvm_vtable_type(self.llvm_context, self.types, interface);
+
+        let name = self.stmt_name_gen.next("data");
+        let data_ref = LLVMBuildExtractValue(builder, args[0], 0, name.as_ptr());
+
+        let name = self.stmt_name_gen.next("vtable");
+        let vtable_ref = LLVMBuildExtractValue(builder, args[0], 1, name.as_ptr());
+        let name = self.stmt_name_gen.next("vtable");
+        let vtable_ref = LLVMBuildPointerCast(builder, vtable_ref, LLVMPointerType(vtable_type, 0), name.as_ptr());
+
+        let mut indices = [self.const_i32(0), self.const_i32(i as i32)];
+        let name = self.stmt_name_gen.next("method_ptr");
+        let method_ptr_ref = LLVMBuildGEP2(builder, vtable_type, vtable_ref, indices.as_mut_ptr(), indices.len() as c_uint, name.as_ptr());
+
+        let name = self.stmt_name_gen.next("method");
+        let method_ref = LLVMBuildLoad2(builder, LLVMPointerType(method_type, 0), method_ptr_ref, name.as_ptr());
+
+        let mut call_args = Vec::with_capacity(args.len());
+        call_args.push(data_ref);
+        call_args.extend_from_slice(&args[1..]);
+
+        let name = self.stmt_name_gen.next("result");
+        LLVMBuildCall2(builder, method_type, method_ref, call_args.as_mut_ptr(), call_args.len() as c_uint, name.as_ptr())
+    }
+
+    // The env is a copy of the closure, it's null if the function doesn't capture anything
+    unsafe fn build_create_function_value(&mut self, builder: LLVMBuilderRef, func_ref: lir::FunctionRef, closure_ref: LLVMValueRef) -> LLVMValueRef {
+        let pointer_type = LLVMPointerType(LLVMInt8TypeInContext(self.llvm_context), 0);
+        let function_type = LLVMStructTypeInContext(self.llvm_context, [pointer_type, pointer_type].as_mut_ptr(), 2, 0);
+
+        let adapter_ref = self.function_value_adapter(func_ref);
+
+        let name = self.stmt_name_gen.next("function_ptr");
+        let function_ptr_ref = LLVMBuildPointerCast(builder, adapter_ref, pointer_type, name.as_ptr());
+
+        let env_ref = match self.functions[func_ref.i].closure_type {
+            None => LLVMConstNull(pointer_type),
+            Some(closure_type) => {
+                let (alloc_type, alloc_ref) = self.runtime_fn(
+                    runtime_fns::ALLOC,
+                    &mut [LLVMInt64TypeInContext(self.llvm_context)],
+                    pointer_type
+                );
+
+                let mut args = [LLVMSizeOf(closure_type)];
+                let name = self.stmt_name_gen.next("env");
+                let env_ref = LLVMBuildCall2(builder, alloc_type, alloc_ref, args.as_mut_ptr(), args.len() as c_uint, name.as_ptr());
+
+                let name = self.stmt_name_gen.next("env_ptr");
+                let typed_env_ref = LLVMBuildPointerCast(builder, env_ref, LLVMPointerType(closure_type, 0), name.as_ptr());
+                LLVMBuildStore(builder, closure_ref, typed_env_ref);
+
+                env_ref
+            }
+        };
+
+        let name = self.stmt_name_gen.next("function");
+        let result_ref = LLVMBuildInsertValue(builder, LLVMGetUndef(function_type), function_ptr_ref, 0, name.as_ptr());
+        let name = self.stmt_name_gen.next("function");
+        LLVMBuildInsertValue(builder, result_ref, env_ref, 1, name.as_ptr())
+    }
+
+    unsafe fn build_call_function_value(&mut self, builder: LLVMBuilderRef, function_type: ir::Type, args: &[LLVMValueRef]) -> LLVMValueRef {
+        let signature = match function_type {
+            ir::Type::Function(function_ref) => self.types.function_type(function_ref),
+            typ => panic!("Expected a function type, got {:?}", typ)
+        };
+        let fn_type = llvm_function_value_fn_type(self.llvm_context, self.types, signature);
+
+        let name = self.stmt_name_gen.next("function_ptr");
+        let function_ptr_ref = LLVMBuildExtractValue(builder, args[0], 0, name.as_ptr());
+        let name = self.stmt_name_gen.next("function_ptr");
+        let function_ptr_ref = LLVMBuildPointerCast(builder, function_ptr_ref, LLVMPointerType(fn_type, 0), name.as_ptr());
+
+        let name = self.stmt_name_gen.next("env");
+        let env_ref = LLVMBuildExtractValue(builder, args[0], 1, name.as_ptr());
+
+        let mut call_args = Vec::with_capacity(args.len());
+        call_args.extend_from_slice(&args[1..]);
+        call_args.push(env_ref);
+
+        let name = self.stmt_name_gen.next("result");
+        LLVMBuildCall2(builder, fn_type, function_ptr_ref, call_args.as_mut_ptr(), call_args.len() as c_uint, name.as_ptr())
+    }
+
+    // Functions don't take an env parameter, so function values point to a wrapper which does. The
+    // wrapper loads the closure from the env if the function captures values. It's defined in the
+    // module the first time it's needed.
+    unsafe fn function_value_adapter(&self, func_ref: lir::FunctionRef) -> LLVMValueRef {
+        let c_name = CString::new(format!("func_{}_value", func_ref.i)).unwrap();
+        let adapter_ref = LLVMGetNamedFunction(self.llvm_module, c_name.as_ptr());
+        if !adapter_ref.is_null() {
+            return adapter_ref;
+        }
+
+        let decl = &self.functions[func_ref.i];
+
+        let mut param_types = decl.param_types.clone();
+        param_types.push(LLVMPointerType(LLVMInt8TypeInContext(self.llvm_context), 0));
+
+        let adapter_type = LLVMFunctionType(LLVMGetReturnType(decl.type_ref), param_types.as_mut_ptr(), param_types.len() as c_uint, 0);
+        let adapter_ref = LLVMAddFunction(self.llvm_module, c_name.as_ptr(), adapter_type);
+        LLVMSetLinkage(adapter_ref, LLVMLinkage::LLVMInternalLinkage);
+
+        let basic_block = LLVMAppendBasicBlockInContext(self.llvm_context, adapter_ref, c_str!("entry"));
+        let builder = LLVMCreateBuilderInContext(self.llvm_context);
+        LLVMPositionBuilderAtEnd(builder, basic_block);
+
+        let mut args: Vec<LLVMValueRef> = (0..decl.param_types.len())
+            .map(|i| LLVMGetParam(adapter_ref, i as c_uint))
+            .collect();
+
+        if let Some(closure_type) = decl.closure_type {
+            let env_ref = LLVMGetParam(adapter_ref, decl.param_types.len() as c_uint);
+            let env_ref = LLVMBuildPointerCast(builder, env_ref, LLVMPointerType(closure_type, 0), c_str!("env"));
+
+            args.push(LLVMBuildLoad2(builder, closure_type, env_ref, c_str!("closure")));
+        }
+
+        let result_ref = LLVMBuildCall2(builder, decl.type_ref, decl.func_ref, args.as_mut_ptr(), args.len() as c_uint, c_str!("result"));
+        LLVMBuildRet(builder, result_ref);
+        LLVMDisposeBuilder(builder);
+
+        adapter_ref
+    }
+
+    // Declares the function in the module the first time it's used
+    unsafe fn runtime_fn(&self, name: &str, param_types: &mut [LLVMTypeRef], return_type: LLVMTypeRef) -> (LLVMTypeRef, LLVMValueRef) {
+        let fn_type = LLVMFunctionType(return_type, param_types.as_mut_ptr(), param_types.len() as c_uint, 0);
+
+        let c_name = CString::new(name).unwrap();
+        let mut fn_ref = LLVMGetNamedFunction(self.llvm_module, c_name.as_ptr());
+        if fn_ref.is_null() {
+            fn_ref = LLVMAddFunction(self.llvm_module, c_name.as_ptr(), fn_type);
+        }
+
+        (fn_type, fn_ref)
     }
 
     unsafe fn llvm_value_refs_of(&mut self, builder: LLVMBuilderRef, lir_value_refs: &[lir::ValueRef]) -> Vec<LLVMValueRef> {
@@ -101,47 +670,46 @@ impl <'a> FunctionBuilder<'a> {
             lir::ValueRef::None => self.const_lir_value(&ir::Value::None),
             lir::ValueRef::Bool(value) => self.const_lir_value(&ir::Value::Bool(value)),
             lir::ValueRef::Int(value) => self.const_lir_value(&ir::Value::Int(value)),
-            lir::ValueRef::Float(_) => todo!("Support float consts"),
-            // lir::ValueRef::Global(_) => todo!("Support globals"),
+            lir::ValueRef::Float(value) => self.const_lir_value(&ir::Value::Float(value)),
+
+            // TODO: Type error instead of panic
+            lir::ValueRef::Type(_) => panic!("Cannot export Type to runtime as it's not serializable"),
+            lir::ValueRef::Global(global_ref) => {
+                let global = &self.globals[global_ref.i];
+                let name = self.stmt_name_gen.next("global");
+
+                LLVMBuildLoad2(builder, global.type_ref, global.global_ref, name.as_ptr())
+            }
             // lir::ValueRef::ComptimeExport(_) => todo!("Support comptime exports"),
-            // lir::ValueRef::Const(const_ref) => self.const_lir_value(&self.lir_module.constants[const_ref.i]),
-            // lir::ValueRef::Capture(capture_ref) => {
-            //     // The last argument is the capture struct
-            //     let capture_struct_ref = LLVMGetParam(self.decl.func_ref, (self.decl.param_types.len() - 1) as c_uint);
-            //
-            //     // TODO: Use the name of the capture variable
-            //     let name = self.stmt_name_gen.next("capture");
-            //
-            //     LLVMBuildExtractValue(builder, capture_struct_ref, capture_ref.i as c_uint, name.as_ptr())
-            // }
+            lir::ValueRef::Capture(capture_ref) => {
+                // The closure is passed after the parameters
+                let closure_ref = LLVMGetParam(self.decl.func_ref, self.decl.param_types.len() as c_uint);
+
+                // TODO: Use the name of the capture variable
+                let name = self.stmt_name_gen.next("capture");
+
+                LLVMBuildExtractValue(builder, closure_ref, capture_ref.i as c_uint, name.as_ptr())
+            }
             lir::ValueRef::Param(param_ref) => LLVMGetParam(self.decl.func_ref, param_ref.i as c_uint),
-            lir::ValueRef::Local(local_ref) => self.local_refs[local_ref.i].expect("Local get before set")
+            lir::ValueRef::Local(local_ref) => match self.variables[local_ref.i] {
+                Some((llvm_type, variable_ref)) => {
+                    let name = self.stmt_name_gen.next("var");
+
+                    LLVMBuildLoad2(builder, llvm_type, variable_ref, name.as_ptr())
+                }
+                None => self.local_refs[local_ref.i].expect("Local get before set")
+            }
         }
     }
 
     unsafe fn const_lir_value(&self, ir_value: &ir::Value) -> LLVMValueRef {
-        match ir_value {
-            // TODO: Better `void` type
-            ir::Value::None => self.const_u8(0),
-            ir::Value::Bool(value) => self.const_u8(if *value { 1 } else { 0 }),
-            ir::Value::Int(value) => self.const_i64(*value),
-            ir::Value::Float(_) => todo!("Support float consts"),
-
-            // TODO: Type error instead of panic
-            ir::Value::Type(_) => panic!("Cannot export Type to runtime as it's not serializable"),
-            ir::Value::Closure(_, _) => todo!("Serialize closure")
-        }
+        llvm_const_value(self.llvm_context, ir_value)
     }
 
     unsafe fn const_u8(&self, value: u8) -> LLVMValueRef {
         LLVMConstInt(LLVMInt8TypeInContext(self.llvm_context), value as u64, 0)
     }
 
-    unsafe fn const_i64(&self, value: i64) -> LLVMValueRef {
-        // TODO: Should this be a transmute? Test with negative numbers
-        LLVMConstInt(LLVMInt64TypeInContext(self.llvm_context), value as u64, 1)
-    }
-
     unsafe fn const_u64(&self, value: u64) -> LLVMValueRef {
         LLVMConstInt(LLVMInt64TypeInContext(self.llvm_context), value, 0)
     }
@@ -11,6 +11,7 @@ use llvm_sys::target_machine::*;
 use llvm_sys::transforms::pass_builder::*;
 use crate::compiler::c_str;
 use crate::compiler::compiler::Compiler;
+use crate::compiler::runtime_fns;
 use crate::{ir, lir};
 
 pub struct JITCompiler {
@@ -103,30 +104,29 @@ impl JITCompiler {
             panic!("Could not add module to JIT: {}", CString::from_raw(error_message).into_string().unwrap());
         }
 
-        // TODO: Define rust intrinsic methods to be able to call
-        // let mut host_symbols = Vec::new();
-        //
-        // for (name, intrinsic_fn) in &self.context.host_fns {
-        //     let c_name = CString::new(name.clone()).unwrap();
-        //
-        //     let fn_name = LLVMOrcLLJITMangleAndIntern(self.jit, c_name.as_ptr());
-        //     let symbol = LLVMJITEvaluatedSymbol {
-        //         Address: intrinsic_fn.func_addr as u64,
-        //         Flags: LLVMJITSymbolFlags {
-        //             GenericFlags: 0, // LLVMJITSymbolGenericFlagsExported as u8,
-        //             TargetFlags: 0, // LLVMJITSymbolGenericFlagsExported as u8
-        //         }
-        //     };
-        //
-        //     host_symbols.push(LLVMOrcCSymbolMapPair { Name: fn_name, Sym: symbol })
-        // }
-        //
-        // let materialization_unit = LLVMOrcAbsoluteSymbols(host_symbols.as_mut_ptr(), host_symbols.len());
-        // let error_ref = LLVMOrcJITDylibDefine(dylib, materialization_unit);
-        // if !error_ref.is_null() {
-        //     let error_message = LLVMGetErrorMessage(error_ref);
-        //     panic!("Could not link to parent: {}", CString::from_raw(error_message).into_string().unwrap());
-        // }
+        let mut host_symbols = Vec::new();
+
+        for (name, address) in runtime_fns::runtime_fn_addresses() {
+            let c_name = CString::new(name).unwrap();
+
+            let fn_name = LLVMOrcLLJITMangleAndIntern(self.jit, c_name.as_ptr());
+            let symbol = LLVMJITEvaluatedSymbol {
+                Address: address,
+                Flags: LLVMJITSymbolFlags {
+                    GenericFlags: 0, // LLVMJITSymbolGenericFlagsExported as u8,
+                    TargetFlags: 0, // LLVMJITSymbolGenericFlagsExported as u8
+                }
+            };
+
+            host_symbols.push(LLVMOrcCSymbolMapPair { Name: fn_name, Sym: symbol })
+        }
+
+        let materialization_unit = LLVMOrcAbsoluteSymbols(host_symbols.as_mut_ptr(), host_symbols.len());
+        let error_ref = LLVMOrcJITDylibDefine(dylib, materialization_unit);
+        if !error_ref.is_null() {
+            let error_message = LLVMGetErrorMessage(error_ref);
+            panic!("Could not define runtime functions: {}", CString::from_raw(error_message).into_string().unwrap());
+        }
     }
 
     unsafe fn optimize_module(&self) {
@@ -0,0 +1,16 @@
+// Functions from the `runtime` crate which the compiled code calls into. They are declared in the
+// LLVM module by name and their addresses are provided to the JIT as absolute symbols.
+
+pub const ALLOC: &str = "photon_alloc";
+pub const LIST_NEW: &str = "photon_list_new";
+pub const LIST_CHECK_INDEX: &str = "photon_list_check_index";
+pub const INT_CHECK_DIV: &str = "photon_int_check_div";
+
+pub fn runtime_fn_addresses() -> [(&'static str, u64); 4] {
+    [
+        (ALLOC, runtime::photon_alloc as *const () as u64),
+        (LIST_NEW, runtime::photon_list_new as *const () as u64),
+        (LIST_CHECK_INDEX, runtime::photon_list_check_index as *const () as u64),
+        (INT_CHECK_DIV, runtime::photon_int_check_div as *const () as u64)
+    ]
+}
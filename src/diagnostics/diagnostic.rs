@@ -0,0 +1,87 @@
+use crate::ast::Location;
+
+/// A message for the developer about a problem in their code, e.g. a compile error.
+/// Use `Renderer` to show it together with the source code it refers to.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+
+    // Stable identifier of the kind of problem, e.g. `E0100`
+    pub code: Option<Box<str>>,
+
+    pub message: Box<str>,
+    pub labels: Vec<Label>,
+
+    pub notes: Vec<Box<str>>,
+    pub help: Option<Box<str>>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub style: LabelStyle,
+    pub location: Location,
+    pub message: Box<str>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelStyle {
+    // Where the problem is
+    Primary,
+
+    // Other code which is related to the problem
+    Secondary
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: &str, message: impl Into<Box<str>>) -> Self {
+        Diagnostic {
+            severity,
+            code: Some(code.into()),
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: None
+        }
+    }
+
+    pub fn error(code: &str, message: impl Into<Box<str>>) -> Self {
+        Self::new(Severity::Error, code, message)
+    }
+
+    pub fn warning(code: &str, message: impl Into<Box<str>>) -> Self {
+        Self::new(Severity::Warning, code, message)
+    }
+
+    pub fn with_primary(mut self, location: Location, message: impl Into<Box<str>>) -> Self {
+        self.labels.push(Label { style: LabelStyle::Primary, location, message: message.into() });
+        self
+    }
+
+    pub fn with_secondary(mut self, location: Location, message: impl Into<Box<str>>) -> Self {
+        self.labels.push(Label { style: LabelStyle::Secondary, location, message: message.into() });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<Box<str>>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<Box<str>>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn primary_location(&self) -> Option<&Location> {
+        self.labels.iter()
+            .find(|label| label.style == LabelStyle::Primary)
+            .map(|label| &label.location)
+    }
+}
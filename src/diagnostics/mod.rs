@@ -0,0 +1,5 @@
+mod diagnostic;
+mod renderer;
+
+pub use diagnostic::*;
+pub use renderer::*;
@@ -0,0 +1,285 @@
+use std::fmt::Write;
+use crate::ast::{FileId, SourceFile, SourceMap};
+use crate::diagnostics::{Diagnostic, Label, LabelStyle, Severity};
+
+/// Formats diagnostics like this, optionally with ANSI colors:
+///
+/// ```text
+/// error[E0100]: Cannot find `foo`
+///  --> main.ph:2:9
+///   |
+/// 2 | val b = foo + 1
+///   |         ^^^ not found in this scope
+///   |
+///   = help: declare it with `val foo = ...` first
+/// ```
+pub struct Renderer {
+    pub colored: bool
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const GREEN: &str = "\x1b[1;32m";
+const BLUE: &str = "\x1b[1;34m";
+
+const TAB_WIDTH: usize = 4;
+
+// The part of a label which is on a single line
+struct Underline<'a> {
+    label: &'a Label,
+    from: usize,
+    to: usize,
+    is_last_line: bool
+}
+
+impl Renderer {
+    pub fn plain() -> Self {
+        Renderer { colored: false }
+    }
+
+    pub fn colored() -> Self {
+        Renderer { colored: true }
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic, source_map: &SourceMap) -> String {
+        let mut out = String::new();
+
+        self.render_header(&mut out, diagnostic);
+
+        let files = Self::files_of(diagnostic);
+        let gutter_width = files.iter()
+            .flat_map(|file| Self::lines_to_show(diagnostic, *file, source_map.file(*file)))
+            .max()
+            .map_or(1, |line| (line + 1).to_string().len());
+
+        for (i, file) in files.iter().enumerate() {
+            self.render_file(&mut out, diagnostic, source_map.file(*file), gutter_width, i == 0);
+        }
+
+        if !diagnostic.notes.is_empty() || diagnostic.help.is_some() {
+            if !files.is_empty() {
+                self.render_gutter(&mut out, gutter_width, "");
+            }
+
+            for note in &diagnostic.notes {
+                self.render_footer(&mut out, gutter_width, "note", note);
+            }
+
+            if let Some(help) = &diagnostic.help {
+                self.render_footer(&mut out, gutter_width, "help", help);
+            }
+        }
+
+        out
+    }
+
+    fn render_header(&self, out: &mut String, diagnostic: &Diagnostic) {
+        let severity = match diagnostic.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note"
+        };
+
+        out.push_str(self.color(self.severity_color(diagnostic.severity)));
+        out.push_str(severity);
+
+        if let Some(code) = &diagnostic.code {
+            write!(out, "[{}]", code).unwrap();
+        }
+
+        out.push_str(self.color(RESET));
+        out.push_str(self.color(BOLD));
+        write!(out, ": {}", diagnostic.message).unwrap();
+        out.push_str(self.color(RESET));
+        out.push('\n');
+    }
+
+    fn render_file(&self, out: &mut String, diagnostic: &Diagnostic, file: &SourceFile, gutter_width: usize, is_first: bool) {
+        let labels: Vec<&Label> = diagnostic.labels.iter()
+            .filter(|label| label.location.file == file.id)
+            .collect();
+
+        let main_label = labels.iter()
+            .find(|label| label.style == LabelStyle::Primary)
+            .unwrap_or(&labels[0]);
+
+        let arrow = if is_first { "-->" } else { ":::" };
+        writeln!(
+            out,
+            "{}{}{}{} {}:{}:{}",
+            " ".repeat(gutter_width),
+            self.color(BLUE),
+            arrow,
+            self.color(RESET),
+            file.name,
+            main_label.location.from.line + 1,
+            main_label.location.from.column + 1
+        ).unwrap();
+
+        self.render_gutter(out, gutter_width, "");
+
+        let mut last_line = None;
+        for line in Self::lines_to_show(diagnostic, file.id, file) {
+            if last_line.is_some_and(|last_line| line > last_line + 1) {
+                out.push_str(self.color(BLUE));
+                out.push_str("...");
+                out.push_str(self.color(RESET));
+                out.push('\n');
+            }
+            last_line = Some(line);
+
+            let text = file.line(line);
+            self.render_source_line(out, gutter_width, line, text);
+
+            let mut underlines: Vec<Underline> = labels.iter()
+                .filter_map(|label| Self::underline(label, line, text))
+                .collect();
+            underlines.sort_by_key(|underline| underline.from);
+
+            for underline in underlines {
+                self.render_underline(out, gutter_width, text, &underline, diagnostic.severity);
+            }
+        }
+    }
+
+    fn render_source_line(&self, out: &mut String, gutter_width: usize, line: usize, text: &str) {
+        write!(
+            out,
+            "{}{:>width$} |{}",
+            self.color(BLUE),
+            line + 1,
+            self.color(RESET),
+            width = gutter_width
+        ).unwrap();
+
+        if !text.is_empty() {
+            out.push(' ');
+            out.push_str(&text.replace('\t', &" ".repeat(TAB_WIDTH)));
+        }
+
+        out.push('\n');
+    }
+
+    fn render_underline(&self, out: &mut String, gutter_width: usize, text: &str, underline: &Underline, severity: Severity) {
+        let (marker, color) = match underline.label.style {
+            LabelStyle::Primary => ('^', self.severity_color(severity)),
+            LabelStyle::Secondary => ('-', BLUE)
+        };
+
+        let indent = Self::display_width(text, 0, underline.from);
+        let width = Self::display_width(text, underline.from, underline.to).max(1);
+
+        let mut content = format!("{}{}{}", " ".repeat(indent), self.color(color), marker.to_string().repeat(width));
+
+        if underline.is_last_line && !underline.label.message.is_empty() {
+            write!(content, " {}", underline.label.message).unwrap();
+        }
+
+        content.push_str(self.color(RESET));
+
+        self.render_gutter(out, gutter_width, &content);
+    }
+
+    fn render_gutter(&self, out: &mut String, gutter_width: usize, content: &str) {
+        write!(out, "{}{} |{}", " ".repeat(gutter_width), self.color(BLUE), self.color(RESET)).unwrap();
+
+        if !content.is_empty() {
+            write!(out, " {}", content).unwrap();
+        }
+
+        out.push('\n');
+    }
+
+    fn render_footer(&self, out: &mut String, gutter_width: usize, kind: &str, message: &str) {
+        writeln!(
+            out,
+            "{}{} ={} {}{}:{} {}",
+            " ".repeat(gutter_width),
+            self.color(BLUE),
+            self.color(RESET),
+            self.color(BOLD),
+            kind,
+            self.color(RESET),
+            message
+        ).unwrap();
+    }
+
+    // Files in the order they should be shown, the one with the primary label is first
+    fn files_of(diagnostic: &Diagnostic) -> Vec<FileId> {
+        let mut files = Vec::new();
+
+        if let Some(location) = diagnostic.primary_location() {
+            files.push(location.file);
+        }
+
+        for label in &diagnostic.labels {
+            if !files.contains(&label.location.file) {
+                files.push(label.location.file);
+            }
+        }
+
+        files
+    }
+
+    // The first and last lines of each label, spans over many lines are shortened
+    fn lines_to_show(diagnostic: &Diagnostic, file_id: FileId, file: &SourceFile) -> Vec<usize> {
+        let last_line = file.line_count() - 1;
+
+        let mut lines: Vec<usize> = diagnostic.labels.iter()
+            .filter(|label| label.location.file == file_id)
+            .flat_map(|label| [label.location.from.line, label.location.to.line])
+            .map(|line| (line.max(0) as usize).min(last_line))
+            .collect();
+
+        lines.sort();
+        lines.dedup();
+
+        lines
+    }
+
+    fn underline<'a>(label: &'a Label, line: usize, text: &str) -> Option<Underline<'a>> {
+        let from = &label.location.from;
+        let to = &label.location.to;
+
+        let from_line = from.line.max(0) as usize;
+        let to_line = (to.line.max(0) as usize).max(from_line);
+
+        if line != from_line && line != to_line {
+            return None;
+        }
+
+        let line_length = text.chars().count();
+
+        let start = if line == from_line { from.column.max(0) as usize } else { 0 };
+        let end = if line == to_line { to.column.max(0) as usize } else { line_length };
+
+        Some(Underline {
+            label,
+            from: start.min(line_length),
+            to: end.min(line_length).max(start.min(line_length)),
+            is_last_line: line == to_line
+        })
+    }
+
+    fn display_width(text: &str, from: usize, to: usize) -> usize {
+        text.chars()
+            .skip(from)
+            .take(to.saturating_sub(from))
+            .map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+            .sum()
+    }
+
+    fn severity_color(&self, severity: Severity) -> &'static str {
+        match severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+            Severity::Note => GREEN
+        }
+    }
+
+    fn color(&self, color: &'static str) -> &'static str {
+        if self.colored { color } else { "" }
+    }
+}
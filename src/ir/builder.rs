@@ -1,5 +1,7 @@
 use crate::{ast, ir};
+use crate::diagnostics::Diagnostic;
 use crate::ir::{Globals, Value};
+use crate::ir::definition_order::order_definitions;
 use crate::ir::lexical_scope::{NameAccessError, NameRef, RootScope, ScopeStack};
 use crate::vec_map::VecMap;
 
@@ -8,14 +10,24 @@ pub struct Builder {
 }
 
 impl Builder {
-    // TODO: Actual error handling instead of panics
-    pub fn build_module(ast: ast::AST, globals: &Globals) -> ir::Module {
+    pub fn build_module(ast: ast::AST, globals: &Globals) -> Result<ir::Module, Diagnostic> {
+        // Top-level definitions can be used before they are defined
+        let ast = match ast.value {
+            ast::Value::Block(asts) => ast::AST {
+                value: ast::Value::Block(order_definitions(asts)?),
+                location: ast.location
+            },
+            _ => ast
+        };
+
         let main_fn_ast = ast::Function {
             params: Vec::new(),
             body: Box::new(ast),
 
             // TODO: Signal that it doesn't have a return type, not that we don't know it yet
-            return_type: None
+            return_type: None,
+
+            doc_comment: None
         };
 
         let mut scope = ScopeStack::new(
@@ -26,15 +38,15 @@ impl Builder {
             functions: Vec::new()
         };
 
-        let main = builder.build_function(&mut scope, main_fn_ast);
+        let main = builder.build_function(&mut scope, main_fn_ast)?;
 
-        ir::Module {
+        Ok(ir::Module {
             functions: builder.functions,
             main
-        }
+        })
     }
 
-    fn build_function(&mut self, scope: &mut ScopeStack, ast: ast::Function) -> ir::Function {
+    fn build_function(&mut self, scope: &mut ScopeStack, ast: ast::Function) -> Result<ir::Function, Diagnostic> {
         let param_count = ast.params.len();
 
         let mut scope_params = Vec::with_capacity(param_count);
@@ -47,23 +59,17 @@ impl Builder {
 
         scope.push_stack_frame(scope_params);
 
+        // The bindings in the parameter types are visible in the next parameters, the return type
+        // and the body
+        scope.push_block();
+
         let mut params = Vec::with_capacity(param_count);
         for param in ast.params {
-            // TODO: Do we need this block to be here?
-            scope.push_block();
-
             let param_type = match param.typ {
                 None => None,
-                Some(ast::Pattern { value: ast::PatternValue::SpecificValue(ast), .. }) => {
-                    let ir = self.compile_implicit_comptime_ast(scope, ast);
-
-                    Some(Box::new(ir))
-                }
-                _ => todo!("Support patterns in arguments")
+                Some(pattern) => Some(self.build_pattern(scope, pattern)?)
             };
 
-            scope.pop_block();
-
             params.push(ir::Param {
                 typ: param_type,
                 comptime: param.comptime
@@ -72,13 +78,14 @@ impl Builder {
 
         let return_type = match ast.return_type {
             None => None,
-            Some(ast) => Some(self.compile_implicit_comptime_ast(scope, *ast))
+            Some(ast) => Some(self.compile_implicit_comptime_ast(scope, *ast)?)
         };
 
         scope.push_block();
 
-        let body = self.build_ir(scope, *ast.body);
+        let body = self.build_ir(scope, *ast.body)?;
 
+        scope.pop_block();
         scope.pop_block();
         let stack_frame = scope.pop_stack_frame();
 
@@ -92,13 +99,17 @@ impl Builder {
 
         let mut locals = Vec::with_capacity(stack_frame.locals.len());
         for local in stack_frame.locals {
-            locals.push(ir::Local { comptime: local.comptime });
+            locals.push(ir::Local {
+                comptime: local.comptime,
+                mutable: local.mutable,
+                boxed: local.mutable && local.captured && !local.comptime
+            });
         }
 
-        ir::Function { captures, params, return_type, locals, body }
+        Ok(ir::Function { captures, params, return_type, locals, body })
     }
 
-    fn build_ir(&mut self, scope: &mut ScopeStack, ast: ast::AST) -> ir::IR {
+    fn build_ir(&mut self, scope: &mut ScopeStack, ast: ast::AST) -> Result<ir::IR, Diagnostic> {
         let location = ast.location.clone();
         let node = match ast.value {
             ast::Value::Literal(ast::Literal::Bool(value)) => ir::Node::Constant(Value::Bool(value)),
@@ -109,9 +120,23 @@ impl Builder {
             ast::Value::Block(asts) => {
                 scope.push_block();
 
-                let mut irs = Vec::with_capacity(asts.len());
+                // `rec val`s are declared before everything else in the block, so that functions
+                // can call the ones defined after them
+                for ast in &asts {
+                    if let ast::Value::Let { name, recursive: true, .. } = &ast.value {
+                        scope.define_local(String::from(name.as_ref()), true);
+                    }
+                }
+
+                let mut recursive_fns = Vec::new();
+                let mut irs = Vec::with_capacity(asts.len() + 1);
                 for ast in asts {
-                    let ir = self.build_ir(scope, ast);
+                    let ir = match ast.value {
+                        ast::Value::Let { name, value, recursive: true, .. } => {
+                            self.build_recursive_let(scope, name, *value, ast.location, &mut recursive_fns)?
+                        }
+                        _ => self.build_ir(scope, ast)?
+                    };
 
                     // TODO: Flatten nested blocks?
                     // TODO: What if we have a block where the last value is a compile-time assignment
@@ -123,6 +148,10 @@ impl Builder {
 
                 scope.pop_block();
 
+                if !recursive_fns.is_empty() {
+                    irs.insert(0, ir::IR { node: ir::Node::DeclareRecursive(recursive_fns), location: location.clone() });
+                }
+
                 if irs.len() == 0 {
                     // This is a weird case - we seem to have a block consisting only of
                     // compile-time assignments, so there is no actual code to be executed at runtime.
@@ -132,9 +161,23 @@ impl Builder {
                 }
             }
 
-            ast::Value::Let { name, value, recursive, comptime } => {
+            ast::Value::List(asts) => {
+                let mut irs = Vec::with_capacity(asts.len());
+                for ast in asts {
+                    irs.push(self.build_ir(scope, ast)?);
+                }
+
+                ir::Node::List(irs)
+            }
+
+            ast::Value::Let { name, value, recursive, comptime, mutable, .. } => {
+                // Blocks declare their recursive vals up front, see `build_recursive_let`
                 if recursive {
-                    todo!("Remove recursive lets")
+                    return Err(
+                        Diagnostic::error("E0107", format!("Cannot define the recursive value `{}` here", name))
+                            .with_primary(location, "this is not directly in a block")
+                            .with_help("recursive values can only be defined in a block, e.g. `{ rec val f = ... }`")
+                    );
                 }
 
                 if comptime {
@@ -142,22 +185,51 @@ impl Builder {
                 }
                 scope.push_block();
 
-                let ir = self.build_ir(scope, *value);
+                let ir = self.build_ir(scope, *value)?;
 
                 scope.pop_block();
                 if comptime {
                     scope.pop_comptime_portal();
                 }
 
-                let local_ref = scope.define_local(String::from(name), comptime);
+                let local_ref = if mutable {
+                    scope.define_var(String::from(name), comptime)
+                } else {
+                    scope.define_local(String::from(name), comptime)
+                };
 
                 ir::Node::LocalSet(local_ref, Box::new(ir))
             }
 
-            ast::Value::NameRef(name) => self.lookup_ir(scope, name.as_ref()).expect("Cannot find name"),
+            ast::Value::Assign { name, value } => {
+                let name_ref = scope.lookup_var(&name)
+                    .map_err(|error| error.to_diagnostic(&name, &location))?;
+
+                // Comptime vars are assigned at compile time, like `@val`
+                let comptime = match name_ref {
+                    NameRef::Local(local_ref) => local_ref.comptime,
+                    NameRef::Capture(capture_ref) => capture_ref.comptime,
+                    NameRef::Global(_) | NameRef::Param(_) => panic!("Only locals and captures can be vars")
+                };
+
+                let value_ir = if comptime {
+                    self.compile_implicit_comptime_ast(scope, *value)?
+                } else {
+                    self.build_ir(scope, *value)?
+                };
+
+                match name_ref {
+                    NameRef::Local(local_ref) => ir::Node::LocalSet(local_ref, Box::new(value_ir)),
+                    NameRef::Capture(capture_ref) => ir::Node::CaptureSet(capture_ref, Box::new(value_ir)),
+                    NameRef::Global(_) | NameRef::Param(_) => panic!("Only locals and captures can be vars")
+                }
+            }
+
+            ast::Value::NameRef(name) => self.lookup_ir(scope, name.as_ref())
+                .map_err(|error| error.to_diagnostic(&name, &location))?,
 
             ast::Value::Function(func) => {
-                let func_ir = self.build_function(scope, func);
+                let func_ir = self.build_function(scope, func)?;
                 let func_ref = ir::FunctionTemplateRef { i: self.functions.len() };
                 let captures = func_ir.captures.clone();
 
@@ -171,6 +243,32 @@ impl Builder {
                 ir::Node::CreateClosure(func_ref, to_capture)
             }
 
+            // `a and b` is `if a { b } else { false }` and `a or b` is `if a { true } else { b }`,
+            // so that `b` only runs when it's needed
+            ast::Value::Call { name, target: Some(target), mut args }
+                if (name.as_ref() == "and" || name.as_ref() == "or") && args.len() == 1 => {
+                let condition_ir = self.build_ir(scope, *target)?;
+                let value_ast = args.pop().expect("Missing right-hand side");
+                let value_location = value_ast.location.clone();
+
+                scope.push_block();
+                let value_ir = self.build_ir(scope, value_ast)?;
+                scope.pop_block();
+
+                let bool_ir = |node| ir::IR { node, location: value_location.clone() };
+                let value_ir = bool_ir(ir::Node::TypeAssert(
+                    Box::new(value_ir),
+                    Box::new(bool_ir(ir::Node::Constant(ir::Value::Type(ir::Type::Bool))))
+                ));
+                let constant_ir = ir::IR { node: ir::Node::Constant(ir::Value::Bool(name.as_ref() == "or")), location: location.clone() };
+
+                if name.as_ref() == "and" {
+                    ir::Node::If(Box::new(condition_ir), Box::new(value_ir), Some(Box::new(constant_ir)))
+                } else {
+                    ir::Node::If(Box::new(condition_ir), Box::new(constant_ir), Some(Box::new(value_ir)))
+                }
+            }
+
             ast::Value::Call { name, target, args } => {
                 let (target_ir, name) = match target {
                     None => {
@@ -178,24 +276,30 @@ impl Builder {
                         // in the locals
                         match self.lookup_ir(scope, name.as_ref()) {
                             Ok(target) => (ir::IR { node: target, location: location.clone() }, "call".into()),
+
+                            // `Struct` and `Interface` can be shadowed like any other name
+                            Err(NameAccessError::NameNotFound) if name.as_ref() == "Struct" => {
+                                return Ok(ir::IR { node: self.build_struct_definition(scope, args)?, location });
+                            }
+                            Err(NameAccessError::NameNotFound) if name.as_ref() == "Interface" => {
+                                return Ok(ir::IR { node: self.build_interface_definition(scope, args)?, location });
+                            }
+
                             Err(NameAccessError::NameNotFound) => {
                                 match self.lookup_ir(scope, "self") {
                                     Ok(target_self) => (ir::IR { node: target_self, location: location.clone() }, name),
-                                    Err(_) => {
-                                        println!("{:?}", name);
-                                        todo!("Compile error - could not find function")
-                                    }
+                                    Err(_) => return Err(NameAccessError::NameNotFound.to_diagnostic(&name, &location))
                                 }
                             },
-                            Err(_) => todo!("Compile error")
+                            Err(error) => return Err(error.to_diagnostic(&name, &location))
                         }
                     }
-                    Some(target) => (self.build_ir(scope, *target), name)
+                    Some(target) => (self.build_ir(scope, *target)?, name)
                 };
 
                 let mut args_ir = Vec::with_capacity(args.len());
                 for arg in args {
-                    let ir = self.build_ir(scope, arg);
+                    let ir = self.build_ir(scope, arg)?;
 
                     args_ir.push(ir);
                 }
@@ -204,44 +308,241 @@ impl Builder {
             }
 
             ast::Value::If { condition, on_true, on_false } => {
-                let condition_ir = self.build_ir(scope, *condition);
+                let condition_ir = self.build_ir(scope, *condition)?;
 
                 scope.push_block();
-                let on_true_ir = self.build_ir(scope, *on_true);
+                let on_true_ir = self.build_ir(scope, *on_true)?;
                 scope.pop_block();
 
                 scope.push_block();
                 let on_false_ir = match on_false {
                     None => None,
-                    Some(on_false) => Some(Box::new(self.build_ir(scope, *on_false)))
+                    Some(on_false) => Some(Box::new(self.build_ir(scope, *on_false)?))
                 };
                 scope.pop_block();
 
                 ir::Node::If(Box::new(condition_ir), Box::new(on_true_ir), on_false_ir)
             }
 
-            ast::Value::FnType { .. } => todo!("Support fn type definitions"),
-            ast::Value::TypeAssert { .. } => todo!("Support type asserts"),
+            ast::Value::While { condition, body } => {
+                let condition_ir = self.build_ir(scope, *condition)?;
+
+                scope.push_loop_block();
+                let body_ir = self.build_ir(scope, *body)?;
+                scope.pop_block();
+
+                ir::Node::While(Box::new(condition_ir), Box::new(body_ir))
+            }
+
+            ast::Value::Break | ast::Value::Continue => {
+                let (keyword, node) = match ast.value {
+                    ast::Value::Break => ("break", ir::Node::Break),
+                    _ => ("continue", ir::Node::Continue)
+                };
+
+                if !scope.is_in_loop() {
+                    return Err(
+                        Diagnostic::error("E0106", format!("Cannot use `{}` outside of a loop", keyword))
+                            .with_primary(location, "this is not in the body of a loop")
+                            .with_help("closures and compile-time code can't exit the loops they are in")
+                    );
+                }
+
+                node
+            }
+
+            ast::Value::FnType { params, return_type } => {
+                let mut param_irs = Vec::with_capacity(params.len());
+                for param in params {
+                    param_irs.push(self.compile_implicit_comptime_ast(scope, param.typ)?);
+                }
+
+                let return_type_ir = self.compile_implicit_comptime_ast(scope, *return_type)?;
+
+                ir::Node::FnType(param_irs, Box::new(return_type_ir))
+            }
+            ast::Value::TypeAssert { value, typ } => {
+                let value_ir = self.build_ir(scope, *value)?;
+                let type_ir = self.compile_implicit_comptime_ast(scope, *typ)?;
+
+                ir::Node::TypeAssert(Box::new(value_ir), Box::new(type_ir))
+            }
+
+            ast::Value::CompileTimeExpr(ast) => self.compile_comptime_ast(scope, *ast)?,
+
+            // The parser recovered here, the parse error is reported by it
+            ast::Value::Error => return Err(
+                Diagnostic::error("E0108", "Cannot build code with parse errors")
+                    .with_primary(location, "this could not be parsed")
+            )
+        };
+
+        Ok(ir::IR { node, location })
+    }
+
+    // The value must be a function. It's evaluated at compile time like `@val`, so it can only
+    // capture comptime values.
+    fn build_recursive_let(
+        &mut self,
+        scope: &mut ScopeStack,
+        name: Box<str>,
+        value: ast::AST,
+        location: ast::Location,
+        recursive_fns: &mut Vec<(ir::LocalRef, ir::FunctionTemplateRef)>
+    ) -> Result<ir::IR, Diagnostic> {
+        let func = match value.value {
+            ast::Value::Function(func) => func,
+            _ => return Err(
+                Diagnostic::error("E0102", format!("Recursive value `{}` is not a function", name))
+                    .with_primary(value.location, "expected a function")
+                    .with_help("only functions can be defined with `rec val`")
+            )
+        };
+
+        // Declared by the block
+        let local_ref = match scope.lookup(&name) {
+            Ok(NameRef::Local(local_ref)) => local_ref,
+            name_ref => panic!("Expected the recursive val to be declared as a local, got {:?}", name_ref)
+        };
+
+        scope.push_comptime_portal();
+        scope.push_block();
 
-            ast::Value::CompileTimeExpr(ast) => self.compile_comptime_ast(scope, *ast)
+        let func_ir = self.build_function(scope, func);
+
+        scope.pop_block();
+        scope.pop_comptime_portal();
+
+        let func_ir = func_ir?;
+        let func_ref = ir::FunctionTemplateRef { i: self.functions.len() };
+        let captures = func_ir.captures.iter().map(|capture| capture.from).collect();
+
+        self.functions.push(func_ir);
+        recursive_fns.push((local_ref, func_ref));
+
+        Ok(ir::IR { node: ir::Node::DefineRecursive(local_ref, captures), location })
+    }
+
+    fn build_struct_definition(&mut self, scope: &mut ScopeStack, args: Vec<ast::AST>) -> Result<ir::Node, Diagnostic> {
+        let mut fields: Vec<(Box<str>, ir::IR)> = Vec::with_capacity(args.len());
+
+        for arg in args {
+            let (name, typ) = match arg.value {
+                ast::Value::TypeAssert { value, typ } => match value.value {
+                    ast::Value::NameRef(name) => (name, typ),
+                    _ => return Err(Self::invalid_struct_field(&value.location))
+                },
+                _ => return Err(Self::invalid_struct_field(&arg.location))
+            };
+
+            if fields.iter().any(|(field_name, _)| *field_name == name) {
+                return Err(
+                    Diagnostic::error("E0201", format!("Field `{}` is defined more than once", name))
+                        .with_primary(arg.location, "redefined here")
+                );
+            }
+
+            let typ = self.compile_implicit_comptime_ast(scope, *typ)?;
+
+            fields.push((name, typ));
+        }
+
+        Ok(ir::Node::DefineStruct(fields))
+    }
+
+    fn invalid_struct_field(location: &ast::Location) -> Diagnostic {
+        Diagnostic::error("E0200", "Invalid struct field")
+            .with_primary(location.clone(), "expected a field definition")
+            .with_help("struct fields are written as `name: Type`")
+    }
+
+    fn build_interface_definition(&mut self, scope: &mut ScopeStack, args: Vec<ast::AST>) -> Result<ir::Node, Diagnostic> {
+        let mut methods: Vec<ir::MethodSignature> = Vec::with_capacity(args.len());
+
+        for arg in args {
+            let (name, params, return_type) = match arg.value {
+                ast::Value::TypeAssert { value, typ } => match (value.value, typ.value) {
+                    (ast::Value::NameRef(name), ast::Value::FnType { params, return_type }) => (name, params, return_type),
+                    _ => return Err(Self::invalid_interface_method(&arg.location))
+                },
+                _ => return Err(Self::invalid_interface_method(&arg.location))
+            };
+
+            if methods.iter().any(|method| method.name == name) {
+                return Err(
+                    Diagnostic::error("E0203", format!("Method `{}` is defined more than once", name))
+                        .with_primary(arg.location, "redefined here")
+                );
+            }
+
+            let mut param_irs = Vec::with_capacity(params.len());
+            for param in params {
+                param_irs.push(self.compile_implicit_comptime_ast(scope, param.typ)?);
+            }
+
+            let returns = self.compile_implicit_comptime_ast(scope, *return_type)?;
+
+            methods.push(ir::MethodSignature { name, params: param_irs, returns });
+        }
+
+        Ok(ir::Node::DefineInterface(methods))
+    }
+
+    fn invalid_interface_method(location: &ast::Location) -> Diagnostic {
+        Diagnostic::error("E0202", "Invalid interface method")
+            .with_primary(location.clone(), "expected a method signature")
+            .with_help("interface methods are written as `name: (param: Type): ReturnType`")
+    }
+
+    fn build_pattern(&mut self, scope: &mut ScopeStack, pattern: ast::Pattern) -> Result<ir::Pattern, Diagnostic> {
+        let node = match pattern.value {
+            ast::PatternValue::SpecificValue(ast) => ir::PatternNode::SpecificValue(self.compile_implicit_comptime_ast(scope, ast)?),
+            ast::PatternValue::Binding(name) => ir::PatternNode::Binding(scope.define_local(String::from(name), true)),
+            ast::PatternValue::Call { target, name, args } => {
+                let constructor_ast = match target {
+                    None => ast::AST { value: ast::Value::NameRef(name), location: pattern.location.clone() },
+                    Some(target) => ast::AST {
+                        value: ast::Value::Call { target: Some(target), name, args: Vec::new() },
+                        location: pattern.location.clone()
+                    }
+                };
+                let constructor_ir = self.compile_implicit_comptime_ast(scope, constructor_ast)?;
+
+                let mut arg_patterns = Vec::with_capacity(args.len());
+                for arg in args {
+                    arg_patterns.push(self.build_pattern(scope, arg)?);
+                }
+
+                ir::PatternNode::Call(constructor_ir, arg_patterns)
+            }
+            ast::PatternValue::FunctionType { params, return_type } => {
+                let mut param_patterns = Vec::with_capacity(params.len());
+                for param in params {
+                    param_patterns.push(self.build_pattern(scope, param.typ)?);
+                }
+
+                let return_type = self.build_pattern(scope, *return_type)?;
+
+                ir::PatternNode::FunctionType(param_patterns, Box::new(return_type))
+            }
         };
 
-        ir::IR { node, location }
+        Ok(ir::Pattern { node, location: pattern.location })
     }
 
-    fn compile_implicit_comptime_ast(&mut self, scope: &mut ScopeStack, ast: ast::AST) -> ir::IR {
+    fn compile_implicit_comptime_ast(&mut self, scope: &mut ScopeStack, ast: ast::AST) -> Result<ir::IR, Diagnostic> {
         let location = ast.location.clone();
-        let node = self.compile_comptime_ast(scope, ast);
+        let node = self.compile_comptime_ast(scope, ast)?;
 
-        ir::IR { node, location }
+        Ok(ir::IR { node, location })
     }
 
-    fn compile_comptime_ast(&mut self, scope: &mut ScopeStack, ast: ast::AST) -> ir::Node {
+    fn compile_comptime_ast(&mut self, scope: &mut ScopeStack, ast: ast::AST) -> Result<ir::Node, Diagnostic> {
         scope.push_comptime_portal();
         let ir = self.build_ir(scope, ast);
         scope.pop_comptime_portal();
 
-        ir::Node::Comptime(Box::new(ir))
+        Ok(ir::Node::Comptime(Box::new(ir?)))
     }
 
     fn lookup_ir(&mut self, scope: &mut ScopeStack, name: &str) -> Result<ir::Node, NameAccessError> {
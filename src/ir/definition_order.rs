@@ -0,0 +1,386 @@
+use crate::ast;
+use crate::ast::Location;
+use crate::diagnostics::Diagnostic;
+
+// Reorders the top-level statements of a module so that every definition comes before the
+// statements that use it. This lets files be organised top-down.
+//
+// Statements which don't define a name keep their relative order, and the last one stays last
+// since it's the result of the module.
+pub fn order_definitions(asts: Vec<ast::AST>) -> Result<Vec<ast::AST>, Diagnostic> {
+    let statements: Vec<Statement> = asts.iter().map(Statement::from_ast).collect();
+    let dependencies = find_dependencies(&statements);
+
+    let mut placed = vec![false; asts.len()];
+    let mut order = Vec::with_capacity(asts.len());
+
+    // Always take the first statement that can be placed, so that the source order is kept
+    // whenever possible
+    while order.len() < asts.len() {
+        let next = (0..asts.len()).find(|&i| {
+            !placed[i] && dependencies[i].iter().all(|dependency| placed[dependency.on])
+        });
+
+        match next {
+            Some(i) => {
+                placed[i] = true;
+                order.push(i);
+            }
+            None => return Err(cycle_error(&statements, &dependencies, &placed))
+        }
+    }
+
+    let mut asts: Vec<Option<ast::AST>> = asts.into_iter().map(Some).collect();
+
+    Ok(order.into_iter().map(|i| asts[i].take().unwrap()).collect())
+}
+
+struct Statement<'a> {
+    // The name defined by a `val`
+    name: Option<&'a str>,
+    recursive: bool,
+    mutable: bool,
+    is_function: bool,
+    location: &'a Location,
+
+    // The free names used by the statement, with the location of their first use
+    references: Vec<(&'a str, &'a Location)>
+}
+
+impl <'a> Statement<'a> {
+    fn from_ast(ast: &'a ast::AST) -> Self {
+        let mut names = FreeNames { bound: Vec::new(), references: Vec::new() };
+
+        match &ast.value {
+            ast::Value::Let { name, value, recursive, mutable, .. } => {
+                if *recursive {
+                    names.bound.push(name);
+                }
+
+                names.visit(value);
+
+                let is_function = matches!(value.value, ast::Value::Function(_));
+
+                Statement { name: Some(name), recursive: *recursive, mutable: *mutable, is_function, location: &ast.location, references: names.references }
+            }
+            _ => {
+                names.visit(ast);
+
+                Statement { name: None, recursive: false, mutable: false, is_function: false, location: &ast.location, references: names.references }
+            }
+        }
+    }
+}
+
+struct Dependency<'a> {
+    on: usize,
+    reason: DependencyReason<'a>
+}
+
+enum DependencyReason<'a> {
+    Reference(&'a str, &'a Location),
+    Redefinition,
+    Sequence
+}
+
+fn find_dependencies<'a>(statements: &[Statement<'a>]) -> Vec<Vec<Dependency<'a>>> {
+    let mut dependencies: Vec<Vec<Dependency>> = statements.iter().map(|_| Vec::new()).collect();
+
+    // A name defined more than once refers to the closest definition above the use, or to the
+    // first one if it's used before all of them
+    let resolve = |name: &str, i: usize| -> Option<usize> {
+        let mut definitions = (0..statements.len()).filter(|&j| statements[j].name == Some(name));
+        let first = definitions.next()?;
+
+        Some((first..i).rev().find(|&j| statements[j].name == Some(name)).unwrap_or(first))
+    };
+
+    let mut uses: Vec<Vec<usize>> = statements.iter().map(|_| Vec::new()).collect();
+
+    for (i, statement) in statements.iter().enumerate() {
+        for &(name, location) in &statement.references {
+            let definition = match resolve(name, i) {
+                None => continue,
+                Some(definition) => definition
+            };
+
+            // Recursive vals are declared before the rest of the block, so they can use
+            // each other freely
+            if statement.recursive && statements[definition].recursive {
+                continue;
+            }
+
+            uses[definition].push(i);
+            dependencies[i].push(Dependency { on: definition, reason: DependencyReason::Reference(name, location) });
+        }
+    }
+
+    // The statements which use a `var` can change it, so they keep their order
+    for (definition, statement) in statements.iter().enumerate() {
+        if statement.mutable {
+            let mut users = uses[definition].clone();
+            users.sort();
+
+            for pair in users.windows(2) {
+                dependencies[pair[1]].push(Dependency { on: pair[0], reason: DependencyReason::Sequence });
+            }
+        }
+    }
+
+    let mut last_expression = None;
+    for (i, statement) in statements.iter().enumerate() {
+        match statement.name {
+            Some(name) => {
+                // Redefining a name must happen after the previous value is no longer needed
+                let previous = (0..i).rev().find(|&j| statements[j].name == Some(name));
+                if let Some(previous) = previous {
+                    dependencies[i].push(Dependency { on: previous, reason: DependencyReason::Redefinition });
+
+                    for &user in &uses[previous] {
+                        if user != i {
+                            dependencies[i].push(Dependency { on: user, reason: DependencyReason::Redefinition });
+                        }
+                    }
+                }
+            }
+            None => {
+                if let Some(last_expression) = last_expression {
+                    dependencies[i].push(Dependency { on: last_expression, reason: DependencyReason::Sequence });
+                }
+
+                last_expression = Some(i);
+            }
+        }
+    }
+
+    if let Some(last) = statements.len().checked_sub(1) {
+        if statements[last].name.is_none() {
+            for i in 0..last {
+                dependencies[last].push(Dependency { on: i, reason: DependencyReason::Sequence });
+            }
+        }
+    }
+
+    dependencies
+}
+
+fn cycle_error(statements: &[Statement], dependencies: &[Vec<Dependency>], placed: &[bool]) -> Diagnostic {
+    // Every statement which is left has a dependency which is left as well, so following them
+    // has to lead back to one of the statements on the path
+    let mut path: Vec<(usize, usize)> = Vec::new();
+    let mut current = (0..statements.len()).find(|&i| !placed[i]).unwrap();
+
+    let start = loop {
+        if let Some(start) = path.iter().position(|&(i, _)| i == current) {
+            break start;
+        }
+
+        let dependency = dependencies[current].iter()
+            .position(|dependency| !placed[dependency.on])
+            .unwrap();
+
+        path.push((current, dependency));
+        current = dependencies[current][dependency].on;
+    };
+
+    let cycle = &path[start..];
+
+    let mut names: Vec<String> = cycle.iter()
+        .filter_map(|&(i, _)| statements[i].name)
+        .map(|name| format!("`{}`", name))
+        .collect();
+    names.push(names[0].clone());
+
+    let mut diagnostic = Diagnostic::error("E0103", format!("Cyclic definitions: {}", names.join(" -> ")));
+
+    for (n, &(i, dependency)) in cycle.iter().enumerate() {
+        let dependency = &dependencies[i][dependency];
+        let statement = &statements[i];
+
+        let (location, message) = match (&dependency.reason, statement.name) {
+            (DependencyReason::Reference(name, location), Some(defined)) =>
+                ((*location).clone(), format!("`{}` uses `{}` here", defined, name)),
+            (DependencyReason::Reference(name, location), None) =>
+                ((*location).clone(), format!("`{}` is used here", name)),
+            (DependencyReason::Redefinition, Some(defined)) =>
+                (statement.location.clone(), format!("`{}` is redefined here", defined)),
+            (DependencyReason::Redefinition, None) => panic!("Only definitions can redefine names"),
+            (DependencyReason::Sequence, _) =>
+                (statement.location.clone(), String::from("this has to stay in order"))
+        };
+
+        diagnostic = if n == 0 {
+            diagnostic.with_primary(location, message)
+        } else {
+            diagnostic.with_secondary(location, message)
+        };
+    }
+
+    if cycle.iter().all(|&(i, _)| statements[i].is_function) {
+        diagnostic = diagnostic.with_help("functions which call each other can be defined with `rec val`");
+    }
+
+    diagnostic
+}
+
+// Collects the names used by an expression which aren't defined inside of it
+struct FreeNames<'a> {
+    bound: Vec<&'a str>,
+    references: Vec<(&'a str, &'a Location)>
+}
+
+impl <'a> FreeNames<'a> {
+    fn visit(&mut self, ast: &'a ast::AST) {
+        match &ast.value {
+            ast::Value::Literal(_) | ast::Value::Break | ast::Value::Continue | ast::Value::Error => {}
+
+            ast::Value::Block(asts) => {
+                let scope = self.bound.len();
+
+                for ast in asts {
+                    if let ast::Value::Let { name, recursive: true, .. } = &ast.value {
+                        self.bound.push(name);
+                    }
+                }
+
+                for ast in asts {
+                    self.visit(ast);
+                }
+
+                self.bound.truncate(scope);
+            }
+
+            ast::Value::List(asts) => {
+                for ast in asts {
+                    self.visit(ast);
+                }
+            }
+
+            ast::Value::Function(func) => {
+                let scope = self.bound.len();
+
+                for param in &func.params {
+                    self.bound.push(&param.name);
+                }
+
+                for param in &func.params {
+                    if let Some(pattern) = &param.typ {
+                        self.visit_pattern(pattern);
+                    }
+                }
+
+                if let Some(return_type) = &func.return_type {
+                    self.visit(return_type);
+                }
+
+                self.visit(&func.body);
+
+                self.bound.truncate(scope);
+            }
+
+            // The field and method names aren't references
+            ast::Value::Call { name, target: None, args } if name.as_ref() == "Struct" || name.as_ref() == "Interface" => {
+                for arg in args {
+                    match &arg.value {
+                        ast::Value::TypeAssert { typ, .. } => self.visit(typ),
+                        _ => self.visit(arg)
+                    }
+                }
+            }
+
+            ast::Value::Call { name, target, args } => {
+                match target {
+                    None => self.reference(name, &ast.location),
+                    Some(target) => self.visit(target)
+                }
+
+                for arg in args {
+                    self.visit(arg);
+                }
+            }
+
+            // The name is visible until the end of the enclosing block
+            ast::Value::Let { name, value, recursive, .. } => {
+                self.visit(value);
+
+                if !*recursive {
+                    self.bound.push(name);
+                }
+            }
+
+            ast::Value::NameRef(name) => self.reference(name, &ast.location),
+
+            ast::Value::Assign { name, value } => {
+                self.reference(name, &ast.location);
+                self.visit(value);
+            }
+
+            ast::Value::If { condition, on_true, on_false } => {
+                self.visit(condition);
+                self.visit_scoped(on_true);
+
+                if let Some(on_false) = on_false {
+                    self.visit_scoped(on_false);
+                }
+            }
+
+            ast::Value::While { condition, body } => {
+                self.visit(condition);
+                self.visit_scoped(body);
+            }
+
+            ast::Value::FnType { params, return_type } => {
+                for param in params {
+                    self.visit(&param.typ);
+                }
+
+                self.visit(return_type);
+            }
+
+            ast::Value::TypeAssert { value, typ } => {
+                self.visit(value);
+                self.visit(typ);
+            }
+
+            ast::Value::CompileTimeExpr(ast) => self.visit(ast)
+        }
+    }
+
+    fn visit_scoped(&mut self, ast: &'a ast::AST) {
+        let scope = self.bound.len();
+        self.visit(ast);
+        self.bound.truncate(scope);
+    }
+
+    fn visit_pattern(&mut self, pattern: &'a ast::Pattern) {
+        match &pattern.value {
+            ast::PatternValue::SpecificValue(ast) => self.visit(ast),
+            ast::PatternValue::Binding(name) => self.bound.push(name),
+            ast::PatternValue::Call { target, name, args } => {
+                match target {
+                    None => self.reference(name, &pattern.location),
+                    Some(target) => self.visit(target)
+                }
+
+                for arg in args {
+                    self.visit_pattern(arg);
+                }
+            }
+            ast::PatternValue::FunctionType { params, return_type } => {
+                for param in params {
+                    self.visit_pattern(&param.typ);
+                }
+
+                self.visit_pattern(return_type);
+            }
+        }
+    }
+
+    fn reference(&mut self, name: &'a str, location: &'a Location) {
+        if self.bound.contains(&name) || self.references.iter().any(|(other, _)| *other == name) {
+            return;
+        }
+
+        self.references.push((name, location));
+    }
+}
@@ -1,7 +1,8 @@
-use crate::ir::{Type, Value};
+use crate::ir::{Type, TypeConstructor, Value};
 
 pub struct Globals {
-    pub globals: Vec<Global>
+    // Added with `define`, which checks the runtime ones
+    pub(super) globals: Vec<Global>
 }
 
 #[derive(Debug, Clone)]
@@ -23,7 +24,20 @@ impl Globals {
                 Global { name: String::from("Bool"), value: Value::Type(Type::Bool), comptime: true },
                 Global { name: String::from("Int"), value: Value::Type(Type::Int), comptime: true },
                 Global { name: String::from("Float"), value: Value::Type(Type::Float), comptime: true },
+
+                Global { name: String::from("List"), value: Value::Type(Type::Constructor(TypeConstructor::List)), comptime: true },
             ]
         }
     }
+
+    // The compiled code stores the runtime globals as constants, so they can only hold the
+    // values which are constants there. The others have to be comptime.
+    pub fn define(&mut self, name: &str, value: Value, comptime: bool) {
+        assert!(
+            comptime || matches!(value, Value::None | Value::Bool(_) | Value::Int(_) | Value::Float(_)),
+            "The runtime global `{}` must be a None, Bool, Int or Float, got {:?}", name, value
+        );
+
+        self.globals.push(Global { name: String::from(name), value, comptime });
+    }
 }
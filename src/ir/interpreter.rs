@@ -1,10 +1,38 @@
-use crate::{ir, lir};
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::{ast, ir, lir};
+use crate::diagnostics::Diagnostic;
 use crate::ir::{Globals, Type, Value};
 use crate::vec_map::VecMap;
 
 pub struct Interpreter<'a> {
     globals: &'a Globals,
-    functions: Vec<lir::Function>
+    templates: &'a [ir::Function],
+    functions: Vec<lir::Function>,
+    types: ir::Types,
+    vtables: Vec<lir::VTable>,
+    specializations: Vec<Specialization>,
+
+    // The runtime globals which are used, they're added the first time they're referenced
+    runtime_globals: Vec<lir::Global>,
+    runtime_global_map: VecMap<ir::GlobalRef, lir::GlobalRef>,
+
+    // The functions which are being specialized and don't declare a return type. They can't be
+    // called recursively since their return type depends on their body, until it's assumed from
+    // the other branch of an `if`. The location is of that branch.
+    inferring_return_types: Vec<(lir::FunctionRef, Option<ast::Location>)>,
+
+    // The function whose recursive call caused the last E0301
+    recursive_call: Option<lir::FunctionRef>
+}
+
+// A closure specialized for the types of its parameters and the values of its comptime parameters,
+// so that calls with the same ones can reuse it
+struct Specialization {
+    closure: Type,
+    param_types: Vec<Type>,
+    comptime_args: Vec<Value>,
+    func_ref: lir::FunctionRef
 }
 
 struct ComptimeStackFrame {
@@ -15,35 +43,64 @@ struct ComptimeStackFrame {
 
     capture_types: Vec<Type>,
     capture_values: VecMap<ir::CaptureRef, Value>,
+    runtime_capture_map: VecMap<ir::CaptureRef, lir::CaptureRef>,
 
+    locals: Vec<ir::Local>,
     local_types: VecMap<ir::LocalRef, StackFrameType>,
     local_values: VecMap<ir::LocalRef, Value>,
     runtime_local_map: VecMap<ir::LocalRef, lir::LocalRef>,
     runtime_local_count: usize,
 
+    // Set while specializing code which may not run, e.g. the branches of a runtime `if`.
+    // Comptime vars can't be assigned there.
+    in_runtime_branch: bool,
+
+    // Set by a `break` or `continue` evaluated at compile time, until the loop handles it
+    loop_exit: Option<LoopExit>,
+
     return_type: Option<Type>
 }
 
+enum LoopExit {
+    Break,
+    Continue
+}
+
 struct StackFrameType {
     typ: Type,
     comptime: bool
 }
 
 impl <'a> Interpreter<'a> {
-    pub fn eval_comptime(globals: &'a Globals, module: ir::Module) -> lir::Module {
+    pub fn eval_comptime(globals: &'a Globals, module: &'a ir::Module) -> Result<lir::Module, Diagnostic> {
         let mut interpreter = Self {
             globals,
-            functions: Vec::new()
+            templates: &module.functions,
+            functions: Vec::new(),
+            types: ir::Types::new(),
+            vtables: Vec::new(),
+            specializations: Vec::new(),
+            runtime_globals: Vec::new(),
+            runtime_global_map: VecMap::new(),
+            inferring_return_types: Vec::new(),
+            recursive_call: None
         };
         let main = interpreter.specialize_function(
-            module.main,
+            &module.main,
+            None,
             Vec::new(),
             VecMap::new(),
             Vec::new(),
             VecMap::new()
-        );
-
-        lir::Module { main, functions: interpreter.functions }
+        )?;
+
+        Ok(lir::Module {
+            main,
+            functions: interpreter.functions,
+            globals: interpreter.runtime_globals,
+            types: interpreter.types,
+            vtables: interpreter.vtables
+        })
     }
 
     fn specialize_function(
@@ -51,14 +108,18 @@ impl <'a> Interpreter<'a> {
 
         // TODO: Pass a closure here instead of raw FunctionTemplate, we need the types for the
         //       specialization
-        func: ir::Function,
+        func: &ir::Function,
+
+        // The function reserved for the specialization, its signature is set before the body is
+        // specialized so that recursive calls can use it
+        func_ref: Option<lir::FunctionRef>,
 
         param_types: Vec<Type>,
         comptime_param_values: VecMap<ir::ParamRef, Value>,
 
         capture_types: Vec<Type>,
         comptime_capture_values: VecMap<ir::CaptureRef, Value>
-    ) -> lir::Function {
+    ) -> Result<lir::Function, Diagnostic> {
         // TODO: If the function body resolves to a constant - return that constant directly
         //       instead of wrapping in a function?
 
@@ -86,6 +147,18 @@ impl <'a> Interpreter<'a> {
             }
         }
 
+        let mut runtime_capture_map = VecMap::with_capacity(func.captures.len());
+        let mut lir_i = 0;
+        for (ir_i, capture) in func.captures.iter().enumerate() {
+            if !capture.comptime {
+                runtime_capture_map.insert_push(
+                    ir::CaptureRef { i: ir_i, comptime: capture.comptime },
+                    lir::CaptureRef { i: lir_i }
+                );
+                lir_i += 1;
+            }
+        }
+
         let runtime_local_count = runtime_local_map.len();
 
         let mut stack_frame = ComptimeStackFrame {
@@ -95,45 +168,43 @@ impl <'a> Interpreter<'a> {
 
             capture_types,
             capture_values: comptime_capture_values,
+            runtime_capture_map,
 
+            locals: func.locals.clone(),
             local_types: VecMap::new(),
             local_values: VecMap::new(),
             runtime_local_map,
             runtime_local_count,
 
+            in_runtime_branch: false,
+            loop_exit: None,
+
             return_type: None
         };
 
-        // TODO: Eval & type-check arguments
+        let mut body = lir::BasicBlock { code: Vec::new() };
 
-        let return_type = match &func.return_type {
-            None => None,
-            Some(ir) => {
-                todo!("Support comptime eval");
-
-                Some(Type::None)
-                // let (value, _) = self.eval_ir(&mut stack_frame, ir, true);
-                // let value = self.assert_const_value(value);
-                //
-                // Some(match value {
-                //     Value::Type(typ) => typ,
-                //     value => panic!("The return value expression must evaluate to a type, got {:?}", value)
-                // })
-            }
-        };
+        for (i, param) in func.params.iter().enumerate() {
+            if let Some(pattern) = &param.typ {
+                let arg_type = stack_frame.param_types[i];
+                let param_type = self.match_pattern(&mut stack_frame, pattern, arg_type)?;
 
-        let mut body = lir::BasicBlock { code: Vec::new() };
-        let (return_ref, body_typ) = self.specialize_ir(&mut stack_frame, &mut body, &func.body, false);
+                if param.comptime {
+                    let param_ref = ir::ParamRef { i, comptime: true };
+                    let value = stack_frame.param_values.get(&param_ref).expect("Missing comptime param value").clone();
+                    let value = self.coerce_value(value, param_type, &pattern.location)?;
+
+                    stack_frame.param_values.insert(param_ref, value);
+                }
 
-        let return_type = match return_type {
-            None => body_typ,
-            Some(typ) => {
-                todo!("Compare return_type with body_type")
+                stack_frame.param_types[i] = param_type;
             }
-        };
+        }
 
-        // TODO: Better void type
-        body.code.push(lir::Instruction::Return(return_ref));
+        let return_type = match &func.return_type {
+            None => None,
+            Some(type_ir) => Some((self.eval_type(&mut stack_frame, type_ir)?, &type_ir.location))
+        };
 
         let mut runtime_param_types = Vec::new();
         for (i, param) in func.params.iter().enumerate() {
@@ -149,55 +220,96 @@ impl <'a> Interpreter<'a> {
             }
         }
 
-        lir::Function {
+        if let Some(func_ref) = func_ref {
+            let declared = &mut self.functions[func_ref.i];
+            declared.param_types = runtime_param_types.clone();
+            declared.capture_types = runtime_capture_types.clone();
+
+            match return_type {
+                None => self.inferring_return_types.push((func_ref, None)),
+                Some((typ, _)) => declared.return_type = typ
+            }
+        }
+
+        let (return_ref, body_typ) = self.specialize_ir(&mut stack_frame, &mut body, &func.body)?;
+
+        if let Some(func_ref) = func_ref {
+            let inferring = self.inferring_return_types.iter().position(|(inferring_ref, _)| *inferring_ref == func_ref);
+
+            // The recursive calls were specialized with the assumed type, so it must be the actual one
+            if let Some((_, Some(assumed_location))) = inferring.map(|i| self.inferring_return_types.remove(i)) {
+                let assumed_type = self.functions[func_ref.i].return_type;
+
+                if body_typ != assumed_type {
+                    return Err(
+                        self.mismatched_types(&func.body.location, assumed_type, body_typ)
+                            .with_secondary(assumed_location, "the recursive calls assume the type of this branch")
+                    );
+                }
+            }
+        }
+
+        let (return_ref, return_type) = match return_type {
+            None => (return_ref, body_typ),
+            Some((typ, type_location)) => {
+                let return_ref = self.coerce(&mut stack_frame, &mut body, return_ref, body_typ, typ, &func.body.location)
+                    .map_err(|diagnostic| diagnostic.with_secondary(type_location.clone(), "expected because of this return type"))?;
+
+                (return_ref, typ)
+            }
+        };
+
+        // TODO: Better void type
+        body.code.push(lir::Instruction::Return(return_ref));
+
+        // The boxed vars are only set once, their cells are changed instead
+        let mut mutable_locals = Vec::new();
+        for (i, local) in func.locals.iter().enumerate() {
+            if local.mutable && !local.boxed && !local.comptime {
+                let local_ref = ir::LocalRef { i, comptime: false };
+
+                mutable_locals.push(*stack_frame.runtime_local_map.get(&local_ref).expect("Missing local map"));
+            }
+        }
+
+        Ok(lir::Function {
             capture_types: runtime_capture_types,
             param_types: runtime_param_types,
             return_type,
             local_count: stack_frame.runtime_local_count,
+            mutable_locals,
             body
-        }
+        })
     }
 
     fn specialize_ir(
         &mut self,
         frame: &mut ComptimeStackFrame,
         block: &mut lir::BasicBlock,
-        ir: &ir::IR,
-        comptime: bool
-    ) -> (lir::ValueRef, Type) {
-        let location = ir.location.clone();
-
-        match &ir.node {
+        ir: &ir::IR
+    ) -> Result<(lir::ValueRef, Type), Diagnostic> {
+        let result = match &ir.node {
             ir::Node::Nop => (lir::ValueRef::None, Type::None),
-            ir::Node::Constant(value) => {
-                let value_ref = Self::value_to_lir(value);
-
-                (value_ref, value.type_of())
-            }
+            ir::Node::Constant(value) => self.export_value(frame, block, value),
             ir::Node::GlobalRef(global_ref) => {
-                let value_ref = if global_ref.comptime {
-                    todo!("Resolve global value as constant");
-                    todo!("Support specializing Any types");
-                } else {
-                    if comptime {
-                        todo!("panic")
-                    }
+                if global_ref.comptime {
+                    let value = self.globals.globals[global_ref.i].value.clone();
 
-                    // ir::Node::GlobalRef(*global_ref)
-                    todo!("Support global refs")
-                };
+                    self.export_value(frame, block, &value)
+                } else {
+                    let (lir_global_ref, typ) = self.runtime_global(*global_ref);
 
-                todo!()
+                    (lir::ValueRef::Global(lir_global_ref), typ)
+                }
             }
             ir::Node::ParamRef(param_ref) => {
                 if param_ref.comptime {
-                    todo!("Resolve param value as constant");
-                    todo!("Support specializing Any types");
-                } else {
-                    if comptime {
-                        todo!("panic")
-                    }
+                    let value = frame.param_values.get(param_ref)
+                        .expect("Missing comptime param value")
+                        .clone();
 
+                    self.export_value(frame, block, &value)
+                } else {
                     let lir_param_ref = *frame.runtime_param_map.get(param_ref).expect("Missing param map");
                     let value_ref = lir::ValueRef::Param(lir_param_ref);
                     let typ = frame.param_types[param_ref.i];
@@ -210,333 +322,1708 @@ impl <'a> Interpreter<'a> {
                     .expect("Used local before assignment");
 
                 if frame_type.comptime {
-                    if frame_type.typ == Type::Any {
-                        todo!("Support specializing Any types");
-                    }
-
                     let value = frame.local_values.get(local_ref)
-                        .expect("Used local before assignment");
+                        .expect("Used local before assignment")
+                        .clone();
 
-                    (Self::value_to_lir(value), value.type_of())
+                    self.export_value(frame, block, &value)
                 } else {
-                    if comptime {
-                        todo!("panic")
-                    }
-
                     let lir_local_ref = *frame.runtime_local_map.get(local_ref).expect("Missing local map");
-                    let value_ref = lir::ValueRef::Local(lir_local_ref);
-                    let typ = frame.local_types.get(local_ref).expect("Used param before definition").typ;
+                    let typ = frame_type.typ;
 
-                    (value_ref, typ)
+                    self.var_value(frame, block, lir::ValueRef::Local(lir_local_ref), typ)
                 }
             }
-            ir::Node::CaptureRef(_) => todo!("Support specializing captures"),
-            ir::Node::LocalSet(local_ref, value_ir) => {
-                let (value_ref, typ) = self.specialize_ir(frame, block, value_ir, local_ref.comptime);
+            ir::Node::CaptureRef(capture_ref) => {
+                if capture_ref.comptime {
+                    let value = frame.capture_values.get(capture_ref)
+                        .expect("Missing comptime capture value")
+                        .clone();
+
+                    self.export_value(frame, block, &value)
+                } else {
+                    let lir_capture_ref = *frame.runtime_capture_map.get(capture_ref).expect("Missing capture map");
+                    let typ = frame.capture_types[capture_ref.i];
 
+                    self.var_value(frame, block, lir::ValueRef::Capture(lir_capture_ref), typ)
+                }
+            }
+            ir::Node::LocalSet(local_ref, value_ir) => {
                 if local_ref.comptime {
-                    let value = Self::assert_const(value_ref);
+                    let is_assignment = frame.local_values.get(local_ref).is_some();
+                    if is_assignment && frame.in_runtime_branch {
+                        return Err(
+                            Diagnostic::error("E0302", "Cannot assign to a compile-time variable in runtime code")
+                                .with_primary(ir.location.clone(), "this only runs depending on a runtime condition")
+                                .with_help("compile-time variables can only be assigned where it's known that the code runs")
+                        );
+                    }
+
+                    self.eval_ir(frame, ir)?;
+
+                    return Ok((lir::ValueRef::None, Type::None));
+                }
 
-                    if typ == Type::Any {
-                        todo!("Support specializing Any types");
+                let (value_ref, typ) = self.specialize_ir(frame, block, value_ir)?;
+                let lir_local_ref = *frame.runtime_local_map.get(local_ref).expect("Missing local map");
+
+                match frame.local_types.get(local_ref).map(|frame_type| frame_type.typ) {
+                    // Assigning to a var, the value needs to have the type it was defined with
+                    Some(cell_type @ Type::Cell(cell_ref)) => {
+                        let element_type = self.types.cell_type(cell_ref).element;
+                        let value_ref = self.coerce(frame, block, value_ref, typ, element_type, &value_ir.location)?;
+
+                        block.code.push(lir::Instruction::CellSet(cell_type, lir::ValueRef::Local(lir_local_ref), value_ref));
                     }
+                    Some(var_type) => {
+                        let value_ref = self.coerce(frame, block, value_ref, typ, var_type, &value_ir.location)?;
 
-                    frame.local_values.insert(*local_ref, value);
-                } else {
-                    if comptime {
-                        todo!("panic")
+                        block.code.push(lir::Instruction::LocalSet(lir_local_ref, value_ref, var_type));
                     }
 
-                    let lir_local_ref = *frame.runtime_local_map.get(local_ref).expect("Missing local map");
-                    let instruction = lir::Instruction::LocalSet(lir_local_ref, value_ref, typ);
+                    None if frame.locals[local_ref.i].boxed => {
+                        let cell_type = self.types.cell_of(typ);
 
-                    block.code.push(instruction);
+                        block.code.push(lir::Instruction::CreateCell(lir_local_ref, cell_type, value_ref));
+
+                        frame.local_types.insert(*local_ref, StackFrameType { typ: cell_type, comptime: false });
+                    }
+                    None => {
+                        block.code.push(lir::Instruction::LocalSet(lir_local_ref, value_ref, typ));
+
+                        frame.local_types.insert(*local_ref, StackFrameType { typ, comptime: false });
+                    }
                 }
 
-                frame.local_types.insert(*local_ref, StackFrameType {
-                    typ,
-                    comptime: local_ref.comptime
-                });
+                (lir::ValueRef::None, Type::None)
+            }
+            ir::Node::CaptureSet(capture_ref, value_ir) => {
+                let (value_ref, typ) = self.specialize_ir(frame, block, value_ir)?;
+                let lir_capture_ref = *frame.runtime_capture_map.get(capture_ref).expect("Missing capture map");
+                let cell_type = frame.capture_types[capture_ref.i];
+                let element_type = self.cell_element_type(cell_type);
+
+                let value_ref = self.coerce(frame, block, value_ref, typ, element_type, &value_ir.location)?;
+
+                block.code.push(lir::Instruction::CellSet(cell_type, lir::ValueRef::Capture(lir_capture_ref), value_ref));
 
                 (lir::ValueRef::None, Type::None)
             }
             ir::Node::Block(irs) => {
                 let mut result = (lir::ValueRef::None, Type::None);
                 for ir in irs {
-                    result = self.specialize_ir(frame, block, ir, comptime);
+                    result = self.specialize_ir(frame, block, ir)?;
                 }
 
                 result
             }
-            ir::Node::Comptime(_) => todo!("Support specializing and calling comptime blocks"),
+            ir::Node::List(irs) => {
+                let mut element_refs = Vec::with_capacity(irs.len());
+                let mut element_types = Vec::with_capacity(irs.len());
+                for ir in irs {
+                    let (value_ref, value_type) = self.specialize_ir(frame, block, ir)?;
+
+                    element_refs.push(value_ref);
+                    element_types.push(value_type);
+                }
+
+                let element_type = self.list_element_type(&element_types, irs, &ir.location)?;
+                let list_type = self.types.list_of(element_type);
+                let result_local_ref = Self::new_temp_local(frame);
+
+                block.code.push(lir::Instruction::CreateList(result_local_ref, list_type, element_refs));
+
+                (lir::ValueRef::Local(result_local_ref), list_type)
+            }
+            ir::Node::DefineStruct(_) | ir::Node::DefineInterface(_) | ir::Node::FnType(_, _) => {
+                let typ = self.eval_ir(frame, ir)?.assert_type();
+
+                (lir::ValueRef::Type(typ), Type::Type)
+            }
+            ir::Node::Comptime(ir) => {
+                let value = self.eval_ir(frame, ir)?;
+
+                self.export_value(frame, block, &value)
+            }
             ir::Node::Call(name, target, args) => {
-                if comptime {
-                    todo!("Call functions")
+                let (target_ref, target_type) = self.specialize_ir(frame, block, target)?;
+
+                if let (lir::ValueRef::Type(typ @ Type::Struct(_)), "call") = (target_ref, name.as_ref()) {
+                    return self.specialize_create_struct(frame, block, typ, args, &ir.location);
+                }
+
+                // Types are always created at compile time
+                if let (lir::ValueRef::Type(Type::Constructor(constructor)), "call") = (target_ref, name.as_ref()) {
+                    let mut arg_values = Vec::with_capacity(args.len());
+                    for arg in args {
+                        arg_values.push(self.eval_ir(frame, arg)?);
+                    }
+
+                    let typ = self.apply_constructor(constructor, &arg_values, args, &ir.location)?;
+
+                    return Ok((lir::ValueRef::Type(typ), Type::Type));
                 }
 
-                let (target_ref, target_type) = self.specialize_ir(frame, block, target, comptime);
+                let resolved_fn = match self.resolve_fn(target_type, name, args.len()) {
+                    Some(resolved_fn) => resolved_fn,
+                    None => return Err(self.unknown_method(&ir.location, target_type, name))
+                };
+
+                // The arguments for comptime parameters are evaluated at compile time and are not
+                // passed at runtime
+                let templates = self.templates;
+                let comptime_params: Vec<bool> = match resolved_fn {
+                    ResolvedFn::TFunction(template_ref) => templates[template_ref.i].params.iter()
+                        .map(|param| param.comptime)
+                        .collect(),
+                    _ => Vec::new()
+                };
 
                 let mut arg_types = Vec::with_capacity(args.len() + 1);
                 let mut arg_refs = Vec::with_capacity(args.len() + 1);
+                let mut runtime_args = Vec::with_capacity(args.len());
 
                 // TODO: Remove this clone
                 arg_types.push(target_type.clone());
                 arg_refs.push(target_ref);
 
-                for arg in args {
-                    let (value_ref, value_type) = self.specialize_ir(frame, block, arg, comptime);
+                let mut param_types = Vec::with_capacity(args.len());
+                let mut comptime_args = Vec::new();
+
+                for (i, arg) in args.iter().enumerate() {
+                    if comptime_params.get(i).copied().unwrap_or(false) {
+                        let value = self.eval_ir(frame, arg)?;
+
+                        param_types.push(value.type_of());
+                        comptime_args.push(value);
+                    } else {
+                        let (value_ref, value_type) = self.specialize_ir(frame, block, arg)?;
 
-                    arg_refs.push(value_ref);
-                    arg_types.push(value_type);
+                        arg_refs.push(value_ref);
+                        arg_types.push(value_type);
+                        runtime_args.push(arg);
+                        param_types.push(value_type);
+                    }
                 }
 
-                let resolved_fn = match (target_type, name.as_ref()) {
-                    (Type::Any, _) => panic!("Target type cannot be Any"),
-                    (Type::None, _) => todo!("Support calling functions on None"),
-                    (Type::Bool, _) => todo!("Support calling functions on bools"),
-                    (Type::Int, "+") => ResolvedFn::Intrinsic(ir::IntrinsicFn::AddInt),
-                    (Type::Float, _) => todo!("Support calling functions on floats"),
-                    (Type::Type, _) => todo!("Support calling functions on types"),
-                    (Type::Closure(_), _) => todo!("Support calling functions on closures"),
-                    (typ, name) => panic!("Cannot find function {} on {:?}", name, typ)
-                };
+                let resolved_fn = match resolved_fn {
+                    // Functions are specialized for the argument types at each call
+                    ResolvedFn::TFunction(_) => {
+                        let func_ref = self.specialize_call(target_type, param_types, comptime_args, &ir.location)?;
+
+                        if self.inferring_return_types.iter().any(|(inferring_ref, assumed)| *inferring_ref == func_ref && assumed.is_none()) {
+                            self.recursive_call = Some(func_ref);
+
+                            return Err(
+                                Diagnostic::error("E0301", "Cannot infer the return type of a recursive function")
+                                    .with_primary(ir.location.clone(), "called recursively here")
+                                    .with_note("it's only inferred from a branch of an `if` which doesn't call the function")
+                                    .with_help("declare the return type of the function, e.g. `(n: Int): Int { ... }`")
+                            );
+                        }
 
-                let signature = match &resolved_fn {
-                    ResolvedFn::Intrinsic(intrinsic) => intrinsic.signature(&arg_types),
-                    ResolvedFn::TFunction(_) => todo!("Support getting signature of TFunctions"),
-                    ResolvedFn::RFunction(_) => todo!("Support getting signature of RFunctions")
+                        ResolvedFn::RFunction(func_ref)
+                    }
+                    resolved_fn => resolved_fn
                 };
 
+                let signature = self.signature_of(resolved_fn, &arg_types);
+
+                if signature.params.len() != arg_refs.len() {
+                    return Err(Self::wrong_arg_count(&ir.location, signature.params.len() - 1, arg_refs.len() - 1));
+                }
+
+                for i in 1..arg_refs.len() {
+                    arg_refs[i] = self.coerce(frame, block, arg_refs[i], arg_types[i], signature.params[i], &runtime_args[i - 1].location)?;
+                }
+
                 let result_local_ref = Self::new_temp_local(frame);
 
                 let instruction = match resolved_fn {
                     ResolvedFn::Intrinsic(intrinsic) => lir::Instruction::CallIntrinsic(result_local_ref, intrinsic, arg_refs),
-                    ResolvedFn::TFunction(_) => todo!("Support calling TFunctions"),
-                    ResolvedFn::RFunction(_) => todo!("Support calling RFunctions")
+                    ResolvedFn::Method(interface, i) => lir::Instruction::CallMethod(result_local_ref, interface, i, arg_refs),
+                    ResolvedFn::FunctionValue(function_type) => lir::Instruction::CallFunctionValue(result_local_ref, function_type, arg_refs),
+
+                    ResolvedFn::RFunction(func_ref) => {
+                        let mut call_arg_refs = arg_refs[1..].to_vec();
+                        if !self.functions[func_ref.i].capture_types.is_empty() {
+                            call_arg_refs.push(arg_refs[0]);
+                        }
+
+                        lir::Instruction::Call(result_local_ref, func_ref, call_arg_refs)
+                    }
+                    ResolvedFn::TFunction(_) => panic!("Functions must be specialized before they are called")
                 };
 
                 block.code.push(instruction);
 
                 (lir::ValueRef::Local(result_local_ref), signature.returns)
             }
-            ir::Node::CreateClosure(_, _) => todo!("Support specializing closures"),
-            ir::Node::If(_, _, _) => todo!("Support specializing ifs")
+            ir::Node::CreateClosure(template_ref, captures) => {
+                let mut capture_types = Vec::with_capacity(captures.len());
+                let mut comptime_captures = Vec::with_capacity(captures.len());
+                let mut capture_refs = Vec::with_capacity(captures.len());
+                for capture_from in captures {
+                    if capture_from.comptime() {
+                        let value = Self::captured_value(frame, capture_from);
+
+                        capture_types.push(value.type_of());
+                        comptime_captures.push(Some(value));
+                    } else {
+                        let (value_ref, typ) = Self::captured_runtime_ref(frame, capture_from);
+
+                        capture_types.push(typ);
+                        comptime_captures.push(None);
+                        capture_refs.push(value_ref);
+                    }
+                }
+
+                // The function is specialized when we know its parameter types, see `coerce` and
+                // `specialize_call`
+                let typ = self.types.closure_of(ir::ClosureType { template: *template_ref, capture_types, comptime_captures });
+                let result_local_ref = Self::new_temp_local(frame);
+
+                block.code.push(lir::Instruction::CreateClosure(result_local_ref, typ, capture_refs));
+
+                (lir::ValueRef::Local(result_local_ref), typ)
+            }
+            ir::Node::TypeAssert(value_ir, type_ir) => {
+                let (value_ref, value_type) = self.specialize_ir(frame, block, value_ir)?;
+                let typ = self.eval_type(frame, type_ir)?;
+
+                (self.coerce(frame, block, value_ref, value_type, typ, &value_ir.location)?, typ)
+            }
+            ir::Node::DeclareRecursive(_) | ir::Node::DefineRecursive(_, _) => {
+                self.eval_ir(frame, ir)?;
+
+                (lir::ValueRef::None, Type::None)
+            }
+            ir::Node::If(cond_ir, then_ir, else_ir) => {
+                let (cond_ref, cond_type) = self.specialize_ir(frame, block, cond_ir)?;
+
+                if cond_type != Type::Bool {
+                    return Err(self.mismatched_types(&cond_ir.location, Type::Bool, cond_type));
+                }
+
+                // Only the branch which will run is compiled if the condition is known at compile time
+                if let lir::ValueRef::Bool(condition) = cond_ref {
+                    return match (condition, else_ir) {
+                        (true, Some(_)) => self.specialize_ir(frame, block, then_ir),
+                        (false, Some(else_ir)) => self.specialize_ir(frame, block, else_ir),
+                        (true, None) => {
+                            self.specialize_ir(frame, block, then_ir)?;
+
+                            Ok((lir::ValueRef::None, Type::None))
+                        }
+                        (false, None) => Ok((lir::ValueRef::None, Type::None))
+                    };
+                }
+
+                let result_local_ref = Self::new_temp_local(frame);
+
+                let in_runtime_branch = frame.in_runtime_branch;
+                frame.in_runtime_branch = true;
+
+                let mut then_block = lir::BasicBlock { code: Vec::new() };
+                let then_result = self.specialize_ir(frame, &mut then_block, then_ir);
+
+                let mut else_block = lir::BasicBlock { code: Vec::new() };
+                let (then_ref, else_ref, typ) = match else_ir {
+                    // Without an `else` the value of the `if` is None
+                    None => {
+                        then_result?;
+
+                        (lir::ValueRef::None, lir::ValueRef::None, Type::None)
+                    }
+                    Some(else_ir) => {
+                        // A branch with a recursive call is specialized again once the return
+                        // type is assumed from the other one
+                        let ((then_ref, then_type), (else_ref, else_type)) = match then_result {
+                            Ok(then_result) => match self.specialize_ir(frame, &mut else_block, else_ir) {
+                                Ok(else_result) => (then_result, else_result),
+                                Err(diagnostic) => {
+                                    let func_ref = self.recursion_to_assume(diagnostic)?;
+                                    self.set_assumed_return_type(func_ref, then_result.1, &then_ir.location);
+
+                                    else_block.code.clear();
+                                    (then_result, self.specialize_ir(frame, &mut else_block, else_ir)?)
+                                }
+                            },
+                            Err(diagnostic) => {
+                                let func_ref = self.recursion_to_assume(diagnostic)?;
+                                let else_result = self.specialize_ir(frame, &mut else_block, else_ir)?;
+                                self.set_assumed_return_type(func_ref, else_result.1, &else_ir.location);
+
+                                then_block.code.clear();
+                                (self.specialize_ir(frame, &mut then_block, then_ir)?, else_result)
+                            }
+                        };
+
+                        if else_type != then_type {
+                            return Err(
+                                self.mismatched_types(&else_ir.location, then_type, else_type)
+                                    .with_secondary(then_ir.location.clone(), "expected because of this branch")
+                            );
+                        }
+
+                        (then_ref, else_ref, then_type)
+                    }
+                };
+
+                frame.in_runtime_branch = in_runtime_branch;
+
+                then_block.code.push(lir::Instruction::LocalSet(result_local_ref, then_ref, typ));
+                else_block.code.push(lir::Instruction::LocalSet(result_local_ref, else_ref, typ));
+
+                block.code.push(lir::Instruction::If(result_local_ref, cond_ref, then_block, else_block, typ));
+
+                (lir::ValueRef::Local(result_local_ref), typ)
+            }
+            ir::Node::While(cond_ir, body_ir) => {
+                let in_runtime_branch = frame.in_runtime_branch;
+                frame.in_runtime_branch = true;
+
+                // The condition is checked at the start of the loop, so `continue` checks it again
+                let mut loop_block = lir::BasicBlock { code: Vec::new() };
+                let (cond_ref, cond_type) = self.specialize_ir(frame, &mut loop_block, cond_ir)?;
+
+                if cond_type != Type::Bool {
+                    return Err(self.mismatched_types(&cond_ir.location, Type::Bool, cond_type));
+                }
+
+                match cond_ref {
+                    // The body doesn't run at all, only the condition does once
+                    lir::ValueRef::Bool(false) => {
+                        frame.in_runtime_branch = in_runtime_branch;
+                        block.code.append(&mut loop_block.code);
+
+                        return Ok((lir::ValueRef::None, Type::None));
+                    }
+
+                    // The loop only ends with a `break`
+                    lir::ValueRef::Bool(true) => {}
+
+                    // `if cond {} else { break }`
+                    _ => {
+                        let result_local_ref = Self::new_temp_local(frame);
+                        let then_block = lir::BasicBlock {
+                            code: vec![lir::Instruction::LocalSet(result_local_ref, lir::ValueRef::None, Type::None)]
+                        };
+                        let else_block = lir::BasicBlock {
+                            code: vec![
+                                lir::Instruction::Break,
+                                lir::Instruction::LocalSet(result_local_ref, lir::ValueRef::None, Type::None)
+                            ]
+                        };
+
+                        loop_block.code.push(lir::Instruction::If(result_local_ref, cond_ref, then_block, else_block, Type::None));
+                    }
+                }
+
+                self.specialize_ir(frame, &mut loop_block, body_ir)?;
+
+                frame.in_runtime_branch = in_runtime_branch;
+
+                block.code.push(lir::Instruction::Loop(loop_block));
+
+                (lir::ValueRef::None, Type::None)
+            }
+            ir::Node::Break => {
+                block.code.push(lir::Instruction::Break);
+
+                (lir::ValueRef::None, Type::None)
+            }
+            ir::Node::Continue => {
+                block.code.push(lir::Instruction::Continue);
+
+                (lir::ValueRef::None, Type::None)
+            }
+        };
+
+        Ok(result)
+    }
+
+    // All elements of a list need to have the same type, the first one decides it
+    fn list_element_type(&self, element_types: &[Type], irs: &[ir::IR], location: &ast::Location) -> Result<Type, Diagnostic> {
+        let element_type = match element_types.first() {
+            Some(typ) => *typ,
+            None => return Err(
+                Diagnostic::error("E0303", "Cannot infer the type of an empty list")
+                    .with_primary(location.clone(), "the element type is unknown")
+                    .with_note("the type of a list comes from its first element")
+            )
+        };
+
+        for (typ, ir) in element_types.iter().zip(irs).skip(1) {
+            if *typ != element_type {
+                return Err(
+                    self.mismatched_types(&ir.location, element_type, *typ)
+                        .with_secondary(irs[0].location.clone(), "expected because of the first element")
+                );
+            }
         }
+
+        Ok(element_type)
     }
 
-    fn new_temp_local(frame: &mut ComptimeStackFrame) -> lir::LocalRef {
-        let i = frame.runtime_local_count;
-        frame.runtime_local_count += 1;
+    fn apply_constructor(
+        &mut self,
+        constructor: ir::TypeConstructor,
+        values: &[Value],
+        args: &[ir::IR],
+        location: &ast::Location
+    ) -> Result<Type, Diagnostic> {
+        if values.len() != constructor.param_count() {
+            return Err(Self::wrong_arg_count(location, constructor.param_count(), values.len()));
+        }
 
-        lir::LocalRef { i }
+        let mut types = Vec::with_capacity(values.len());
+        for (value, arg) in values.iter().zip(args) {
+            match value {
+                Value::Type(typ) => types.push(*typ),
+                _ => return Err(self.mismatched_types(&arg.location, Type::Type, value.type_of()))
+            }
+        }
+
+        Ok(constructor.apply(&mut self.types, &types))
     }
 
-    // fn eval_ir(
-    //     &mut self,
-    //     frame: &mut ComptimeStackFrame,
-    //     block: &mut lir::BasicBlock,
-    //     ir: &ir::IR,
-    //     in_comptime: bool
-    // ) -> (lir::ValueRef, Type) {
-    //     let location = ir.location.clone();
-    //     let (node, typ) = match &ir.node {
-    //         ir::Node::Nop => (lir::ValueRef::None, Type::None),
-    //         ir::Node::Constant(value) => {
-    //             let value_ref = match value {
-    //                 Value::None => lir::ValueRef::None,
-    //                 Value::Bool(value) => lir::ValueRef::Bool(*value),
-    //                 Value::Int(value) => lir::ValueRef::Int(*value),
-    //                 Value::Float(value) => lir::ValueRef::Float(*value),
-    //                 Value::Type(_) => todo!("Support referencing types from runtime code?"),
-    //                 Value::Closure(_, _) => todo!("Support closure exports")
-    //             };
-    //
-    //             (value_ref, value.type_of())
-    //         }
-    //         ir::Node::GlobalRef(global_ref) => {
-    //             let value_ref = if global_ref.comptime {
-    //                 todo!("Resolve global value as constant");
-    //                 todo!("Support specializing Any types");
-    //             } else {
-    //                 // ir::Node::GlobalRef(*global_ref)
-    //                 todo!("Support global refs")
-    //             };
-    //
-    //             // (value_ref, _)
-    //         }
-    //         ir::Node::ParamRef(param_ref) => {
-    //             let ir = if param_ref.comptime {
-    //                 todo!("Resolve param value as constant");
-    //                 todo!("Support specializing Any types");
-    //             } else {
-    //                 ir::Node::ParamRef(*param_ref)
-    //             };
-    //
-    //             (ir, *frame.param_types.get(param_ref).expect("Missing param type"))
-    //         }
-    //         ir::Node::LocalRef(local_ref) => {
-    //             let frame_type = frame.local_types.get(local_ref)
-    //                 .expect("Used local before assignment");
-    //
-    //             let ir = if frame_type.comptime {
-    //                 if frame_type.typ == Type::Any {
-    //                     todo!("Support specializing Any types");
-    //                 }
-    //
-    //                 let value = frame.local_values.get(local_ref)
-    //                     .expect("Used local before assignment");
-    //
-    //                 ir::Node::Constant(value.clone())
-    //             } else {
-    //                 ir::Node::LocalRef(*local_ref)
-    //             };
-    //
-    //             (ir, frame.local_types.get(local_ref).expect("Used param before definition").typ)
-    //         }
-    //         ir::Node::CaptureRef(capture_ref) => {
-    //             let ir = if capture_ref.comptime {
-    //                 todo!("Resolve captured value as constant");
-    //                 todo!("Support specializing Any types");
-    //             } else {
-    //                 ir::Node::CaptureRef(*capture_ref)
-    //             };
-    //
-    //             (ir, *frame.capture_types.get(capture_ref).expect("Missing capture type"))
-    //         }
-    //         ir::Node::LocalSet(local_ref, value_ir) => {
-    //             let (value, typ) = self.eval_ir(frame, value_ir, local_ref.comptime);
-    //
-    //             let ir = if local_ref.comptime {
-    //                 if typ == Type::Any {
-    //                     todo!("Support specializing Any types");
-    //                 }
-    //
-    //                 let value = self.assert_const_value(value);
-    //
-    //                 frame.local_values.insert(*local_ref, value);
-    //
-    //                 ir::Node::Nop
-    //             } else {
-    //                 ir::Node::LocalSet(*local_ref, Box::new(value))
-    //             };
-    //
-    //             frame.local_types.insert(*local_ref, StackFrameType {
-    //                 typ,
-    //                 comptime: local_ref.comptime
-    //             });
-    //
-    //             (ir, typ)
-    //         }
-    //         ir::Node::Block(irs) => {
-    //             if irs.len() == 0 {
-    //                 (ir::Node::Constant(Value::None), Type::None)
-    //             } else {
-    //                 let mut results = Vec::with_capacity(irs.len());
-    //                 let mut result_type = Type::None;
-    //                 for ir in irs {
-    //                     let (ir, typ) = self.eval_ir(frame, ir, in_comptime);
-    //                     result_type = typ;
-    //
-    //                     // TODO: Remove if it resolves to a constant and it's not the last expression
-    //                     results.push(ir);
-    //                 }
-    //
-    //                 (ir::Node::Block(results), result_type)
-    //             }
-    //         }
-    //         ir::Node::Comptime(ir) => {
-    //             let (result_ir, result_typ) = self.eval_ir(frame, ir, true);
-    //             let value = self.assert_const_value(result_ir);
-    //
-    //             // Doing this because of the location & to make sure we checked that it's fully
-    //             // evaluated
-    //             (ir::Node::Constant(value), result_typ)
-    //         },
-    //         ir::Node::Call(name, target, args) => {
-    //             let (target, target_type) = self.eval_ir(frame, target, in_comptime);
-    //
-    //             let mut arg_types = Vec::with_capacity(args.len() + 1);
-    //             let mut arg_irs = Vec::with_capacity(args.len() + 1);
-    //
-    //             // TODO: Remove this clones
-    //             arg_types.push(target_type.clone());
-    //             arg_irs.push(target);
-    //
-    //             for arg in args {
-    //                 let (ir, typ) = self.eval_ir(frame, arg, in_comptime);
-    //
-    //                 arg_irs.push(ir);
-    //                 arg_types.push(typ);
-    //             }
-    //
-    //             let resolved_fn = match (target_type, name.as_ref()) {
-    //                 (Type::Any, _) => panic!("Target type cannot be Any"),
-    //                 (Type::None, _) => todo!("Support calling functions on None"),
-    //                 (Type::Bool, _) => todo!("Support calling functions on bools"),
-    //                 (Type::Int, "+") => ResolvedFn::Intrinsic(ir::IntrinsicFn::AddInt),
-    //                 (Type::Float, _) => todo!("Support calling functions on floats"),
-    //                 (Type::Type, _) => todo!("Support calling functions on types"),
-    //                 (Type::Closure(_), _) => todo!("Support calling functions on closures"),
-    //                 (typ, name) => panic!("Cannot find function {} on {:?}", name, typ)
-    //             };
-    //
-    //             let signature = match &resolved_fn {
-    //                 ResolvedFn::Intrinsic(intrinsic) => intrinsic.signature(&arg_types),
-    //                 ResolvedFn::TFunction(_) => todo!("Support getting signature of TFunctions"),
-    //                 ResolvedFn::RFunction(_) => todo!("Support getting signature of RFunctions")
-    //             };
-    //
-    //             let node = match resolved_fn {
-    //                 ResolvedFn::Intrinsic(intrinsic) => ir::Node::StaticCallIntrinsic(intrinsic, arg_irs),
-    //                 ResolvedFn::TFunction(_) => todo!("Support calling TFunctions"),
-    //                 ResolvedFn::RFunction(_) => todo!("Support calling RFunctions")
-    //             };
-    //
-    //             (node, signature.returns)
-    //         }
-    //         ir::Node::CreateClosure(func_ref, captures) => {
-    //             todo!("Support CreateClosure in the interpreter")
-    //         }
-    //         ir::Node::StaticCallIntrinsic(_, _) => {
-    //             todo!("Support 'StaticCallIntrinsic' in the interpreter")
-    //         }
-    //         ir::Node::StaticCall(_, _) => {
-    //             todo!("Support 'StaticCall' in the interpreter?")
-    //         }
-    //         ir::Node::If(_, _, _) => {
-    //             todo!("Support 'if' in the interpreter")
-    //         }
-    //     };
-    //
-    //     (ir::IR { node, location }, typ)
-    // }
-
-    fn assert_const(value_ref: lir::ValueRef) -> Value {
-        match value_ref {
-            lir::ValueRef::None => Value::None,
-            lir::ValueRef::Bool(value) => Value::Bool(value),
-            lir::ValueRef::Int(value) => Value::Int(value),
-            lir::ValueRef::Float(value) => Value::Float(value),
-            lir::ValueRef::Param(_) => todo!("Error handling"),
-            lir::ValueRef::Local(_) => todo!("Error handling")
-        }
-    }
-
-    fn value_to_lir(value: &Value) -> lir::ValueRef {
-        match value {
-            Value::None => lir::ValueRef::None,
-            Value::Bool(value) => lir::ValueRef::Bool(*value),
-            Value::Int(value) => lir::ValueRef::Int(*value),
-            Value::Float(value) => lir::ValueRef::Float(*value),
-            Value::Type(_) => todo!("Support referencing types from runtime code?"),
-            Value::Closure(_, _) => todo!("Support closure exports")
+    fn specialize_create_struct(
+        &mut self,
+        frame: &mut ComptimeStackFrame,
+        block: &mut lir::BasicBlock,
+        typ: Type,
+        args: &[ir::IR],
+        location: &ast::Location
+    ) -> Result<(lir::ValueRef, Type), Diagnostic> {
+        let field_types: Vec<Type> = match typ {
+            Type::Struct(struct_ref) => self.types.struct_type(struct_ref).fields.iter()
+                .map(|field| field.typ)
+                .collect(),
+            _ => panic!("Expected a struct type, got {:?}", typ)
+        };
+
+        if args.len() != field_types.len() {
+            return Err(self.wrong_field_count(location, typ, field_types.len(), args.len()));
         }
+
+        let mut value_refs = Vec::with_capacity(args.len());
+        for (arg, field_type) in args.iter().zip(field_types) {
+            let (value_ref, value_type) = self.specialize_ir(frame, block, arg)?;
+
+            value_refs.push(self.coerce(frame, block, value_ref, value_type, field_type, &arg.location)?);
+        }
+
+        let result_local_ref = Self::new_temp_local(frame);
+
+        block.code.push(lir::Instruction::CreateStruct(result_local_ref, typ, value_refs));
+
+        Ok((lir::ValueRef::Local(result_local_ref), typ))
     }
-}
 
-#[derive(Clone, Copy)]
-pub enum ResolvedFn {
-    Intrinsic(ir::IntrinsicFn),
+    fn resolve_fn(&self, target_type: Type, name: &str, arg_count: usize) -> Option<ResolvedFn> {
+        Some(match (target_type, name) {
+            (Type::Bool | Type::Int | Type::Float, name) => ResolvedFn::Intrinsic(ir::IntrinsicFn::operator(target_type, name, arg_count)?),
+            (Type::Closure(closure_ref), "call") => ResolvedFn::TFunction(self.types.closure_type(closure_ref).template),
+            (Type::List(list_ref), "[]") => ResolvedFn::Intrinsic(ir::IntrinsicFn::ListGet(self.types.list_type(list_ref).element)),
+            (Type::Struct(struct_ref), name) if arg_count == 0 => {
+                let (i, field) = self.types.struct_type(struct_ref).field(name)?;
+
+                ResolvedFn::Intrinsic(ir::IntrinsicFn::StructGet(i, field.typ))
+            }
+            (Type::Interface(interface_ref), name) => {
+                let (i, _) = self.types.interface_type(interface_ref).method(name)?;
+
+                ResolvedFn::Method(target_type, i)
+            }
+            (Type::Function(_), "call") => ResolvedFn::FunctionValue(target_type),
+            _ => return None
+        })
+    }
+
+    // The first parameter of the signature is the value the function is called on
+    fn signature_of(&self, resolved_fn: ResolvedFn, arg_types: &[Type]) -> ir::FunctionSignature {
+        match resolved_fn {
+            ResolvedFn::Intrinsic(intrinsic) => intrinsic.signature(arg_types),
+            ResolvedFn::Method(interface, i) => {
+                let method_signature = match interface {
+                    Type::Interface(interface_ref) => &self.types.interface_type(interface_ref).methods[i].signature,
+                    typ => panic!("Expected an interface type, got {:?}", typ)
+                };
+
+                let mut params = Vec::with_capacity(method_signature.params.len() + 1);
+                params.push(interface);
+                params.extend_from_slice(&method_signature.params);
+
+                ir::FunctionSignature { params, returns: method_signature.returns }
+            }
+            ResolvedFn::FunctionValue(function_type) => {
+                let function_signature = match function_type {
+                    Type::Function(function_ref) => self.types.function_type(function_ref),
+                    typ => panic!("Expected a function type, got {:?}", typ)
+                };
+
+                let mut params = Vec::with_capacity(function_signature.params.len() + 1);
+                params.push(function_type);
+                params.extend_from_slice(&function_signature.params);
+
+                ir::FunctionSignature { params, returns: function_signature.returns }
+            }
+            ResolvedFn::RFunction(func_ref) => {
+                let func = &self.functions[func_ref.i];
+
+                let mut params = Vec::with_capacity(func.param_types.len() + 1);
+                params.push(arg_types[0]);
+                params.extend_from_slice(&func.param_types);
+
+                ir::FunctionSignature { params, returns: func.return_type }
+            }
+            ResolvedFn::TFunction(_) => panic!("Functions must be specialized before getting their signature")
+        }
+    }
+
+    // Converts a value to a type which is expected for it, e.g. for a function argument
+    fn coerce(
+        &mut self,
+        frame: &mut ComptimeStackFrame,
+        block: &mut lir::BasicBlock,
+        value_ref: lir::ValueRef,
+        from: Type,
+        to: Type,
+        location: &ast::Location
+    ) -> Result<lir::ValueRef, Diagnostic> {
+        let value_ref = match self.coercion(from, to, location)? {
+            Coercion::None => value_ref,
+            Coercion::FunctionValue(func_ref) => {
+                let result_local_ref = Self::new_temp_local(frame);
+
+                let closure_ref = if self.functions[func_ref.i].capture_types.is_empty() { lir::ValueRef::None } else { value_ref };
+
+                block.code.push(lir::Instruction::CreateFunctionValue(result_local_ref, to, func_ref, closure_ref));
+
+                lir::ValueRef::Local(result_local_ref)
+            }
+            Coercion::InterfaceValue(vtable_ref) => {
+                let result_local_ref = Self::new_temp_local(frame);
+
+                block.code.push(lir::Instruction::CreateInterfaceValue(result_local_ref, vtable_ref, value_ref));
+
+                lir::ValueRef::Local(result_local_ref)
+            }
+        };
+
+        Ok(value_ref)
+    }
+
+    // The same as `coerce`, for values computed at compile time
+    fn coerce_value(&mut self, value: Value, to: Type, location: &ast::Location) -> Result<Value, Diagnostic> {
+        let value = match self.coercion(value.type_of(), to, location)? {
+            Coercion::None => value,
+            Coercion::FunctionValue(func_ref) => Value::Function(to, func_ref, Rc::new(value)),
+            Coercion::InterfaceValue(_) => Value::Interface(to, Rc::new(value))
+        };
+
+        Ok(value)
+    }
+
+    // Which values can be used where a different type is expected
+    fn coercion(&mut self, from: Type, to: Type, location: &ast::Location) -> Result<Coercion, Diagnostic> {
+        if from == to {
+            return Ok(Coercion::None);
+        }
+
+        match (from, to) {
+            (Type::Closure(_), Type::Function(function_ref)) => {
+                let signature = self.types.function_type(function_ref).clone();
+
+                Ok(Coercion::FunctionValue(self.specialize_closure(from, signature, location)?))
+            }
+            (_, Type::Interface(_)) => Ok(Coercion::InterfaceValue(self.vtable_for(from, to, location)?)),
+            _ => Err(self.mismatched_types(location, to, from))
+        }
+    }
+
+    // Type-checks the body of the closure with the parameter types of the signature. The location
+    // is where the closure is converted.
+    fn specialize_closure(
+        &mut self,
+        closure: Type,
+        signature: ir::FunctionSignature,
+        location: &ast::Location
+    ) -> Result<lir::FunctionRef, Diagnostic> {
+        let template_ref = self.closure_type(closure).template;
+        if self.templates[template_ref.i].params.iter().any(|param| param.comptime) {
+            let expected = self.types.function_of(signature);
+
+            return Err(
+                Diagnostic::error("E0311", "Cannot convert a closure with compile-time parameters to a function")
+                    .with_primary(location.clone(), format!("expected `{}`", self.types.type_name(expected)))
+                    .with_note("functions are called at runtime, so their arguments can't be known at compile time")
+            );
+        }
+
+        if self.templates[template_ref.i].params.len() != signature.params.len() {
+            let expected = self.types.function_of(signature);
+
+            return Err(self.mismatched_types(location, expected, closure));
+        }
+
+        let func_ref = self.specialize_call(closure, signature.params.clone(), Vec::new(), location)?;
+        let func = &self.functions[func_ref.i];
+
+        let found = ir::FunctionSignature { params: func.param_types.clone(), returns: func.return_type };
+        if found != signature {
+            let expected = self.types.function_of(signature);
+            let found = self.types.function_of(found);
+
+            return Err(self.mismatched_types(location, expected, found));
+        }
+
+        Ok(func_ref)
+    }
+
+    // The parameter types of the specialized function may differ from the argument types, the
+    // arguments need to be coerced to them. The comptime arguments are in the order of the comptime
+    // parameters.
+    fn specialize_call(
+        &mut self,
+        closure: Type,
+        param_types: Vec<Type>,
+        comptime_args: Vec<Value>,
+        location: &ast::Location
+    ) -> Result<lir::FunctionRef, Diagnostic> {
+        let existing = self.specializations.iter().find(|specialization|
+            specialization.closure == closure &&
+                specialization.param_types == param_types &&
+                specialization.comptime_args == comptime_args
+        );
+
+        if let Some(specialization) = existing {
+            return Ok(specialization.func_ref);
+        }
+
+        let closure_type = self.closure_type(closure);
+        let templates = self.templates;
+        let template = &templates[closure_type.template.i];
+
+        if template.params.len() != param_types.len() {
+            return Err(Self::wrong_arg_count(location, template.params.len(), param_types.len()));
+        }
+
+        let mut comptime_param_values = VecMap::with_capacity(comptime_args.len());
+        let mut comptime_arg_values = comptime_args.iter();
+        for (i, param) in template.params.iter().enumerate() {
+            if param.comptime {
+                let value = comptime_arg_values.next().expect("Missing comptime argument");
+
+                comptime_param_values.insert_push(ir::ParamRef { i, comptime: true }, value.clone());
+            }
+        }
+
+        let mut comptime_capture_values = VecMap::new();
+        for (i, value) in closure_type.comptime_captures.iter().enumerate() {
+            if let Some(value) = value {
+                comptime_capture_values.insert_push(ir::CaptureRef { i, comptime: true }, value.clone());
+            }
+        }
+
+        // The specialization is registered before the body is specialized, so that recursive calls
+        // reuse it. The function is set once it's specialized.
+        let func_ref = lir::FunctionRef { i: self.functions.len() };
+
+        self.functions.push(lir::Function {
+            capture_types: Vec::new(),
+            param_types: Vec::new(),
+            return_type: Type::None,
+            local_count: 0,
+            mutable_locals: Vec::new(),
+            body: lir::BasicBlock { code: Vec::new() }
+        });
+        self.specializations.push(Specialization { closure, param_types: param_types.clone(), comptime_args, func_ref });
+
+        let result = self.specialize_function(
+            template,
+            Some(func_ref),
+            param_types,
+            comptime_param_values,
+            closure_type.capture_types,
+            comptime_capture_values
+        );
+
+        let func = match result {
+            Ok(func) => func,
+
+            // The call may be specialized again once the return type of an outer recursive
+            // function is assumed, so this one is left as an unused function
+            Err(diagnostic) => {
+                self.specializations.retain(|specialization| specialization.func_ref != func_ref);
+                self.inferring_return_types.retain(|(inferring_ref, _)| *inferring_ref != func_ref);
+
+                let unused = &mut self.functions[func_ref.i];
+                unused.return_type = Type::None;
+                unused.body.code = vec![lir::Instruction::Return(lir::ValueRef::None)];
+
+                return Err(diagnostic);
+            }
+        };
+
+        self.functions[func_ref.i] = func;
+
+        Ok(func_ref)
+    }
+
+    // Binds the `val T`s in the pattern and returns the type of the parameter
+    fn match_pattern(
+        &mut self,
+        frame: &mut ComptimeStackFrame,
+        pattern: &ir::Pattern,
+        typ: Type
+    ) -> Result<Type, Diagnostic> {
+        match &pattern.node {
+            ir::PatternNode::SpecificValue(type_ir) => self.eval_type(frame, type_ir),
+            ir::PatternNode::Binding(local_ref) => {
+                frame.local_values.insert(*local_ref, Value::Type(typ));
+                frame.local_types.insert(*local_ref, StackFrameType { typ: Type::Type, comptime: true });
+
+                Ok(typ)
+            }
+            ir::PatternNode::Call(constructor_ir, arg_patterns) => {
+                let constructor = match self.eval_ir(frame, constructor_ir)? {
+                    Value::Type(Type::Constructor(constructor)) => constructor,
+                    value => return Err(
+                        Diagnostic::error("E0300", "Mismatched types")
+                            .with_primary(constructor_ir.location.clone(), format!(
+                                "expected a type constructor, found `{}`",
+                                self.types.type_name(value.type_of())
+                            ))
+                    )
+                };
+
+                if arg_patterns.len() != constructor.param_count() {
+                    return Err(Self::wrong_arg_count(&pattern.location, constructor.param_count(), arg_patterns.len()));
+                }
+
+                let arg_types = match constructor.unapply(&self.types, typ) {
+                    Some(arg_types) => arg_types,
+                    None => return Err(
+                        Diagnostic::error("E0300", "Mismatched types")
+                            .with_primary(pattern.location.clone(), format!(
+                                "expected a `{}`, found `{}`",
+                                constructor.name(),
+                                self.types.type_name(typ)
+                            ))
+                    )
+                };
+
+                let mut types = Vec::with_capacity(arg_types.len());
+                for (arg_pattern, arg_type) in arg_patterns.iter().zip(arg_types) {
+                    types.push(self.match_pattern(frame, arg_pattern, arg_type)?);
+                }
+
+                Ok(constructor.apply(&mut self.types, &types))
+            }
+            ir::PatternNode::FunctionType(param_patterns, return_pattern) => {
+                let signature = match typ {
+                    Type::Function(function_ref) => self.types.function_type(function_ref).clone(),
+                    Type::Closure(_) => return self.match_closure_pattern(frame, pattern, param_patterns, return_pattern, typ),
+                    _ => return Err(
+                        Diagnostic::error("E0300", "Mismatched types")
+                            .with_primary(pattern.location.clone(), format!("expected a function, found `{}`", self.types.type_name(typ)))
+                    )
+                };
+
+                if signature.params.len() != param_patterns.len() {
+                    return Err(
+                        Diagnostic::error("E0300", "Mismatched types")
+                            .with_primary(pattern.location.clone(), format!(
+                                "expected a function with {} parameters, found `{}`",
+                                param_patterns.len(),
+                                self.types.type_name(typ)
+                            ))
+                    )
+                }
+
+                let mut params = Vec::with_capacity(param_patterns.len());
+                for (param_pattern, param_type) in param_patterns.iter().zip(signature.params) {
+                    params.push(self.match_pattern(frame, param_pattern, param_type)?);
+                }
+
+                let returns = self.match_pattern(frame, return_pattern, signature.returns)?;
+
+                Ok(self.types.function_of(ir::FunctionSignature { params, returns }))
+            }
+        }
+    }
+
+    // The parameter types come from the closure where it declares them and from the pattern
+    // otherwise, the return type from specializing the closure with them
+    fn match_closure_pattern(
+        &mut self,
+        frame: &mut ComptimeStackFrame,
+        pattern: &ir::Pattern,
+        param_patterns: &[ir::Pattern],
+        return_pattern: &ir::Pattern,
+        closure: Type
+    ) -> Result<Type, Diagnostic> {
+        let closure_type = self.closure_type(closure);
+        let templates = self.templates;
+        let template = &templates[closure_type.template.i];
+
+        if template.params.len() != param_patterns.len() {
+            return Err(
+                Diagnostic::error("E0300", "Mismatched types")
+                    .with_primary(pattern.location.clone(), format!(
+                        "expected a function with {} parameters, found a closure with {}",
+                        param_patterns.len(),
+                        template.params.len()
+                    ))
+            );
+        }
+
+        // The types of the closure parameters can use its comptime captures and the `val T`s of
+        // the parameters before them
+        let mut capture_values = VecMap::new();
+        for (i, value) in closure_type.comptime_captures.iter().enumerate() {
+            if let Some(value) = value {
+                capture_values.insert_push(ir::CaptureRef { i, comptime: true }, value.clone());
+            }
+        }
+
+        let mut closure_frame = ComptimeStackFrame {
+            param_types: Vec::new(),
+            param_values: VecMap::new(),
+            runtime_param_map: VecMap::new(),
+
+            capture_types: closure_type.capture_types,
+            capture_values,
+            runtime_capture_map: VecMap::new(),
+
+            locals: template.locals.clone(),
+            local_types: VecMap::new(),
+            local_values: VecMap::new(),
+            runtime_local_map: VecMap::new(),
+            runtime_local_count: 0,
+
+            in_runtime_branch: false,
+            loop_exit: None,
+
+            return_type: None
+        };
+
+        let mut params = Vec::with_capacity(param_patterns.len());
+        for (param, param_pattern) in template.params.iter().zip(param_patterns) {
+            let typ = match (&param.typ, &param_pattern.node) {
+                (Some(ir::Pattern { node: ir::PatternNode::SpecificValue(type_ir), .. }), _) =>
+                    self.eval_type(&mut closure_frame, type_ir)?,
+                (_, ir::PatternNode::SpecificValue(type_ir)) => self.eval_type(frame, type_ir)?,
+                _ => return Err(
+                    Diagnostic::error("E0307", "Cannot infer the parameter types of the closure")
+                        .with_primary(param_pattern.location.clone(), "the type of this parameter is unknown")
+                        .with_help("declare the parameter types of the closure, e.g. `(n: Int) n + 1`")
+                )
+            };
+
+            if let Some(closure_pattern) = &param.typ {
+                self.match_pattern(&mut closure_frame, closure_pattern, typ)?;
+            }
+
+            params.push(self.match_pattern(frame, param_pattern, typ)?);
+        }
+
+        let func_ref = self.specialize_call(closure, params.clone(), Vec::new(), &pattern.location)?;
+        let returns = self.match_pattern(frame, return_pattern, self.functions[func_ref.i].return_type)?;
+
+        Ok(self.types.function_of(ir::FunctionSignature { params, returns }))
+    }
+
+    // Interface values can also be converted to other interfaces, their vtable calls the methods
+    // through the vtable of the interface value
+    fn vtable_for(&mut self, concrete: Type, interface: Type, location: &ast::Location) -> Result<lir::VTableRef, Diagnostic> {
+        if let Some(i) = self.vtables.iter().position(|vtable| vtable.concrete == concrete && vtable.interface == interface) {
+            return Ok(lir::VTableRef { i });
+        }
+
+        let interface_methods = match interface {
+            Type::Interface(interface_ref) => self.types.interface_type(interface_ref).methods.clone(),
+            typ => panic!("Expected an interface type, got {:?}", typ)
+        };
+
+        let mut methods = Vec::with_capacity(interface_methods.len());
+        for method in interface_methods {
+            let resolved_fn = match self.resolve_fn(concrete, &method.name, method.signature.params.len()) {
+                Some(resolved_fn) => resolved_fn,
+                None => return Err(
+                    self.not_implemented(location, concrete, interface)
+                        .with_note(format!("`{}` has no method `{}`", self.types.type_name(concrete), method.name))
+                )
+            };
+
+            let resolved_fn = match resolved_fn {
+                ResolvedFn::TFunction(_) => ResolvedFn::RFunction(self.specialize_call(concrete, method.signature.params.clone(), Vec::new(), location)?),
+                resolved_fn => resolved_fn
+            };
+
+            let mut arg_types = Vec::with_capacity(method.signature.params.len() + 1);
+            arg_types.push(concrete);
+            arg_types.extend_from_slice(&method.signature.params);
+
+            let signature = self.signature_of(resolved_fn, &arg_types);
+
+            let found = ir::FunctionSignature { params: signature.params[1..].to_vec(), returns: signature.returns };
+            if found != method.signature {
+                return Err(
+                    self.not_implemented(location, concrete, interface)
+                        .with_note(format!(
+                            "`{}` has the type `{}`, expected `{}`",
+                            method.name,
+                            self.types.signature_name(&found),
+                            self.types.signature_name(&method.signature)
+                        ))
+                );
+            }
+
+            methods.push(self.build_method_thunk(resolved_fn, signature));
+        }
+
+        self.vtables.push(lir::VTable { concrete, interface, methods });
+
+        Ok(lir::VTableRef { i: self.vtables.len() - 1 })
+    }
+
+    // A function which calls the method on a concrete value, so it can be put in a vtable
+    fn build_method_thunk(&mut self, resolved_fn: ResolvedFn, signature: ir::FunctionSignature) -> lir::FunctionRef {
+        let arg_refs: Vec<lir::ValueRef> = (0..signature.params.len())
+            .map(|i| lir::ValueRef::Param(lir::ParamRef { i }))
+            .collect();
+
+        let result_local_ref = lir::LocalRef { i: 0 };
+
+        let instruction = match resolved_fn {
+            ResolvedFn::Intrinsic(intrinsic) => lir::Instruction::CallIntrinsic(result_local_ref, intrinsic, arg_refs),
+            ResolvedFn::Method(interface, i) => lir::Instruction::CallMethod(result_local_ref, interface, i, arg_refs),
+            ResolvedFn::FunctionValue(function_type) => lir::Instruction::CallFunctionValue(result_local_ref, function_type, arg_refs),
+            ResolvedFn::RFunction(func_ref) => {
+                // The concrete value is the closure
+                let mut call_arg_refs = arg_refs[1..].to_vec();
+                if !self.functions[func_ref.i].capture_types.is_empty() {
+                    call_arg_refs.push(arg_refs[0]);
+                }
+
+                lir::Instruction::Call(result_local_ref, func_ref, call_arg_refs)
+            }
+            ResolvedFn::TFunction(_) => panic!("Functions must be specialized before they are called")
+        };
+
+        self.functions.push(lir::Function {
+            capture_types: Vec::new(),
+            param_types: signature.params,
+            return_type: signature.returns,
+            local_count: 1,
+            mutable_locals: Vec::new(),
+            body: lir::BasicBlock {
+                code: vec![instruction, lir::Instruction::Return(lir::ValueRef::Local(result_local_ref))]
+            }
+        });
+
+        lir::FunctionRef { i: self.functions.len() - 1 }
+    }
+
+    fn runtime_global(&mut self, global_ref: ir::GlobalRef) -> (lir::GlobalRef, Type) {
+        let global = &self.globals.globals[global_ref.i];
+        let typ = global.value.type_of();
+
+        if let Some(lir_global_ref) = self.runtime_global_map.get(&global_ref) {
+            return (*lir_global_ref, typ);
+        }
+
+        self.runtime_globals.push(lir::Global { typ, value: global.value.clone() });
+
+        let lir_global_ref = lir::GlobalRef { i: self.runtime_globals.len() - 1 };
+        self.runtime_global_map.insert_push(global_ref, lir_global_ref);
+
+        (lir_global_ref, typ)
+    }
+
+    fn new_temp_local(frame: &mut ComptimeStackFrame) -> lir::LocalRef {
+        let i = frame.runtime_local_count;
+        frame.runtime_local_count += 1;
+
+        lir::LocalRef { i }
+    }
+
+    // Runs the code at compile time. Everything it references needs to be known at compile time.
+    fn eval_ir(&mut self, frame: &mut ComptimeStackFrame, ir: &ir::IR) -> Result<Value, Diagnostic> {
+        let value = match &ir.node {
+            ir::Node::Nop => Value::None,
+            ir::Node::Constant(value) => value.clone(),
+            ir::Node::GlobalRef(global_ref) => {
+                if !global_ref.comptime {
+                    panic!("Runtime globals cannot be used at compile time")
+                }
+
+                self.globals.globals[global_ref.i].value.clone()
+            }
+            ir::Node::ParamRef(param_ref) => frame.param_values.get(param_ref)
+                .expect("Missing param value")
+                .clone(),
+            ir::Node::LocalRef(local_ref) => Self::var_value_of(
+                frame.local_values.get(local_ref).expect("Used local before assignment")
+            ),
+            ir::Node::CaptureRef(capture_ref) => Self::var_value_of(
+                frame.capture_values.get(capture_ref).expect("Missing capture value")
+            ),
+            ir::Node::LocalSet(local_ref, value_ir) => {
+                let value = self.eval_ir(frame, value_ir)?;
+                let local = frame.locals[local_ref.i];
+
+                match frame.local_values.get(local_ref) {
+                    Some(Value::Cell(_, cell)) => {
+                        let cell = cell.clone();
+
+                        self.assign_cell(&cell, value, &value_ir.location)?;
+                    }
+                    Some(existing) if local.mutable => {
+                        let value = self.coerce_value(value, existing.type_of(), &value_ir.location)?;
+
+                        frame.local_values.insert(*local_ref, value);
+                    }
+                    _ => {
+                        let value = if local.boxed {
+                            Value::Cell(self.types.cell_of(value.type_of()), Rc::new(RefCell::new(value)))
+                        } else {
+                            value
+                        };
+
+                        frame.local_types.insert(*local_ref, StackFrameType { typ: value.type_of(), comptime: true });
+                        frame.local_values.insert(*local_ref, value);
+                    }
+                }
+
+                Value::None
+            }
+            ir::Node::CaptureSet(capture_ref, value_ir) => {
+                let value = self.eval_ir(frame, value_ir)?;
+                let cell = match frame.capture_values.get(capture_ref) {
+                    Some(Value::Cell(_, cell)) => cell.clone(),
+                    value => panic!("Expected a captured var to be a cell, got {:?}", value)
+                };
+
+                self.assign_cell(&cell, value, &value_ir.location)?;
+
+                Value::None
+            }
+            ir::Node::Block(irs) => {
+                let mut result = Value::None;
+                for ir in irs {
+                    result = self.eval_ir(frame, ir)?;
+
+                    // The rest of the block is skipped after a `break` or `continue`
+                    if frame.loop_exit.is_some() {
+                        return Ok(Value::None);
+                    }
+                }
+
+                result
+            }
+            ir::Node::List(irs) => {
+                let mut values = Vec::with_capacity(irs.len());
+                for ir in irs {
+                    values.push(self.eval_ir(frame, ir)?);
+                }
+
+                let element_types: Vec<Type> = values.iter().map(|value| value.type_of()).collect();
+                let element_type = self.list_element_type(&element_types, irs, &ir.location)?;
+
+                Value::List(self.types.list_of(element_type), Rc::new(values))
+            }
+            ir::Node::DefineStruct(field_irs) => {
+                let mut fields = Vec::with_capacity(field_irs.len());
+                for (name, type_ir) in field_irs {
+                    let typ = self.eval_type(frame, type_ir)?;
+
+                    fields.push(ir::StructField { name: name.clone(), typ });
+                }
+
+                Value::Type(self.types.define_struct(fields))
+            }
+            ir::Node::DefineInterface(method_irs) => {
+                let mut methods = Vec::with_capacity(method_irs.len());
+                for method_ir in method_irs {
+                    let mut params = Vec::with_capacity(method_ir.params.len());
+                    for param_ir in &method_ir.params {
+                        params.push(self.eval_type(frame, param_ir)?);
+                    }
+
+                    let returns = self.eval_type(frame, &method_ir.returns)?;
+
+                    methods.push(ir::InterfaceMethod {
+                        name: method_ir.name.clone(),
+                        signature: ir::FunctionSignature { params, returns }
+                    });
+                }
+
+                Value::Type(self.types.define_interface(methods))
+            }
+            ir::Node::FnType(param_irs, return_type_ir) => {
+                let mut params = Vec::with_capacity(param_irs.len());
+                for param_ir in param_irs {
+                    params.push(self.eval_type(frame, param_ir)?);
+                }
+
+                let returns = self.eval_type(frame, return_type_ir)?;
+
+                Value::Type(self.types.function_of(ir::FunctionSignature { params, returns }))
+            }
+            ir::Node::Comptime(ir) => self.eval_ir(frame, ir)?,
+            ir::Node::Call(name, target, args) => {
+                let target_value = self.eval_ir(frame, target)?;
+
+                let mut arg_values = Vec::with_capacity(args.len());
+                for arg in args {
+                    arg_values.push(self.eval_ir(frame, arg)?);
+                }
+
+                if let (Value::Type(typ @ Type::Struct(_)), "call") = (&target_value, name.as_ref()) {
+                    return self.eval_create_struct(*typ, arg_values, args, &ir.location);
+                }
+
+                if let (Value::Type(Type::Constructor(constructor)), "call") = (&target_value, name.as_ref()) {
+                    return Ok(Value::Type(self.apply_constructor(*constructor, &arg_values, args, &ir.location)?));
+                }
+
+                self.eval_method_call(target_value, name, arg_values, args, &ir.location)?
+            }
+            ir::Node::CreateClosure(template_ref, captures) => {
+                let (capture_types, comptime_captures, values) = Self::eval_captures(frame, captures);
+                let typ = self.types.closure_of(ir::ClosureType { template: *template_ref, capture_types, comptime_captures });
+
+                Value::Closure(typ, Rc::new(values))
+            }
+            ir::Node::DeclareRecursive(declarations) => {
+                for (local_ref, template_ref) in declarations {
+                    let typ = self.types.declare_closure(*template_ref);
+
+                    frame.local_types.insert(*local_ref, StackFrameType { typ, comptime: true });
+                    frame.local_values.insert(*local_ref, Value::Closure(typ, Rc::new(Vec::new())));
+                }
+
+                Value::None
+            }
+            ir::Node::DefineRecursive(local_ref, captures) => {
+                let closure_ref = match frame.local_values.get(local_ref) {
+                    Some(Value::Closure(Type::Closure(closure_ref), _)) => *closure_ref,
+                    value => panic!("Expected a declared recursive closure, got {:?}", value)
+                };
+
+                let (capture_types, comptime_captures, values) = Self::eval_captures(frame, captures);
+                if !values.is_empty() {
+                    panic!("Recursive closures can only capture comptime values")
+                }
+
+                let template = self.types.closure_type(closure_ref).template;
+                self.types.define_closure(closure_ref, ir::ClosureType { template, capture_types, comptime_captures });
+
+                Value::None
+            }
+            ir::Node::TypeAssert(value_ir, type_ir) => {
+                let value = self.eval_ir(frame, value_ir)?;
+                let typ = self.eval_type(frame, type_ir)?;
+
+                self.coerce_value(value, typ, &value_ir.location)?
+            }
+            ir::Node::If(cond_ir, then_ir, else_ir) => {
+                let condition = self.eval_ir(frame, cond_ir)?;
+
+                if condition.type_of() != Type::Bool {
+                    return Err(self.mismatched_types(&cond_ir.location, Type::Bool, condition.type_of()));
+                }
+
+                match (condition.assert_bool(), else_ir) {
+                    (true, Some(_)) => self.eval_ir(frame, then_ir)?,
+                    (false, Some(else_ir)) => self.eval_ir(frame, else_ir)?,
+                    (true, None) => {
+                        self.eval_ir(frame, then_ir)?;
+
+                        Value::None
+                    }
+                    (false, None) => Value::None
+                }
+            }
+            ir::Node::While(cond_ir, body_ir) => {
+                // The locals defined in the body are new in each iteration, so that e.g. the
+                // closures created in different iterations don't share the same vars
+                let local_count = frame.local_values.len();
+
+                loop {
+                    frame.local_values.truncate(local_count);
+
+                    let condition = self.eval_ir(frame, cond_ir)?;
+
+                    if condition.type_of() != Type::Bool {
+                        return Err(self.mismatched_types(&cond_ir.location, Type::Bool, condition.type_of()));
+                    }
+
+                    if !condition.assert_bool() {
+                        break;
+                    }
+
+                    self.eval_ir(frame, body_ir)?;
+
+                    match frame.loop_exit.take() {
+                        None | Some(LoopExit::Continue) => {}
+                        Some(LoopExit::Break) => break
+                    }
+                }
+
+                Value::None
+            }
+            ir::Node::Break => {
+                frame.loop_exit = Some(LoopExit::Break);
+
+                Value::None
+            }
+            ir::Node::Continue => {
+                frame.loop_exit = Some(LoopExit::Continue);
+
+                Value::None
+            }
+        };
+
+        Ok(value)
+    }
+
+    fn eval_method_call(
+        &mut self,
+        target_value: Value,
+        name: &str,
+        arg_values: Vec<Value>,
+        args: &[ir::IR],
+        location: &ast::Location
+    ) -> Result<Value, Diagnostic> {
+        let target_type = target_value.type_of();
+        let resolved_fn = match self.resolve_fn(target_type, name, args.len()) {
+            Some(resolved_fn) => resolved_fn,
+            None => return Err(self.unknown_method(location, target_type, name))
+        };
+
+        // The parameter types of closures are only known once they're matched with the arguments
+        if let ResolvedFn::TFunction(_) = resolved_fn {
+            return self.eval_call(target_type, target_value.assert_closure(), arg_values, args, location);
+        }
+
+        let mut arg_types = Vec::with_capacity(arg_values.len() + 1);
+        arg_types.push(target_type);
+        arg_types.extend(arg_values.iter().map(|value| value.type_of()));
+
+        let signature = self.signature_of(resolved_fn, &arg_types);
+
+        if signature.params.len() != arg_types.len() {
+            return Err(Self::wrong_arg_count(location, signature.params.len() - 1, arg_values.len()));
+        }
+
+        let mut values = Vec::with_capacity(arg_types.len());
+        values.push(target_value);
+        for ((value, param_type), arg) in arg_values.into_iter().zip(&signature.params[1..]).zip(args) {
+            values.push(self.coerce_value(value, *param_type, &arg.location)?);
+        }
+
+        let value = match resolved_fn {
+            ResolvedFn::Intrinsic(intrinsic) => intrinsic.eval(&values).map_err(|error| error.to_diagnostic(location))?,
+
+            // The method is called on the concrete value, like the vtable does at runtime
+            ResolvedFn::Method(_, _) => {
+                let arg_values = values.split_off(1);
+                let value = values[0].assert_interface().clone();
+
+                self.eval_method_call(value, name, arg_values, args, location)?
+            }
+            ResolvedFn::FunctionValue(_) => {
+                let arg_values = values.split_off(1);
+                let closure = values[0].assert_function().1.clone();
+
+                self.eval_call(closure.type_of(), closure.assert_closure(), arg_values, args, location)?
+            }
+            ResolvedFn::TFunction(_) | ResolvedFn::RFunction(_) => panic!("Specialized functions cannot be called at compile time")
+        };
+
+        self.coerce_value(value, signature.returns, location)
+    }
+
+    fn eval_create_struct(&mut self, typ: Type, values: Vec<Value>, args: &[ir::IR], location: &ast::Location) -> Result<Value, Diagnostic> {
+        let field_types: Vec<Type> = match typ {
+            Type::Struct(struct_ref) => self.types.struct_type(struct_ref).fields.iter()
+                .map(|field| field.typ)
+                .collect(),
+            _ => panic!("Expected a struct type, got {:?}", typ)
+        };
+
+        if values.len() != field_types.len() {
+            return Err(self.wrong_field_count(location, typ, field_types.len(), values.len()));
+        }
+
+        let mut field_values = Vec::with_capacity(values.len());
+        for ((value, field_type), arg) in values.into_iter().zip(field_types).zip(args) {
+            field_values.push(self.coerce_value(value, field_type, &arg.location)?);
+        }
+
+        Ok(Value::Struct(typ, Rc::new(field_values)))
+    }
+
+    // The captures are the runtime captures of the closure, the comptime ones are in its type
+    fn eval_call(
+        &mut self,
+        closure: Type,
+        captures: &[Value],
+        args: Vec<Value>,
+        arg_irs: &[ir::IR],
+        location: &ast::Location
+    ) -> Result<Value, Diagnostic> {
+        let closure_type = self.closure_type(closure);
+        let templates = self.templates;
+        let template = &templates[closure_type.template.i];
+
+        if template.params.len() != args.len() {
+            return Err(Self::wrong_arg_count(location, template.params.len(), args.len()));
+        }
+
+        let param_types: Vec<Type> = args.iter().map(|value| value.type_of()).collect();
+
+        let mut param_values = VecMap::with_capacity(args.len());
+        for (i, (param, value)) in template.params.iter().zip(args).enumerate() {
+            param_values.insert_push(ir::ParamRef { i, comptime: param.comptime }, value);
+        }
+
+        let mut runtime_captures = captures.iter();
+        let mut capture_values = VecMap::with_capacity(template.captures.len());
+        for (i, (capture, comptime_value)) in template.captures.iter().zip(&closure_type.comptime_captures).enumerate() {
+            let value = match comptime_value {
+                Some(value) => value.clone(),
+                None => runtime_captures.next().expect("Missing captured value").clone()
+            };
+
+            capture_values.insert_push(ir::CaptureRef { i, comptime: capture.comptime }, value);
+        }
+
+        let mut frame = ComptimeStackFrame {
+            param_types,
+            param_values,
+            runtime_param_map: VecMap::new(),
+
+            capture_types: closure_type.capture_types,
+            capture_values,
+            runtime_capture_map: VecMap::new(),
+
+            locals: template.locals.clone(),
+            local_types: VecMap::new(),
+            local_values: VecMap::new(),
+            runtime_local_map: VecMap::new(),
+            runtime_local_count: 0,
+
+            in_runtime_branch: false,
+            loop_exit: None,
+
+            return_type: None
+        };
+
+        for ((i, param), arg_ir) in template.params.iter().enumerate().zip(arg_irs) {
+            if let Some(pattern) = &param.typ {
+                let arg_type = frame.param_types[i];
+                let param_type = self.match_pattern(&mut frame, pattern, arg_type)?;
+
+                let param_ref = ir::ParamRef { i, comptime: param.comptime };
+                let value = frame.param_values.get(&param_ref).expect("Missing param value").clone();
+                let value = self.coerce_value(value, param_type, &arg_ir.location)?;
+
+                frame.param_values.insert(param_ref, value);
+                frame.param_types[i] = param_type;
+            }
+        }
+
+        let value = self.eval_ir(&mut frame, &template.body)?;
+
+        match &template.return_type {
+            None => Ok(value),
+            Some(type_ir) => {
+                let typ = self.eval_type(&mut frame, type_ir)?;
+
+                self.coerce_value(value, typ, &template.body.location)
+                    .map_err(|diagnostic| diagnostic.with_secondary(type_ir.location.clone(), "expected because of this return type"))
+            }
+        }
+    }
+
+    // Converts a value computed at compile time to something runtime code can use
+    fn export_value(&mut self, frame: &mut ComptimeStackFrame, block: &mut lir::BasicBlock, value: &Value) -> (lir::ValueRef, Type) {
+        match value {
+            Value::List(typ, values) => {
+                let element_refs = values.iter()
+                    .map(|value| self.export_value(frame, block, value).0)
+                    .collect();
+                let result_local_ref = Self::new_temp_local(frame);
+
+                block.code.push(lir::Instruction::CreateList(result_local_ref, *typ, element_refs));
+
+                (lir::ValueRef::Local(result_local_ref), *typ)
+            }
+            Value::Struct(typ, values) => {
+                let value_refs = values.iter()
+                    .map(|value| self.export_value(frame, block, value).0)
+                    .collect();
+                let result_local_ref = Self::new_temp_local(frame);
+
+                block.code.push(lir::Instruction::CreateStruct(result_local_ref, *typ, value_refs));
+
+                (lir::ValueRef::Local(result_local_ref), *typ)
+            }
+            Value::Closure(typ, captures) => {
+                let capture_refs = captures.iter()
+                    .map(|value| self.export_value(frame, block, value).0)
+                    .collect();
+                let result_local_ref = Self::new_temp_local(frame);
+
+                block.code.push(lir::Instruction::CreateClosure(result_local_ref, *typ, capture_refs));
+
+                (lir::ValueRef::Local(result_local_ref), *typ)
+            }
+            Value::Cell(typ, cell) => {
+                let value = cell.borrow().clone();
+                let value_ref = self.export_value(frame, block, &value).0;
+                let result_local_ref = Self::new_temp_local(frame);
+
+                block.code.push(lir::Instruction::CreateCell(result_local_ref, *typ, value_ref));
+
+                (lir::ValueRef::Local(result_local_ref), *typ)
+            }
+            Value::Interface(typ, value) => {
+                let (value_ref, concrete) = self.export_value(frame, block, value);
+
+                // The vtable was created when the value was converted to the interface
+                let vtable_i = self.vtables.iter()
+                    .position(|vtable| vtable.concrete == concrete && vtable.interface == *typ)
+                    .expect("Missing vtable");
+                let result_local_ref = Self::new_temp_local(frame);
+
+                block.code.push(lir::Instruction::CreateInterfaceValue(result_local_ref, lir::VTableRef { i: vtable_i }, value_ref));
+
+                (lir::ValueRef::Local(result_local_ref), *typ)
+            }
+            Value::Function(typ, func_ref, closure) => {
+                let closure_ref = if self.functions[func_ref.i].capture_types.is_empty() {
+                    lir::ValueRef::None
+                } else {
+                    self.export_value(frame, block, closure).0
+                };
+                let result_local_ref = Self::new_temp_local(frame);
+
+                block.code.push(lir::Instruction::CreateFunctionValue(result_local_ref, *typ, *func_ref, closure_ref));
+
+                (lir::ValueRef::Local(result_local_ref), *typ)
+            }
+            Value::None => (lir::ValueRef::None, Type::None),
+            Value::Bool(value) => (lir::ValueRef::Bool(*value), Type::Bool),
+            Value::Int(value) => (lir::ValueRef::Int(*value), Type::Int),
+            Value::Float(value) => (lir::ValueRef::Float(*value), Type::Float),
+            Value::Type(typ) => (lir::ValueRef::Type(*typ), Type::Type)
+        }
+    }
+
+    // Types are values, so any expression can be written where a type is expected
+    fn eval_type(&mut self, frame: &mut ComptimeStackFrame, ir: &ir::IR) -> Result<Type, Diagnostic> {
+        match self.eval_ir(frame, ir)? {
+            Value::Type(typ) => Ok(typ),
+            value => Err(self.mismatched_types(&ir.location, Type::Type, value.type_of()))
+        }
+    }
+
+    // The capture types, the values of the comptime captures and the values of the runtime ones
+    fn eval_captures(frame: &ComptimeStackFrame, captures: &[ir::CaptureFrom]) -> (Vec<Type>, Vec<Option<Value>>, Vec<Value>) {
+        let mut capture_types = Vec::with_capacity(captures.len());
+        let mut comptime_captures = Vec::with_capacity(captures.len());
+        let mut values = Vec::with_capacity(captures.len());
+        for capture_from in captures {
+            let value = Self::captured_value(frame, capture_from);
+
+            capture_types.push(value.type_of());
+
+            if capture_from.comptime() {
+                comptime_captures.push(Some(value));
+            } else {
+                comptime_captures.push(None);
+                values.push(value);
+            }
+        }
+
+        (capture_types, comptime_captures, values)
+    }
+
+    // The value of a captured variable which is known at compile time
+    fn captured_value(frame: &ComptimeStackFrame, capture_from: &ir::CaptureFrom) -> Value {
+        let value = match capture_from {
+            ir::CaptureFrom::Capture(capture_ref) => frame.capture_values.get(capture_ref),
+            ir::CaptureFrom::Param(param_ref) => frame.param_values.get(param_ref),
+            ir::CaptureFrom::Local(local_ref) => frame.local_values.get(local_ref)
+        };
+
+        value.expect("Missing captured value").clone()
+    }
+
+    fn captured_runtime_ref(frame: &ComptimeStackFrame, capture_from: &ir::CaptureFrom) -> (lir::ValueRef, Type) {
+        match capture_from {
+            ir::CaptureFrom::Capture(capture_ref) => {
+                let lir_capture_ref = *frame.runtime_capture_map.get(capture_ref).expect("Missing capture map");
+
+                (lir::ValueRef::Capture(lir_capture_ref), frame.capture_types[capture_ref.i])
+            }
+            ir::CaptureFrom::Param(param_ref) => {
+                let lir_param_ref = *frame.runtime_param_map.get(param_ref).expect("Missing param map");
+
+                (lir::ValueRef::Param(lir_param_ref), frame.param_types[param_ref.i])
+            }
+            ir::CaptureFrom::Local(local_ref) => {
+                let lir_local_ref = *frame.runtime_local_map.get(local_ref).expect("Missing local map");
+                let typ = frame.local_types.get(local_ref).expect("Captured local before assignment").typ;
+
+                (lir::ValueRef::Local(lir_local_ref), typ)
+            }
+        }
+    }
+
+    // Captured vars are cells, their value is read from the cell
+    fn var_value(
+        &mut self,
+        frame: &mut ComptimeStackFrame,
+        block: &mut lir::BasicBlock,
+        value_ref: lir::ValueRef,
+        typ: Type
+    ) -> (lir::ValueRef, Type) {
+        match typ {
+            Type::Cell(cell_ref) => {
+                let result_local_ref = Self::new_temp_local(frame);
+
+                block.code.push(lir::Instruction::CellGet(result_local_ref, typ, value_ref));
+
+                (lir::ValueRef::Local(result_local_ref), self.types.cell_type(cell_ref).element)
+            }
+            _ => (value_ref, typ)
+        }
+    }
+
+    fn var_value_of(value: &Value) -> Value {
+        match value {
+            Value::Cell(_, cell) => cell.borrow().clone(),
+            value => value.clone()
+        }
+    }
+
+    fn assign_cell(&mut self, cell: &RefCell<Value>, value: Value, location: &ast::Location) -> Result<(), Diagnostic> {
+        let typ = cell.borrow().type_of();
+        let value = self.coerce_value(value, typ, location)?;
+
+        *cell.borrow_mut() = value;
+
+        Ok(())
+    }
+
+    fn cell_element_type(&self, cell_type: Type) -> Type {
+        match cell_type {
+            Type::Cell(cell_ref) => self.types.cell_type(cell_ref).element,
+            typ => panic!("Expected a cell type, got {:?}", typ)
+        }
+    }
+
+    fn closure_type(&self, closure: Type) -> ir::ClosureType {
+        match closure {
+            Type::Closure(closure_ref) => self.types.closure_type(closure_ref).clone(),
+            typ => panic!("Expected a closure type, got {:?}", typ)
+        }
+    }
+
+    fn mismatched_types(&self, location: &ast::Location, expected: Type, found: Type) -> Diagnostic {
+        Diagnostic::error("E0300", "Mismatched types")
+            .with_primary(location.clone(), format!(
+                "expected `{}`, found `{}`",
+                self.types.type_name(expected),
+                self.types.type_name(found)
+            ))
+    }
+
+    // Only a recursive call whose return type is still unknown can be assumed, other errors are returned
+    fn recursion_to_assume(&mut self, diagnostic: Diagnostic) -> Result<lir::FunctionRef, Diagnostic> {
+        match self.recursive_call.take() {
+            Some(func_ref) if diagnostic.code.as_deref() == Some("E0301") => Ok(func_ref),
+            _ => Err(diagnostic)
+        }
+    }
+
+    fn set_assumed_return_type(&mut self, func_ref: lir::FunctionRef, typ: Type, location: &ast::Location) {
+        self.functions[func_ref.i].return_type = typ;
+
+        for (inferring_ref, assumed_location) in &mut self.inferring_return_types {
+            if *inferring_ref == func_ref {
+                *assumed_location = Some(location.clone());
+            }
+        }
+    }
+
+    fn unknown_method(&self, location: &ast::Location, target_type: Type, name: &str) -> Diagnostic {
+        let type_name = self.types.type_name(target_type);
+
+        if name == "call" {
+            Diagnostic::error("E0308", format!("Cannot call a value of type `{}`", type_name))
+                .with_primary(location.clone(), "this is not a function")
+        } else {
+            Diagnostic::error("E0308", format!("Cannot find method `{}` on `{}`", name, type_name))
+                .with_primary(location.clone(), "method not found")
+        }
+    }
+
+    fn wrong_arg_count(location: &ast::Location, expected: usize, found: usize) -> Diagnostic {
+        Diagnostic::error("E0306", "Wrong number of arguments")
+            .with_primary(location.clone(), format!("expected {}, found {}", count_of(expected, "argument"), found))
+    }
+
+    fn not_implemented(&self, location: &ast::Location, concrete: Type, interface: Type) -> Diagnostic {
+        Diagnostic::error("E0305", format!("`{}` does not implement the interface", self.types.type_name(concrete)))
+            .with_primary(location.clone(), format!("expected `{}`", self.types.type_name(interface)))
+    }
+
+    fn wrong_field_count(&self, location: &ast::Location, typ: Type, field_count: usize, value_count: usize) -> Diagnostic {
+        Diagnostic::error("E0204", "Wrong number of struct fields")
+            .with_primary(location.clone(), format!("expected {}, found {}", count_of(field_count, "value"), value_count))
+            .with_note(format!("the struct is `{}`", self.types.type_name(typ)))
+    }
+}
+
+// E.g. `1 value` or `2 values`
+fn count_of(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("{} {}", count, noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
+
+enum Coercion {
+    None,
+
+    // A closure used as a value of a function type, the function is its specialization for it
+    FunctionValue(lir::FunctionRef),
+
+    // A concrete value used as an interface value
+    InterfaceValue(lir::VTableRef)
+}
+
+#[derive(Clone, Copy)]
+pub enum ResolvedFn {
+    Intrinsic(ir::IntrinsicFn),
+
+    // A method of an interface value, the type is the interface
+    Method(Type, usize),
+
+    // Calling a value of a function type
+    FunctionValue(Type),
+
+    // A function which is not specialized yet, see `specialize_call`
     TFunction(ir::FunctionTemplateRef),
-    RFunction(ir::FunctionRef)
+    RFunction(lir::FunctionRef)
 }
\ No newline at end of file
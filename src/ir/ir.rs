@@ -24,13 +24,43 @@ pub struct Capture {
 
 #[derive(Debug)]
 pub struct Param {
-    pub typ: Option<Box<IR>>,
+    pub typ: Option<Pattern>,
     pub comptime: bool
 }
 
+// The type of a parameter, matched against the type of the argument when the function is
+// specialized
+#[derive(Debug)]
+pub struct Pattern {
+    pub node: PatternNode,
+    pub location: ast::Location
+}
+
+#[derive(Debug)]
+pub enum PatternNode {
+    // A specific type, evaluated at compile time
+    SpecificValue(IR),
+
+    // `val T`, binds the type of the argument to a comptime local
+    Binding(LocalRef),
+
+    // `List(val T)`, the IR is the type constructor
+    Call(IR, Vec<Pattern>),
+
+    // (param: Pattern, ...): Pattern
+    FunctionType(Vec<Pattern>, Box<Pattern>)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Local {
-    pub comptime: bool
+    pub comptime: bool,
+
+    // A `var`, it can be set more than once
+    pub mutable: bool,
+
+    // A runtime `var` which is captured by a closure. It's stored in a cell so that the function
+    // and the closures share it.
+    pub boxed: bool
 }
 
 #[derive(Debug)]
@@ -50,15 +80,52 @@ pub enum Node {
     LocalRef(LocalRef),
     CaptureRef(CaptureRef),
 
+    // Defines a local or assigns to a `var`
     LocalSet(LocalRef, Box<IR>),
 
+    // Assigns to a captured `var`, the capture is its cell
+    CaptureSet(CaptureRef, Box<IR>),
+
     Block(Vec<IR>),
+    List(Vec<IR>),
+
+    // Struct(name: Type, ...), the field types are evaluated at compile time
+    DefineStruct(Vec<(Box<str>, IR)>),
+
+    // Interface(name: (param: Type): ReturnType, ...)
+    DefineInterface(Vec<MethodSignature>),
+
+    // (param: Type, ...): ReturnType, the types are evaluated at compile time
+    FnType(Vec<IR>, Box<IR>),
     Comptime(Box<IR>),
 
+    // value: Type, the type is evaluated at compile time
+    TypeAssert(Box<IR>, Box<IR>),
+
     Call(Box<str>, Box<IR>, Vec<IR>),
     CreateClosure(FunctionTemplateRef, Vec<CaptureFrom>),
 
-    If(Box<IR>, Box<IR>, Option<Box<IR>>)
+    // Declares the closures of the `rec val`s in a block before they're defined, so that they can
+    // reference themselves and each other
+    DeclareRecursive(Vec<(LocalRef, FunctionTemplateRef)>),
+
+    // Sets the captures of a closure declared by `DeclareRecursive`
+    DefineRecursive(LocalRef, Vec<CaptureFrom>),
+
+    If(Box<IR>, Box<IR>, Option<Box<IR>>),
+
+    // The condition is checked before each run of the body
+    While(Box<IR>, Box<IR>),
+    Break,
+    Continue
+}
+
+// The types are evaluated at compile time
+#[derive(Debug)]
+pub struct MethodSignature {
+    pub name: Box<str>,
+    pub params: Vec<IR>,
+    pub returns: IR
 }
 
 #[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
@@ -84,4 +151,14 @@ pub enum CaptureFrom {
     Capture(CaptureRef),
     Param(ParamRef),
     Local(LocalRef)
+}
+
+impl CaptureFrom {
+    pub fn comptime(&self) -> bool {
+        match self {
+            CaptureFrom::Capture(capture_ref) => capture_ref.comptime,
+            CaptureFrom::Param(param_ref) => param_ref.comptime,
+            CaptureFrom::Local(local_ref) => local_ref.comptime
+        }
+    }
 }
\ No newline at end of file
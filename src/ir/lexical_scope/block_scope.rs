@@ -4,13 +4,17 @@ use crate::ir::LocalRef;
 /// the defined name is only accessible by the children of the block scope.
 pub struct BlockScope {
     /// The referenced names defined in the stack frame but only accessible by children
-    names: Vec<(String, LocalRef)>
+    names: Vec<(String, LocalRef)>,
+
+    /// Is this the body of a loop? `break` and `continue` can be used inside of it.
+    pub is_loop: bool
 }
 
 impl BlockScope {
-    pub fn new() -> Self {
+    pub fn new(is_loop: bool) -> Self {
         Self {
-            names: Vec::new()
+            names: Vec::new(),
+            is_loop
         }
     }
 
@@ -1,5 +1,7 @@
 use crate::ir::lexical_scope::*;
 use crate::ir::{CaptureFrom, CaptureRef, GlobalRef, LocalRef, ParamRef};
+use crate::ast::Location;
+use crate::diagnostics::Diagnostic;
 
 pub struct ScopeStack {
     stack: Vec<Scope>
@@ -28,7 +30,14 @@ impl ScopeStack {
     }
 
     pub fn push_block(&mut self) {
-        let scope = BlockScope::new();
+        let scope = BlockScope::new(false);
+
+        self.stack.push(Scope::BlockScope(scope))
+    }
+
+    /// The body of a loop, see `is_in_loop`
+    pub fn push_loop_block(&mut self) {
+        let scope = BlockScope::new(true);
 
         self.stack.push(Scope::BlockScope(scope))
     }
@@ -81,11 +90,11 @@ impl ScopeStack {
     }
 
     // TODO: Specify `comptime` inside of the LocalRef as well
-    pub fn define_stack_frame_local(&mut self, mut comptime: bool) -> LocalRef {
+    pub fn define_stack_frame_local(&mut self, mut comptime: bool, mutable: bool) -> LocalRef {
         for i in (0..self.stack.len()).rev() {
             match &mut self.stack[i] {
                 Scope::RootScope(_) => {},
-                Scope::StackFrame(frame) => return frame.define_stack_frame_local(comptime),
+                Scope::StackFrame(frame) => return frame.define_stack_frame_local(comptime, mutable),
                 Scope::BlockScope(_) => {}
                 Scope::ComptimePortal(_) => comptime = true
             }
@@ -95,7 +104,16 @@ impl ScopeStack {
     }
 
     pub fn define_local(&mut self, name: String, comptime: bool) -> LocalRef {
-        let local_ref = self.define_stack_frame_local(comptime);
+        self.define_block_local(name, comptime, false)
+    }
+
+    /// Defines a local which can be assigned to, see `lookup_var`
+    pub fn define_var(&mut self, name: String, comptime: bool) -> LocalRef {
+        self.define_block_local(name, comptime, true)
+    }
+
+    fn define_block_local(&mut self, name: String, comptime: bool, mutable: bool) -> LocalRef {
+        let local_ref = self.define_stack_frame_local(comptime, mutable);
 
         let block = match self.stack.last_mut() {
             Some(Scope::BlockScope(block)) => block,
@@ -108,6 +126,20 @@ impl ScopeStack {
         local_ref
     }
 
+    /// Can `break` and `continue` be used here? Loops can't be exited from closures or from
+    /// compile-time code inside of them.
+    pub fn is_in_loop(&self) -> bool {
+        for scope in self.stack.iter().rev() {
+            match scope {
+                Scope::BlockScope(block) if block.is_loop => return true,
+                Scope::BlockScope(_) => {}
+                Scope::RootScope(_) | Scope::StackFrame(_) | Scope::ComptimePortal(_) => return false
+            }
+        }
+
+        false
+    }
+
     pub fn lookup(&mut self, name: &str) -> Result<NameRef, NameAccessError> {
         let mut i = self.stack.len() - 1;
         let mut result = None;
@@ -168,8 +200,6 @@ impl ScopeStack {
         }
 
         if let Some(mut result) = result {
-            i += 1;
-
             let comptime = match result {
                 NameRef::Global(global_ref) => global_ref.comptime,
                 NameRef::Capture(capture_ref) => capture_ref.comptime,
@@ -177,6 +207,21 @@ impl ScopeStack {
                 NameRef::Local(local_ref) => local_ref.comptime
             };
 
+            let mutable = match result {
+                NameRef::Capture(capture_ref) => self.frame_at_or_below(i).captures[capture_ref.i].mutable,
+                NameRef::Local(local_ref) => self.frame_at_or_below(i).locals[local_ref.i].mutable,
+                NameRef::Global(_) | NameRef::Param(_) => false
+            };
+
+            // Vars which are captured need to be shared with the closure, see `ir::Local`
+            if let NameRef::Local(local_ref) = result {
+                if self.stack[i + 1..].iter().any(|scope| matches!(scope, Scope::StackFrame(_))) {
+                    self.frame_at_or_below(i).locals[local_ref.i].captured = true;
+                }
+            }
+
+            i += 1;
+
             // Walk down the stack to process the result
             while i < self.stack.len() {
                 match &mut self.stack[i] {
@@ -194,7 +239,7 @@ impl ScopeStack {
                         match capture_from {
                             None => {}
                             Some(capture_from) => {
-                                let capture_ref = frame.define_capture(capture_from, String::from(name));
+                                let capture_ref = frame.define_capture(capture_from, String::from(name), mutable);
 
                                 result = NameRef::Capture(capture_ref);
                             }
@@ -202,13 +247,8 @@ impl ScopeStack {
                     },
 
                     Scope::ComptimePortal(_) => {
-                        match result {
-                            NameRef::Capture(_) | NameRef::Param(_) | NameRef::Local(_) => {
-                                if !comptime {
-                                    return Err(NameAccessError::CannotReferenceRuntimeNameFromComptime)
-                                }
-                            }
-                            _ => {}
+                        if !comptime {
+                            return Err(NameAccessError::CannotReferenceRuntimeNameFromComptime)
                         }
                     }
                 }
@@ -221,6 +261,40 @@ impl ScopeStack {
 
         Err(NameAccessError::NameNotFound)
     }
+
+    /// Looks up a name which is assigned to. It has to be a `var`, and comptime vars can't be
+    /// assigned to from closures since they only capture their value.
+    pub fn lookup_var(&mut self, name: &str) -> Result<NameRef, NameAccessError> {
+        let result = self.lookup(name)?;
+        let frame = self.frame_at_or_below(self.stack.len() - 1);
+
+        let (mutable, comptime_capture) = match result {
+            NameRef::Local(local_ref) => (frame.locals[local_ref.i].mutable, false),
+            NameRef::Capture(capture_ref) => (frame.captures[capture_ref.i].mutable, capture_ref.comptime),
+            NameRef::Global(_) | NameRef::Param(_) => (false, false)
+        };
+
+        if !mutable {
+            return Err(NameAccessError::CannotAssignToVal);
+        }
+
+        if comptime_capture {
+            return Err(NameAccessError::CannotAssignToComptimeCapture);
+        }
+
+        Ok(result)
+    }
+
+    // The stack frame the scope at `i` is in
+    fn frame_at_or_below(&mut self, i: usize) -> &mut StackFrame {
+        for scope in self.stack[..=i].iter_mut().rev() {
+            if let Scope::StackFrame(frame) = scope {
+                return frame;
+            }
+        }
+
+        panic!("This should not happen - missing StackFrame in scope chain")
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -243,5 +317,31 @@ pub enum NameRef {
 #[derive(Debug, PartialEq)]
 pub enum NameAccessError {
     NameNotFound,
-    CannotReferenceRuntimeNameFromComptime
+    CannotReferenceRuntimeNameFromComptime,
+    CannotAssignToVal,
+    CannotAssignToComptimeCapture
+}
+
+impl NameAccessError {
+    pub fn to_diagnostic(&self, name: &str, location: &Location) -> Diagnostic {
+        match self {
+            NameAccessError::NameNotFound =>
+                Diagnostic::error("E0100", format!("Cannot find `{}`", name))
+                    .with_primary(location.clone(), "not found in this scope"),
+
+            NameAccessError::CannotReferenceRuntimeNameFromComptime =>
+                Diagnostic::error("E0101", format!("Cannot use `{}` at compile time", name))
+                    .with_primary(location.clone(), "this is only known at runtime")
+                    .with_help(format!("declare `{}` with `@val` to make it available at compile time", name)),
+
+            NameAccessError::CannotAssignToVal =>
+                Diagnostic::error("E0104", format!("Cannot assign to `{}`", name))
+                    .with_primary(location.clone(), format!("`{}` is not a `var`", name))
+                    .with_help(format!("declare `{}` with `var` to be able to change it", name)),
+
+            NameAccessError::CannotAssignToComptimeCapture =>
+                Diagnostic::error("E0105", format!("Cannot assign to the compile-time variable `{}` from a closure", name))
+                    .with_primary(location.clone(), "closures only capture the value of compile-time variables")
+        }
+    }
 }
\ No newline at end of file
@@ -21,7 +21,10 @@ pub struct Capture {
     /// The local to capture from the parent stack frame
     pub from: CaptureFrom,
 
-    pub comptime: bool
+    pub comptime: bool,
+
+    /// Is this capturing a `var`?
+    pub mutable: bool
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -37,7 +40,13 @@ pub struct Param {
 pub struct StackFrameLocal {
     // TODO: Include an optional name, for debugging purposes
 
-    pub comptime: bool
+    pub comptime: bool,
+
+    /// Is this a `var`?
+    pub mutable: bool,
+
+    /// Is this captured by a closure?
+    pub captured: bool
 }
 
 impl StackFrame {
@@ -49,24 +58,20 @@ impl StackFrame {
         }
     }
 
-    pub fn define_stack_frame_local(&mut self, comptime: bool) -> LocalRef {
+    pub fn define_stack_frame_local(&mut self, comptime: bool, mutable: bool) -> LocalRef {
         let i = self.locals.len();
 
-        self.locals.push(StackFrameLocal { comptime });
+        self.locals.push(StackFrameLocal { comptime, mutable, captured: false });
 
         LocalRef { i, comptime }
     }
 
-    pub fn define_capture(&mut self, from: CaptureFrom, name: String) -> CaptureRef {
+    pub fn define_capture(&mut self, from: CaptureFrom, name: String, mutable: bool) -> CaptureRef {
         let i = self.captures.len();
 
-        let comptime = match from {
-            CaptureFrom::Capture(capture) => capture.comptime,
-            CaptureFrom::Param(param) => param.comptime,
-            CaptureFrom::Local(local) => local.comptime
-        };
+        let comptime = from.comptime();
 
-        self.captures.push(Capture { name, from, comptime });
+        self.captures.push(Capture { name, from, comptime, mutable });
 
         CaptureRef { i, comptime }
     }
@@ -2,6 +2,7 @@ mod ir;
 mod value;
 mod r#type;
 mod builder;
+mod definition_order;
 mod globals;
 pub(crate) mod lexical_scope;
 mod interpreter;
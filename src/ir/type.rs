@@ -1,4 +1,7 @@
-use crate::ir;
+use std::fmt;
+use crate::{ast, ir};
+use crate::arena::{Arena, ArenaRef};
+use crate::diagnostics::Diagnostic;
 
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
 pub enum Type {
@@ -9,20 +12,290 @@ pub enum Type {
     Float,
     Type,
 
-    // TODO: We'll also need an interface type for functions which the closures can be assigned to
-    Closure(ir::FunctionTemplateRef)
+    // The type of a specific lambda before it's specialized, it can be converted to any `Function`
+    // type which its body type-checks with
+    Closure(ArenaRef<ClosureType>),
 
-    // TODO
-    // Struct(ArenaRef<StructType>),
-    // Interface(ArenaRef<InterfaceType>)
+    // Any function with this signature
+    Function(ArenaRef<FunctionSignature>),
+
+    List(ArenaRef<ListType>),
+    Struct(ArenaRef<StructType>),
+    Interface(ArenaRef<InterfaceType>),
+
+    // Shared storage for a `var` which is captured by closures, it can't be written in the source
+    Cell(ArenaRef<CellType>),
+
+    // Creates types from other types, e.g. `List` in `List(Int)`. There are no values of it.
+    Constructor(TypeConstructor)
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum TypeConstructor {
+    List
+}
+
+impl TypeConstructor {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TypeConstructor::List => "List"
+        }
+    }
+
+    pub fn param_count(&self) -> usize {
+        match self {
+            TypeConstructor::List => 1
+        }
+    }
+
+    pub fn apply(&self, types: &mut Types, args: &[Type]) -> Type {
+        match self {
+            TypeConstructor::List => types.list_of(args[0])
+        }
+    }
+
+    // The arguments the type was created with, if it was created by this constructor. This is how
+    // patterns like `List(val T)` are matched.
+    pub fn unapply(&self, types: &Types, typ: Type) -> Option<Vec<Type>> {
+        match (self, typ) {
+            (TypeConstructor::List, Type::List(list_ref)) => Some(vec![types.list_type(list_ref).element]),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ListType {
+    pub element: Type
+}
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct CellType {
+    pub element: Type
+}
+
+// The function of a closure and what it captured. The function is specialized for the types of the
+// captures and the values of the comptime ones, so they're part of the type.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClosureType {
+    pub template: ir::FunctionTemplateRef,
+
+    // In the order of the function captures
+    pub capture_types: Vec<Type>,
+
+    // The values of the comptime captures, None for the ones which are only known at runtime
+    pub comptime_captures: Vec<Option<ir::Value>>
+}
+
+impl ClosureType {
+    // The captures stored in the closure value
+    pub fn runtime_capture_types(&self) -> Vec<Type> {
+        self.capture_types.iter().zip(&self.comptime_captures)
+            .filter(|(_, value)| value.is_none())
+            .map(|(typ, _)| *typ)
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructType {
+    pub fields: Vec<StructField>
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructField {
+    pub name: Box<str>,
+    pub typ: Type
+}
+
+impl StructType {
+    pub fn field(&self, name: &str) -> Option<(usize, &StructField)> {
+        self.fields.iter().enumerate().find(|(_, field)| field.name.as_ref() == name)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct InterfaceType {
+    pub methods: Vec<InterfaceMethod>
+}
+
+// The signature doesn't include the value the method is called on
+#[derive(Clone, Debug, PartialEq)]
+pub struct InterfaceMethod {
+    pub name: Box<str>,
+    pub signature: FunctionSignature
+}
+
+impl InterfaceType {
+    pub fn method(&self, name: &str) -> Option<(usize, &InterfaceMethod)> {
+        self.methods.iter().enumerate().find(|(_, method)| method.name.as_ref() == name)
+    }
+}
+
+// Owns the definitions of the types which can't be represented by a plain `Type` value. Each
+// distinct type is stored once, so two `Type`s are the same type iff they are equal.
+#[derive(Debug)]
+pub struct Types {
+    lists: Arena<ListType>,
+    structs: Arena<StructType>,
+    interfaces: Arena<InterfaceType>,
+    functions: Arena<FunctionSignature>,
+    closures: Arena<ClosureType>,
+    cells: Arena<CellType>
+}
+
+impl Types {
+    pub fn new() -> Self {
+        Self {
+            lists: Arena::new(),
+            structs: Arena::new(),
+            interfaces: Arena::new(),
+            functions: Arena::new(),
+            closures: Arena::new(),
+            cells: Arena::new()
+        }
+    }
+
+    pub fn list_of(&mut self, element: Type) -> Type {
+        Type::List(self.lists.intern(ListType { element }))
+    }
+
+    pub fn list_type(&self, list_ref: ArenaRef<ListType>) -> &ListType {
+        self.lists.get(list_ref)
+    }
+
+    pub fn cell_of(&mut self, element: Type) -> Type {
+        Type::Cell(self.cells.intern(CellType { element }))
+    }
+
+    pub fn cell_type(&self, cell_ref: ArenaRef<CellType>) -> &CellType {
+        self.cells.get(cell_ref)
+    }
+
+    // Structs are nominal, two definitions with the same fields are still different types
+    pub fn define_struct(&mut self, fields: Vec<StructField>) -> Type {
+        Type::Struct(self.structs.allocate(StructType { fields }))
+    }
+
+    pub fn struct_type(&self, struct_ref: ArenaRef<StructType>) -> &StructType {
+        self.structs.get(struct_ref)
+    }
+
+    // Values don't need to declare which interfaces they implement, any type which has all the
+    // methods can be used
+    pub fn define_interface(&mut self, methods: Vec<InterfaceMethod>) -> Type {
+        Type::Interface(self.interfaces.allocate(InterfaceType { methods }))
+    }
+
+    pub fn interface_type(&self, interface_ref: ArenaRef<InterfaceType>) -> &InterfaceType {
+        self.interfaces.get(interface_ref)
+    }
+
+    // Function types are structural, all functions with the same signature have the same type
+    pub fn function_of(&mut self, signature: FunctionSignature) -> Type {
+        Type::Function(self.functions.intern(signature))
+    }
+
+    pub fn function_type(&self, function_ref: ArenaRef<FunctionSignature>) -> &FunctionSignature {
+        self.functions.get(function_ref)
+    }
+
+    pub fn closure_of(&mut self, closure: ClosureType) -> Type {
+        Type::Closure(self.closures.intern(closure))
+    }
+
+    // A closure type which doesn't capture anything yet, so that the closure can capture itself.
+    // The captures are set with `define_closure`.
+    pub fn declare_closure(&mut self, template: ir::FunctionTemplateRef) -> Type {
+        Type::Closure(self.closures.allocate(ClosureType { template, capture_types: Vec::new(), comptime_captures: Vec::new() }))
+    }
+
+    pub fn define_closure(&mut self, closure_ref: ArenaRef<ClosureType>, closure: ClosureType) {
+        self.closures.set(closure_ref, closure)
+    }
+
+    pub fn closure_type(&self, closure_ref: ArenaRef<ClosureType>) -> &ClosureType {
+        self.closures.get(closure_ref)
+    }
+
+    // How the type is written in the source, for error messages
+    pub fn type_name(&self, typ: Type) -> String {
+        match typ {
+            Type::Any => String::from("Any"),
+            Type::None => String::from("None"),
+            Type::Bool => String::from("Bool"),
+            Type::Int => String::from("Int"),
+            Type::Float => String::from("Float"),
+            Type::Type => String::from("Type"),
+            Type::Closure(_) => String::from("closure"),
+            Type::Function(function_ref) => self.signature_name(self.function_type(function_ref)),
+            Type::List(list_ref) => format!("List({})", self.type_name(self.list_type(list_ref).element)),
+            Type::Struct(struct_ref) => {
+                let fields: Vec<String> = self.struct_type(struct_ref).fields.iter()
+                    .map(|field| format!("{}: {}", field.name, self.type_name(field.typ)))
+                    .collect();
+
+                format!("Struct({})", fields.join(", "))
+            }
+            Type::Interface(interface_ref) => {
+                let methods: Vec<String> = self.interface_type(interface_ref).methods.iter()
+                    .map(|method| format!("{}: {}", method.name, self.signature_name(&method.signature)))
+                    .collect();
+
+                format!("Interface({})", methods.join(", "))
+            }
+            Type::Cell(cell_ref) => format!("Cell({})", self.type_name(self.cell_type(cell_ref).element)),
+            Type::Constructor(constructor) => String::from(constructor.name())
+        }
+    }
+
+    pub fn signature_name(&self, signature: &FunctionSignature) -> String {
+        let params: Vec<String> = signature.params.iter()
+            .map(|param| self.type_name(*param))
+            .collect();
+
+        format!("({}): {}", params.join(", "), self.type_name(signature.returns))
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub enum IntrinsicFn {
-    AddInt
+    AddInt,
+    SubInt,
+    MulInt,
+    DivInt,
+    NegInt,
+    EqInt,
+    NotEqInt,
+    LessInt,
+    LessEqInt,
+    GreaterInt,
+    GreaterEqInt,
+
+    AddFloat,
+    SubFloat,
+    MulFloat,
+    DivFloat,
+    NegFloat,
+    EqFloat,
+    NotEqFloat,
+    LessFloat,
+    LessEqFloat,
+    GreaterFloat,
+    GreaterEqFloat,
+
+    NotBool,
+    EqBool,
+    NotEqBool,
+
+    // The argument is the type of the list elements
+    ListGet(Type),
+
+    // The index and the type of the field
+    StructGet(usize, Type)
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct FunctionSignature {
     pub params: Vec<Type>,
     pub returns: Type
@@ -30,9 +303,159 @@ pub struct FunctionSignature {
 
 // PERFORMANCE: Optimize to not create new objects every time
 impl IntrinsicFn {
-    pub fn signature(&self, _arg_types: &[Type]) -> FunctionSignature {
+    // The operators of Int, Float and Bool values. Unary operators don't have arguments.
+    pub fn operator(typ: Type, name: &str, arg_count: usize) -> Option<IntrinsicFn> {
+        use IntrinsicFn::*;
+
+        Some(match (typ, name, arg_count) {
+            (Type::Int, "+", 1) => AddInt,
+            (Type::Int, "-", 1) => SubInt,
+            (Type::Int, "*", 1) => MulInt,
+            (Type::Int, "/", 1) => DivInt,
+            (Type::Int, "-", 0) => NegInt,
+            (Type::Int, "==", 1) => EqInt,
+            (Type::Int, "!=", 1) => NotEqInt,
+            (Type::Int, "<", 1) => LessInt,
+            (Type::Int, "<=", 1) => LessEqInt,
+            (Type::Int, ">", 1) => GreaterInt,
+            (Type::Int, ">=", 1) => GreaterEqInt,
+
+            (Type::Float, "+", 1) => AddFloat,
+            (Type::Float, "-", 1) => SubFloat,
+            (Type::Float, "*", 1) => MulFloat,
+            (Type::Float, "/", 1) => DivFloat,
+            (Type::Float, "-", 0) => NegFloat,
+            (Type::Float, "==", 1) => EqFloat,
+            (Type::Float, "!=", 1) => NotEqFloat,
+            (Type::Float, "<", 1) => LessFloat,
+            (Type::Float, "<=", 1) => LessEqFloat,
+            (Type::Float, ">", 1) => GreaterFloat,
+            (Type::Float, ">=", 1) => GreaterEqFloat,
+
+            (Type::Bool, "!", 0) => NotBool,
+            (Type::Bool, "==", 1) => EqBool,
+            (Type::Bool, "!=", 1) => NotEqBool,
+
+            _ => return None
+        })
+    }
+
+    pub fn signature(&self, arg_types: &[Type]) -> FunctionSignature {
+        use IntrinsicFn::*;
+
+        let (params, returns) = match self {
+            AddInt | SubInt | MulInt | DivInt => (vec![Type::Int, Type::Int], Type::Int),
+            NegInt => (vec![Type::Int], Type::Int),
+            EqInt | NotEqInt | LessInt | LessEqInt | GreaterInt | GreaterEqInt => (vec![Type::Int, Type::Int], Type::Bool),
+
+            AddFloat | SubFloat | MulFloat | DivFloat => (vec![Type::Float, Type::Float], Type::Float),
+            NegFloat => (vec![Type::Float], Type::Float),
+            EqFloat | NotEqFloat | LessFloat | LessEqFloat | GreaterFloat | GreaterEqFloat => (vec![Type::Float, Type::Float], Type::Bool),
+
+            NotBool => (vec![Type::Bool], Type::Bool),
+            EqBool | NotEqBool => (vec![Type::Bool, Type::Bool], Type::Bool),
+
+            ListGet(element) => (vec![arg_types[0], Type::Int], *element),
+            StructGet(_, field_type) => (vec![arg_types[0]], *field_type)
+        };
+
+        FunctionSignature { params, returns }
+    }
+
+    // The first argument is the value the function is called on
+    pub fn eval(&self, args: &[ir::Value]) -> Result<ir::Value, IntrinsicError> {
+        use IntrinsicFn::*;
+
+        let value = match self {
+            // Same as the compiled code, which wraps on overflow
+            AddInt => ir::Value::Int(args[0].assert_int().wrapping_add(args[1].assert_int())),
+            SubInt => ir::Value::Int(args[0].assert_int().wrapping_sub(args[1].assert_int())),
+            MulInt => ir::Value::Int(args[0].assert_int().wrapping_mul(args[1].assert_int())),
+            NegInt => ir::Value::Int(args[0].assert_int().wrapping_neg()),
+
+            // The compiled code aborts in these cases, see `photon_int_check_div`
+            DivInt => match (args[0].assert_int(), args[1].assert_int()) {
+                (_, 0) => return Err(IntrinsicError::DivisionByZero),
+                (a, b) => ir::Value::Int(a.checked_div(b).ok_or(IntrinsicError::Overflow)?)
+            },
+
+            EqInt => ir::Value::Bool(args[0].assert_int() == args[1].assert_int()),
+            NotEqInt => ir::Value::Bool(args[0].assert_int() != args[1].assert_int()),
+            LessInt => ir::Value::Bool(args[0].assert_int() < args[1].assert_int()),
+            LessEqInt => ir::Value::Bool(args[0].assert_int() <= args[1].assert_int()),
+            GreaterInt => ir::Value::Bool(args[0].assert_int() > args[1].assert_int()),
+            GreaterEqInt => ir::Value::Bool(args[0].assert_int() >= args[1].assert_int()),
+
+            AddFloat => ir::Value::Float(args[0].assert_float() + args[1].assert_float()),
+            SubFloat => ir::Value::Float(args[0].assert_float() - args[1].assert_float()),
+            MulFloat => ir::Value::Float(args[0].assert_float() * args[1].assert_float()),
+            DivFloat => ir::Value::Float(args[0].assert_float() / args[1].assert_float()),
+            NegFloat => ir::Value::Float(-args[0].assert_float()),
+            EqFloat => ir::Value::Bool(args[0].assert_float() == args[1].assert_float()),
+            NotEqFloat => ir::Value::Bool(args[0].assert_float() != args[1].assert_float()),
+            LessFloat => ir::Value::Bool(args[0].assert_float() < args[1].assert_float()),
+            LessEqFloat => ir::Value::Bool(args[0].assert_float() <= args[1].assert_float()),
+            GreaterFloat => ir::Value::Bool(args[0].assert_float() > args[1].assert_float()),
+            GreaterEqFloat => ir::Value::Bool(args[0].assert_float() >= args[1].assert_float()),
+
+            NotBool => ir::Value::Bool(!args[0].assert_bool()),
+            EqBool => ir::Value::Bool(args[0].assert_bool() == args[1].assert_bool()),
+            NotEqBool => ir::Value::Bool(args[0].assert_bool() != args[1].assert_bool()),
+
+            ListGet(_) => {
+                let values = args[0].assert_list();
+                let index = args[1].assert_int();
+
+                if index < 0 || index as usize >= values.len() {
+                    return Err(IntrinsicError::IndexOutOfBounds { index, length: values.len() });
+                }
+
+                values[index as usize].clone()
+            }
+            StructGet(i, _) => args[0].assert_struct()[*i].clone()
+        };
+
+        Ok(value)
+    }
+}
+
+// The errors which the compiled code aborts on, they are reported when the intrinsic runs at
+// compile time
+#[derive(Debug, Clone, PartialEq)]
+pub enum IntrinsicError {
+    IndexOutOfBounds { index: i64, length: usize },
+    DivisionByZero,
+
+    // Only `Int.MIN / -1`, the other operators wrap
+    Overflow
+}
+
+impl IntrinsicError {
+    pub fn to_diagnostic(&self, location: &ast::Location) -> Diagnostic {
         match self {
-            IntrinsicFn::AddInt => FunctionSignature { params: vec![Type::Int, Type::Int], returns: Type::Int }
+            IntrinsicError::IndexOutOfBounds { index, length } =>
+                Diagnostic::error("E0304", "Index out of bounds")
+                    .with_primary(location.clone(), format!("the length is {} but the index is {}", length, index)),
+
+            IntrinsicError::DivisionByZero =>
+                Diagnostic::error("E0309", "Division by zero")
+                    .with_primary(location.clone(), "the divisor is 0"),
+
+            IntrinsicError::Overflow =>
+                Diagnostic::error("E0310", "Integer overflow")
+                    .with_primary(location.clone(), "the result doesn't fit in an `Int`")
+        }
+    }
+}
+
+impl fmt::Display for IntrinsicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntrinsicError::IndexOutOfBounds { index, length } =>
+                write!(f, "Index out of bounds: the length is {} but the index is {}", length, index),
+
+            IntrinsicError::DivisionByZero => write!(f, "Division by zero"),
+            IntrinsicError::Overflow => write!(f, "Integer overflow")
         }
     }
 }
@@ -1,6 +1,7 @@
+use std::cell::RefCell;
 use std::rc::Rc;
-use crate::ir;
 use crate::ir::r#type::Type;
+use crate::lir;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum Value {
@@ -10,8 +11,26 @@ pub enum Value {
     Float(f64),
     Type(Type),
 
+    // The type is the closure type, the values are the runtime captures. The comptime ones are
+    // part of the type.
     // PERFORMANCE: Potential to optimize performance by packing this?
-    Closure(ir::FunctionTemplateRef, Rc<Vec<Value>>)
+    Closure(Type, Rc<Vec<Value>>),
+
+    // The type is the list type itself, not the type of the elements
+    List(Type, Rc<Vec<Value>>),
+
+    // The field values are in the same order as in the struct definition
+    Struct(Type, Rc<Vec<Value>>),
+
+    // The type is the cell type, the value is shared by all copies
+    Cell(Type, Rc<RefCell<Value>>),
+
+    // The type is the interface type, the value has the concrete type which implements it
+    Interface(Type, Rc<Value>),
+
+    // The type is the function type. The function is the specialization of the closure for it, the
+    // closure is kept so that it can also be called at compile time.
+    Function(Type, lir::FunctionRef, Rc<Value>)
 }
 
 impl Value {
@@ -50,13 +69,48 @@ impl Value {
         }
     }
 
-    pub fn assert_closure(&self) -> (ir::FunctionTemplateRef, &Vec<Value>) {
+    pub fn assert_closure(&self) -> &Vec<Value> {
         match self {
-            Value::Closure(func_ref, value) => (*func_ref, value.as_ref()),
+            Value::Closure(_, values) => values.as_ref(),
             _ => panic!("Invalid value: expected Closure, got {:?}", self)
         }
     }
 
+    pub fn assert_list(&self) -> &Vec<Value> {
+        match self {
+            Value::List(_, values) => values.as_ref(),
+            _ => panic!("Invalid value: expected List, got {:?}", self)
+        }
+    }
+
+    pub fn assert_struct(&self) -> &Vec<Value> {
+        match self {
+            Value::Struct(_, values) => values.as_ref(),
+            _ => panic!("Invalid value: expected Struct, got {:?}", self)
+        }
+    }
+
+    pub fn assert_cell(&self) -> &RefCell<Value> {
+        match self {
+            Value::Cell(_, value) => value.as_ref(),
+            _ => panic!("Invalid value: expected Cell, got {:?}", self)
+        }
+    }
+
+    pub fn assert_interface(&self) -> &Value {
+        match self {
+            Value::Interface(_, value) => value.as_ref(),
+            _ => panic!("Invalid value: expected Interface, got {:?}", self)
+        }
+    }
+
+    pub fn assert_function(&self) -> (lir::FunctionRef, &Value) {
+        match self {
+            Value::Function(_, func_ref, closure) => (*func_ref, closure.as_ref()),
+            _ => panic!("Invalid value: expected Function, got {:?}", self)
+        }
+    }
+
     pub fn type_of(&self) -> Type {
         match self {
             Value::None => Type::None,
@@ -64,7 +118,12 @@ impl Value {
             Value::Int(_) => Type::Int,
             Value::Float(_) => Type::Float,
             Value::Type(_) => Type::Type,
-            Value::Closure(func_ref, _) => Type::Closure(*func_ref),
+            Value::Closure(typ, _) => *typ,
+            Value::List(typ, _) => *typ,
+            Value::Struct(typ, _) => *typ,
+            Value::Cell(typ, _) => *typ,
+            Value::Interface(typ, _) => *typ,
+            Value::Function(typ, _, _) => *typ
         }
     }
 }
\ No newline at end of file
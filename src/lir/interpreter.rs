@@ -1,10 +1,13 @@
-use crate::ir::{Globals, Value};
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::ir::Value;
 use crate::lir;
 use crate::lir::{BasicBlock, Instruction, ValueRef};
 
 pub struct Interpreter<'a> {
-    pub globals: &'a Globals,
-    pub functions: &'a [lir::Function]
+    pub globals: &'a [lir::Global],
+    pub functions: &'a [lir::Function],
+    pub vtables: &'a [lir::VTable]
 }
 
 // TODO: Optimize into a single stack frame array for locality
@@ -14,8 +17,16 @@ pub struct StackFrame {
     pub captures: Vec<Value>
 }
 
+// How the evaluation of a block ended
+enum BlockExit {
+    End,
+    Break,
+    Continue,
+    Return(Value)
+}
+
 impl <'a> Interpreter<'a> {
-    pub fn eval_call(&self, func: lir::Function, params: Vec<Value>, captures: Vec<Value>) -> Value {
+    pub fn eval_call(&self, func: &lir::Function, params: Vec<Value>, captures: Vec<Value>) -> Value {
         let mut locals = Vec::new();
         locals.resize(func.local_count, Value::None);
 
@@ -25,6 +36,13 @@ impl <'a> Interpreter<'a> {
     }
 
     pub fn eval(&self, frame: &mut StackFrame, block: &BasicBlock) -> Value {
+        match self.eval_block(frame, block) {
+            BlockExit::Return(value) => value,
+            BlockExit::End | BlockExit::Break | BlockExit::Continue => Value::None
+        }
+    }
+
+    fn eval_block(&self, frame: &mut StackFrame, block: &BasicBlock) -> BlockExit {
         for instruction in &block.code {
             match instruction {
                 Instruction::LocalSet(local_ref, value_ref, _) => {
@@ -32,13 +50,130 @@ impl <'a> Interpreter<'a> {
 
                     frame.locals[local_ref.i] = value;
                 }
-                Instruction::CallIntrinsic(local_ref, func, args) => todo!("Support intrinsic calls"),
-                Instruction::Return(value_ref) => return self.resolve(frame, *value_ref),
-                Instruction::If(_, _, _, _, _) => todo!("Support if")
+                Instruction::CallIntrinsic(local_ref, func, arg_refs) => {
+                    let args: Vec<Value> = arg_refs.iter()
+                        .map(|value_ref| self.resolve(frame, *value_ref))
+                        .collect();
+
+                    frame.locals[local_ref.i] = func.eval(&args).unwrap_or_else(|error| panic!("{}", error));
+                }
+                Instruction::CreateList(local_ref, typ, value_refs) => {
+                    let values = value_refs.iter()
+                        .map(|value_ref| self.resolve(frame, *value_ref))
+                        .collect();
+
+                    frame.locals[local_ref.i] = Value::List(*typ, Rc::new(values));
+                }
+                Instruction::CreateStruct(local_ref, typ, value_refs) => {
+                    let values = value_refs.iter()
+                        .map(|value_ref| self.resolve(frame, *value_ref))
+                        .collect();
+
+                    frame.locals[local_ref.i] = Value::Struct(*typ, Rc::new(values));
+                }
+                Instruction::CreateClosure(local_ref, typ, value_refs) => {
+                    let values = value_refs.iter()
+                        .map(|value_ref| self.resolve(frame, *value_ref))
+                        .collect();
+
+                    frame.locals[local_ref.i] = Value::Closure(*typ, Rc::new(values));
+                }
+                Instruction::CreateCell(local_ref, typ, value_ref) => {
+                    let value = self.resolve(frame, *value_ref);
+
+                    frame.locals[local_ref.i] = Value::Cell(*typ, Rc::new(RefCell::new(value)));
+                }
+                Instruction::CellGet(local_ref, _, cell_ref) => {
+                    let value = self.resolve(frame, *cell_ref).assert_cell().borrow().clone();
+
+                    frame.locals[local_ref.i] = value;
+                }
+                Instruction::CellSet(_, cell_ref, value_ref) => {
+                    let value = self.resolve(frame, *value_ref);
+
+                    *self.resolve(frame, *cell_ref).assert_cell().borrow_mut() = value;
+                }
+                Instruction::CreateInterfaceValue(local_ref, vtable_ref, value_ref) => {
+                    let value = self.resolve(frame, *value_ref);
+                    let interface = self.vtables[vtable_ref.i].interface;
+
+                    frame.locals[local_ref.i] = Value::Interface(interface, Rc::new(value));
+                }
+                Instruction::CallMethod(local_ref, interface, i, arg_refs) => {
+                    let mut args: Vec<Value> = arg_refs.iter()
+                        .map(|value_ref| self.resolve(frame, *value_ref))
+                        .collect();
+
+                    // The methods in the vtable take the concrete value instead of the interface value
+                    let value = args[0].assert_interface().clone();
+                    let vtable = self.vtables.iter()
+                        .find(|vtable| vtable.concrete == value.type_of() && vtable.interface == *interface)
+                        .expect("Missing vtable");
+                    args[0] = value;
+
+                    frame.locals[local_ref.i] = self.eval_call(&self.functions[vtable.methods[*i].i], args, Vec::new());
+                }
+                Instruction::CreateFunctionValue(local_ref, typ, func_ref, closure_ref) => {
+                    let closure = self.resolve(frame, *closure_ref);
+
+                    frame.locals[local_ref.i] = Value::Function(*typ, *func_ref, Rc::new(closure));
+                }
+                Instruction::CallFunctionValue(local_ref, _, arg_refs) => {
+                    let function = self.resolve(frame, arg_refs[0]);
+                    let (func_ref, closure) = function.assert_function();
+                    let func = &self.functions[func_ref.i];
+
+                    let args = arg_refs[1..].iter()
+                        .map(|value_ref| self.resolve(frame, *value_ref))
+                        .collect();
+
+                    let captures = if func.capture_types.is_empty() {
+                        Vec::new()
+                    } else {
+                        closure.assert_closure().clone()
+                    };
+
+                    frame.locals[local_ref.i] = self.eval_call(func, args, captures);
+                }
+                Instruction::Call(local_ref, func_ref, arg_refs) => {
+                    let func = &self.functions[func_ref.i];
+                    let mut args: Vec<Value> = arg_refs.iter()
+                        .map(|value_ref| self.resolve(frame, *value_ref))
+                        .collect();
+
+                    // The closure is passed after the arguments if the function captures values
+                    let captures = if func.capture_types.is_empty() {
+                        Vec::new()
+                    } else {
+                        args.pop().expect("Missing closure argument").assert_closure().clone()
+                    };
+
+                    frame.locals[local_ref.i] = self.eval_call(func, args, captures);
+                }
+                Instruction::Return(value_ref) => return BlockExit::Return(self.resolve(frame, *value_ref)),
+                Instruction::If(_, cond_ref, then_block, else_block, _) => {
+                    let block = if self.resolve(frame, *cond_ref).assert_bool() { then_block } else { else_block };
+
+                    match self.eval_block(frame, block) {
+                        BlockExit::End => {}
+                        exit => return exit
+                    }
+                }
+                Instruction::Loop(body) => {
+                    loop {
+                        match self.eval_block(frame, body) {
+                            BlockExit::End | BlockExit::Continue => {}
+                            BlockExit::Break => break,
+                            BlockExit::Return(value) => return BlockExit::Return(value)
+                        }
+                    }
+                }
+                Instruction::Break => return BlockExit::Break,
+                Instruction::Continue => return BlockExit::Continue
             }
         }
 
-        Value::None
+        BlockExit::End
     }
 
     #[inline]
@@ -48,6 +183,9 @@ impl <'a> Interpreter<'a> {
             ValueRef::Bool(value) => Value::Bool(value),
             ValueRef::Int(value) => Value::Int(value),
             ValueRef::Float(value) => Value::Float(value),
+            ValueRef::Type(typ) => Value::Type(typ),
+            ValueRef::Global(global_ref) => self.globals[global_ref.i].value.clone(),
+            ValueRef::Capture(capture_ref) => frame.captures[capture_ref.i].clone(),
             ValueRef::Param(param_ref) => frame.params[param_ref.i].clone(),
             ValueRef::Local(local_ref) => frame.locals[local_ref.i].clone()
         }
@@ -3,15 +3,40 @@ use crate::ir;
 #[derive(Debug)]
 pub struct Module {
     pub functions: Vec<Function>,
-    pub main: Function
+    pub main: Function,
+    pub globals: Vec<Global>,
+    pub types: ir::Types,
+    pub vtables: Vec<VTable>
+}
+
+// The methods of `concrete` which implement `interface`, in the order of the interface methods.
+// Each function takes the concrete value as its first parameter.
+#[derive(Debug)]
+pub struct VTable {
+    pub concrete: ir::Type,
+    pub interface: ir::Type,
+    pub methods: Vec<FunctionRef>
+}
+
+// Module-level storage for the runtime globals which are used, the value is the initial one
+#[derive(Debug)]
+pub struct Global {
+    pub typ: ir::Type,
+    pub value: ir::Value
 }
 
 #[derive(Debug)]
 pub struct Function {
+    // If the function captures values, it takes the closure with them as a last parameter
     pub capture_types: Vec<ir::Type>,
     pub param_types: Vec<ir::Type>,
     pub return_type: ir::Type,
     pub local_count: usize,
+
+    // The locals which can be set more than once, e.g. `var`s. The others are set at most once on
+    // each path through the function.
+    pub mutable_locals: Vec<LocalRef>,
+
     pub body: BasicBlock
 }
 
@@ -25,12 +50,54 @@ pub enum Instruction {
     LocalSet(LocalRef, ValueRef, ir::Type),
 
     CallIntrinsic(LocalRef, ir::IntrinsicFn, Vec<ValueRef>),
-    // Call(FunctionRef, Vec<IR>),
-    // CreateClosure(FunctionRef, Vec<CaptureFrom>),
+
+    // The type is the list type, see `ir::Types::list_of`
+    CreateList(LocalRef, ir::Type, Vec<ValueRef>),
+
+    // The type is the struct type, the values are in the order of the fields
+    CreateStruct(LocalRef, ir::Type, Vec<ValueRef>),
+
+    // Wraps a concrete value so it can be used where an interface is expected
+    CreateInterfaceValue(LocalRef, VTableRef, ValueRef),
+
+    // The type is the interface type and the number is the index of the method in it. The first
+    // argument is the interface value.
+    CallMethod(LocalRef, ir::Type, usize, Vec<ValueRef>),
+
+    // The type is the closure type, the values are its runtime captures in order
+    CreateClosure(LocalRef, ir::Type, Vec<ValueRef>),
+
+    // The type is the cell type, the value is the initial one. See `ir::Types::cell_of`.
+    CreateCell(LocalRef, ir::Type, ValueRef),
+
+    // The type is the cell type, the value is the cell
+    CellGet(LocalRef, ir::Type, ValueRef),
+
+    // The first value is the cell, the second one is the new value
+    CellSet(ir::Type, ValueRef, ValueRef),
+
+    // A value of the `Function` type which calls the function. The value is the closure the
+    // function gets its captures from, None if it doesn't capture anything.
+    CreateFunctionValue(LocalRef, ir::Type, FunctionRef, ValueRef),
+
+    // The type is the function type. The first argument is the function value.
+    CallFunctionValue(LocalRef, ir::Type, Vec<ValueRef>),
+
+    // A direct call to a function which is known at compile time. If the function captures values,
+    // the last argument is the closure.
+    Call(LocalRef, FunctionRef, Vec<ValueRef>),
 
     Return(ValueRef),
 
-    If(LocalRef, ValueRef, BasicBlock, BasicBlock, ir::Type)
+    // Both blocks end by setting the local to their result, the type is the type of the result
+    If(LocalRef, ValueRef, BasicBlock, BasicBlock, ir::Type),
+
+    // Runs the block repeatedly until it's exited with a `Break`
+    Loop(BasicBlock),
+
+    // Exit the innermost loop, or go back to its start
+    Break,
+    Continue
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -39,13 +106,28 @@ pub enum ValueRef {
     Bool(bool),
     Int(i64),
     Float(f64),
-    // Global(GlobalRef),
-    // Const(ConstRef),
-    // Capture(CaptureRef),
+
+    // Only valid at compile time, types can't be used by the compiled code
+    Type(ir::Type),
+
+    Global(GlobalRef),
+    Capture(CaptureRef),
     Param(ParamRef),
     Local(LocalRef)
 }
 
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct FunctionRef { pub i: usize }
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct VTableRef { pub i: usize }
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct GlobalRef { pub i: usize }
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub struct CaptureRef { pub i: usize }
+
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub struct ParamRef { pub i: usize }
 
@@ -3,6 +3,7 @@ extern crate core;
 use crate::llvm_test::llvm_test;
 
 mod ast;
+mod diagnostics;
 mod tests;
 mod mir;
 mod llvm_test;
@@ -14,6 +15,7 @@ mod ir;
 mod compiler;
 mod ref_registry;
 mod vec_map;
+mod arena;
 mod lir;
 
 fn main() {
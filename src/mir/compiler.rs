@@ -5,7 +5,10 @@ use std::borrow::Borrow;
 use crate::old_lir::Globals;
 
 #[derive(Debug)]
-pub enum CompileError {}
+pub enum CompileError {
+    // The AST has `Error` nodes where the parser recovered
+    ParseError
+}
 
 pub struct Compiler {
     pub const_strings: Vec<Box<str>>,
@@ -25,7 +28,9 @@ impl Compiler {
             body: Box::new(ast),
 
             // TODO: Signal that it doesn't have a return type, not that we don't know it yet
-            return_type: None
+            return_type: None,
+
+            doc_comment: None
         };
 
         // TODO: Populate both of these with the default types like `Int`, `Bool`, `Float`, etc.
@@ -155,7 +160,7 @@ impl Compiler {
                 }
             },
 
-            ast::Value::Let { name, value, recursive, comptime } => {
+            ast::Value::Let { name, value, recursive, comptime, .. } => {
                 if recursive {
                     todo!("Support rec vals - should be enough to call define before compile_ast(value)")
                 }
@@ -268,12 +273,18 @@ impl Compiler {
 
             ast::Value::FnType { .. } => todo!("Support fn type definitions"),
             ast::Value::TypeAssert { .. } => todo!("Support type asserts"),
+            ast::Value::Assign { .. } => todo!("Support assignments"),
+            ast::Value::While { .. } | ast::Value::Break | ast::Value::Continue => todo!("Support loops"),
 
             ast::Value::CompileTimeExpr(ast) => {
                 let export_ref = self.compile_comptime_expr(scope, *ast)?;
 
                 mir::Node::CompileTimeGet(export_ref)
             }
+
+            ast::Value::List(_) => todo!("Support list literals"),
+
+            ast::Value::Error => return Err(CompileError::ParseError)
         };
 
         Ok(mir::MIR {
@@ -1,6 +1,7 @@
 use std::time::Instant;
-use crate::{ast, compiler, ir};
-use crate::ir::Globals;
+use crate::{ast, compiler, ir, lir};
+use crate::diagnostics::Renderer;
+use crate::ir::{Globals, Value};
 
 #[test]
 fn test_literals() {
@@ -36,19 +37,837 @@ fn test_add() {
     "), 42);
 }
 
-fn run<T>(code: &str) -> T {
+#[test]
+fn test_lists() {
+    assert_eq!(run::<i64>("
+        val xs = [1, 2, 3]
+
+        xs[1]
+    "), 2);
+
+    assert_eq!(run::<i64>("
+        val a = 40
+        val xs = [a, a + 1, a + 2]
+
+        xs[0] + xs[2]
+    "), 82);
+}
+
+#[test]
+fn test_nested_lists() {
+    assert_eq!(run::<i64>("
+        val xs = [[1, 2], [3, 4, 5]]
+
+        xs[1][2]
+    "), 5);
+}
+
+#[test]
+fn test_structs() {
+    assert_eq!(run::<i64>("
+        @val Point = Struct(x: Int, y: Int)
+
+        val point = Point(40, 2)
+
+        point.x + point.y
+    "), 42);
+}
+
+#[test]
+fn test_nested_structs() {
+    assert_eq!(run::<i64>("
+        @val Point = Struct(x: Int, y: Int)
+        @val Line = Struct(from: Point, to: Point)
+
+        val line = Line(Point(1, 2), Point(3, 39))
+        val points = [line.from, line.to]
+
+        points[0].y + points[1].y + line.to.x
+    "), 44);
+}
+
+#[test]
+fn test_shadowing_struct() {
+    assert_eq!(run::<i64>("
+        val Struct = (a: Int) a + 1
+
+        Struct(41)
+    "), 42);
+}
+
+#[test]
+fn test_interfaces() {
+    assert_eq!(run::<i64>("
+        @val HasX = Interface(x: (): Int)
+
+        @val Point = Struct(x: Int, y: Int)
+        @val Reversed = Struct(y: Int, x: Int)
+        @val Pair = Struct(a: HasX, b: HasX)
+
+        val pair = Pair(Point(40, 1), Reversed(100, 2))
+
+        pair.a.x + pair.b.x
+    "), 42);
+}
+
+#[test]
+fn test_interfaces_returning_interfaces() {
+    assert_eq!(run::<i64>("
+        @val HasX = Interface(x: (): Int)
+        @val HasPoint = Interface(point: (): HasX)
+
+        @val Point = Struct(x: Int, y: Int)
+        @val Circle = Struct(radius: Int, point: HasX)
+        @val Shapes = Struct(a: HasPoint, b: HasPoint)
+
+        val shapes = Shapes(Circle(10, Point(40, 0)), Circle(20, Point(2, 0)))
+
+        shapes.a.point.x + shapes.b.point.x
+    "), 42);
+}
+
+#[test]
+fn test_converting_between_interfaces() {
+    assert_eq!(run::<i64>("
+        @val HasXY = Interface(x: (): Int, y: (): Int)
+        @val HasX = Interface(x: (): Int)
+        @val Point = Struct(x: Int, y: Int)
+
+        val xy: HasXY = Point(40, 2)
+        val x: HasX = xy
+
+        x.x + xy.y
+    "), 42);
+}
+
+#[test]
+fn test_interpreting_interfaces() {
+    assert_eq!(interpret("
+        @val HasX = Interface(x: (): Int)
+        @val HasPoint = Interface(point: (): HasX)
+        @val Point = Struct(x: Int, y: Int)
+        @val Circle = Struct(radius: Int, point: HasX)
+
+        val circle: HasPoint = Circle(10, Point(40, 2))
+        val x: HasX = circle.point
+
+        x.x + circle.point.x - 38
+    "), ir::Value::Int(42));
+}
+
+#[test]
+fn test_function_values() {
+    assert_eq!(run::<i64>("
+        @val IntFn = (x: Int): Int
+        @val Holder = Struct(f: IntFn, g: IntFn)
+
+        val holder = Holder((x) x + 1, (x: Int): Int { x + x })
+        val fs = [holder.f, holder.g]
+        val f = fs[0]
+        val g = fs[1]
+
+        f(g(20))
+    "), 41);
+}
+
+#[test]
+fn test_interpreting_function_values() {
+    assert_eq!(interpret("val inc: (n: Int): Int = (n: Int) n + 1\ninc(41)"), ir::Value::Int(42));
+
+    assert_eq!(interpret("
+        @val IntFn = (x: Int): Int
+        @val Holder = Struct(f: IntFn)
+
+        val offset = 40
+        val holder = Holder((x: Int) x + offset)
+        val f = holder.f
+
+        f(2)
+    "), ir::Value::Int(42));
+}
+
+#[test]
+fn test_type_asserts() {
+    assert_eq!(run::<i64>("
+        @val HasX = Interface(x: (): Int)
+        @val Point = Struct(x: Int, y: Int)
+
+        val a: Int = 40
+        val point: HasX = Point(2, 0)
+
+        val result: Int = a + point.x
+
+        result
+    "), 42);
+}
+
+#[test]
+fn test_generic_functions() {
+    assert_eq!(run::<i64>("
+        @val Point = Struct(x: Int, y: Int)
+
+        val first = (a: val T, b: T): T { a }
+
+        first(Point(40, 0), Point(1, 2)).x + first(2, 3)
+    "), 42);
+}
+
+#[test]
+fn test_patterns_in_function_types() {
+    assert_eq!(run::<i64>("
+        val apply = (f: (n: val T): T, value: T) f(value)
+        val inc: (n: Int): Int = (n: Int) n + 1
+
+        apply(inc, 41)
+    "), 42);
+}
+
+#[test]
+fn test_closures_for_patterns_in_function_types() {
+    assert_eq!(run::<i64>("
+        val apply = (f: (n: val T): T, value: T) f(value)
+        val offset = 2
+
+        apply((n: Int) n + 1, 39) + apply((n: Int) n + offset, 0)
+    "), 42);
+}
+
+#[test]
+fn test_call_patterns() {
+    assert_eq!(run::<i64>("
+        val first = (xs: List(val T)): T { xs[0] }
+        val xs: List(Int) = [40, 1]
+
+        first(xs) + first([[2]])[0]
+    "), 42);
+}
+
+#[test]
+fn test_comptime_params() {
+    assert_eq!(run::<i64>("
+        val add = (@n: Int, x: Int) x + n
+        val convert = (@T, x: T): T { x }
+
+        add(@1, 40) + add(@0, convert(@Int, 1))
+    "), 42);
+}
+
+#[test]
+fn test_reusing_specializations() {
+    let module = specialize("
+        val add = (@n: Int, x: Int) x + n
+        val first = (a: val T, b: T) a
+
+        add(@1, 40) + add(@1, 0) + add(@2, first(1, 2)) + first(3, 4)
+    ");
+
+    assert_eq!(module.functions.len(), 3);
+}
+
+#[test]
+fn test_comptime_blocks() {
+    assert_eq!(run::<i64>("
+        @val add = (a: Int, b: Int) a + b
+        @val Point = Struct(x: Int, y: Int)
+
+        @val point = Point(add(1, 2), 4)
+        @val xs = [point.x, add(point.y, 30)]
+
+        val a = @(add(xs[1], 5))
+
+        a + xs[0]
+    "), 42);
+}
+
+#[test]
+fn test_comptime_function_values() {
+    assert_eq!(run::<i64>("
+        @val inc: (n: Int): Int = (n: Int) n + 1
+        @val a = inc(40)
+
+        inc(a)
+    "), 42);
+}
+
+#[test]
+fn test_comptime_interface_values() {
+    assert_eq!(run::<i64>("
+        @val HasX = Interface(x: (): Int)
+        @val Point = Struct(x: Int, y: Int)
+
+        @val p: HasX = Point(40, 2)
+        @val x = p.x
+
+        p.x + x - 38
+    "), 42);
+}
+
+#[test]
+fn test_comptime_coercions() {
+    assert_eq!(run::<i64>("
+        @val HasX = Interface(x: (): Int)
+        @val Point = Struct(x: Int, y: Int)
+        @val Holder = Struct(value: HasX)
+        @val getX = (value: HasX) value.x
+
+        @var p: HasX = Point(1, 2)
+        @(p = Point(20, 0))
+        @val holder = Holder(Point(20, 0))
+
+        @getX(p) + holder.value.x + 2
+    "), 42);
+}
+
+#[test]
+fn test_comptime_closures_with_captures() {
+    assert_eq!(run::<i64>("
+        @val offset = 10
+        @val addOffset = (x: Int): Int { x + offset }
+
+        @addOffset(32)
+    "), 42);
+}
+
+#[test]
+fn test_param_captures() {
+    assert_eq!(run::<i64>("
+        val add = (a: Int) (b: Int) a + b
+
+        add(1)(41)
+    "), 42);
+}
+
+#[test]
+fn test_nested_captures() {
+    assert_eq!(run::<i64>("
+        val add = (a: Int) (b: Int) (c: Int) a + b + c
+        val addOne = add(1)
+
+        addOne(1)(40)
+    "), 42);
+}
+
+#[test]
+fn test_function_values_with_captures() {
+    assert_eq!(run::<i64>("
+        @val IntFn = (x: Int): Int
+        @val Holder = Struct(f: IntFn)
+
+        val offset = 2
+        val holder = Holder((x) x + offset)
+        val f = holder.f
+
+        f(40)
+    "), 42);
+}
+
+#[test]
+fn test_static_calls() {
+    assert_eq!(run::<i64>("
+        val f = (a: Int) a + 1
+        val g = (a: Int, b: Int): Int { f(a) + f(b) }
+
+        g(1, 39)
+    "), 42);
+}
+
+#[test]
+fn test_closures_as_interface_methods() {
+    assert_eq!(run::<i64>("
+        @val Callable = Interface(call: (x: Int): Int)
+        @val Holder = Struct(f: Callable)
+
+        val offset = 2
+        val holder = Holder((x: Int) x + offset)
+        val f = holder.f
+
+        f(40)
+    "), 42);
+
+    assert_eq!(run::<i64>("
+        @val Callable = Interface(call: (x: Int): Int)
+
+        val offset = 2
+        val inc: (x: Int): Int = (x: Int) x + offset
+        val f: Callable = inc
+
+        f(40)
+    "), 42);
+}
+
+#[test]
+fn test_interpreting_static_calls() {
+    assert_eq!(interpret("
+        val f = (a: Int) a + 1
+        val add = (a: Int) (b: Int) a + b
+
+        add(f(1))(f(39))
+    "), ir::Value::Int(42));
+}
+
+#[test]
+fn test_recursive_functions() {
+    assert_eq!(run::<i64>("
+        rec val fact = (n: Int): Int { if n <= 1 { 1 } else { n * fact(n - 1) } }
+        rec val fib = (n: Int): Int { if n < 2 { n } else { fib(n - 1) + fib(n - 2) } }
+
+        fact(5) + fib(10) - 133
+    "), 42);
+
+    assert_eq!(run::<i64>("
+        rec val fact = (n: Int): Int { if n <= 1 { 1 } else { n * fact(n - 1) } }
+
+        @fact(5) - 78
+    "), 42);
+}
+
+#[test]
+fn test_inferring_return_types_of_recursive_functions() {
+    assert_eq!(run::<i64>("
+        rec val fact = (n: Int) { if n <= 1 { 1 } else { n * fact(n - 1) } }
+        rec val fib = (n: Int) { if n >= 2 { fib(n - 1) + fib(n - 2) } else { n } }
+
+        fact(5) + fib(10) - 133
+    "), 42);
+
+    assert_eq!(run::<i64>("
+        val toInt = (flag: Bool) { if flag { 1 } else { 0 } }
+
+        rec val isEven = (n: Int) { if n == 0 { true } else { isOdd(n - 1) } }
+        rec val isOdd = (n: Int) { if n == 0 { false } else { isEven(n - 1) } }
+
+        toInt(isEven(10)) + toInt(isOdd(7)) * 2 + toInt(isEven(3)) * 4
+    "), 3);
+
+    // The inner closure is specialized again after the return type is assumed
+    assert_eq!(run::<i64>("
+        rec val sum = (n: Int) {
+            val rest = (m: Int) { sum(m) }
+
+            if n == 0 { 0 } else { n + rest(n - 1) }
+        }
+
+        sum(6) + 21
+    "), 42);
+}
+
+#[test]
+fn test_mutually_recursive_functions() {
+    assert_eq!(run::<i64>("
+        val toInt = (flag: Bool) { if flag { 1 } else { 0 } }
+
+        rec val isEven = (n: Int): Bool { if n == 0 { true } else { isOdd(n - 1) } }
+        rec val isOdd = (n: Int): Bool { if n == 0 { false } else { isEven(n - 1) } }
+
+        toInt(isEven(10)) + toInt(isOdd(7)) * 2 + toInt(isEven(3)) * 4
+    "), 3);
+}
+
+#[test]
+fn test_interpreting_recursive_functions() {
+    assert_eq!(interpret("
+        rec val fact = (n: Int): Int { if n <= 1 { 1 } else { n * fact(n - 1) } }
+
+        fact(5)
+    "), ir::Value::Int(120));
+}
+
+#[test]
+fn test_top_level_definitions_in_any_order() {
+    assert_eq!(run::<i64>("
+        lineLength(line)
+
+        val lineLength = (line: Line) { line.to.x - line.from.x + offset }
+        val line = Line(Point(1, 2), Point(41, 2))
+
+        @val Line = Struct(from: Point, to: Point)
+        @val Point = Struct(x: Int, y: Int)
+        @val offset = base + 1
+        @val base = 1
+    "), 42);
+
+    assert_eq!(run::<i64>("
+        val a = 1
+        val b = a + later
+        val a = 40
+        val later = 1
+
+        b + a
+    "), 42);
+}
+
+#[test]
+fn test_recursive_functions_defined_before_their_dependencies() {
+    assert_eq!(run::<i64>("
+        rec val countdown = (n: Int): Int { if n == 0 { stop } else { countdown(n - step) } }
+
+        @val step = 1
+        @val stop = 42
+
+        countdown(10)
+    "), 42);
+}
+
+#[test]
+fn test_ifs() {
+    assert_eq!(run::<i64>("
+        val pick = (flag: Bool, a: Int, b: Int) { if flag { a } else { b } }
+
+        pick(true, 40, 0) + pick(false, 0, 2)
+    "), 42);
+}
+
+#[test]
+fn test_nested_ifs() {
+    assert_eq!(run::<i64>("
+        val choose = (a: Bool, b: Bool): Int {
+            if a {
+                if b then 10 else 20
+            } else {
+                12
+            }
+        }
+
+        choose(true, true) + choose(true, false) + choose(false, true)
+    "), 42);
+}
+
+#[test]
+fn test_ifs_without_else() {
+    assert_eq!(run::<i64>("
+        val run = (flag: Bool) {
+            if flag { 1 }
+            42
+        }
+
+        run(true)
+    "), 42);
+}
+
+#[test]
+fn test_ifs_with_comptime_conditions() {
+    assert_eq!(run::<i64>("
+        val configure = (@fractional: Bool, x: Int) { if fractional { x + 1.5 } else { x } }
+
+        configure(@false, 42)
+    "), 42);
+
+    assert_eq!(run::<i64>("
+        @val pick = (flag: Bool, a: Int, b: Int) { if flag { a } else { b } }
+
+        @pick(false, 1, 42)
+    "), 42);
+}
+
+#[test]
+fn test_int_operators() {
+    assert_eq!(run::<i64>("
+        val a = 50
+        val b = 4
+
+        (a - b) * 2 / 4 + -a + 69
+    "), 42);
+
+    assert_eq!(run::<i64>("
+        @val a = (10 - 4) * 14 / 2
+
+        a
+    "), 42);
+}
+
+#[test]
+fn test_float_operators() {
+    assert_eq!(run::<f64>("
+        val a = 1.5
+        val b = 4.0
+
+        (a * b - 1.0) / 2.0 + -a
+    "), 1.0);
+}
+
+#[test]
+fn test_comparison_operators() {
+    assert_eq!(run::<i64>("
+        val toInt = (flag: Bool) { if flag { 1 } else { 0 } }
+        val a = 1
+        val b = 2
+
+        toInt(a < b) + toInt(a <= a) * 2 + toInt(b > a) * 4 + toInt(a >= b) * 8 + toInt(a == a) * 16 + toInt(a != a) * 32
+    "), 23);
+
+    assert_eq!(run::<i64>("
+        val toInt = (flag: Bool) { if flag { 1 } else { 0 } }
+        val a = 1.5
+        val b = 2.5
+
+        toInt(a < b) + toInt(b <= a) * 2 + toInt(b > a) * 4 + toInt(a >= a) * 8 + toInt(a == b) * 16 + toInt(a != b) * 32
+    "), 45);
+}
+
+#[test]
+fn test_logical_operators() {
+    assert_eq!(run::<i64>("
+        val toInt = (flag: Bool) { if flag { 1 } else { 0 } }
+        val t = true
+        val f = false
+
+        toInt(t and f) + toInt(t or f) * 2 + toInt(!f) * 4 + toInt(t == f) * 8 + toInt(t != f) * 16
+    "), 22);
+
+    assert_eq!(run::<i64>("
+        @val t = true
+
+        if !t or 1 > 2 { 0 } else { 42 }
+    "), 42);
+
+    // The right side would abort if it ran
+    assert_eq!(run::<i64>("
+        val zero = 0
+        val toInt = (flag: Bool) { if flag { 1 } else { 0 } }
+
+        toInt(false and 1 / zero == 0) + toInt(true or 1 / zero == 0) * 2
+    "), 2);
+
+    assert_eq!(run::<i64>("
+        @val zero = 0
+        @val a = false and 1 / zero == 0
+        @val b = true or 1 / zero == 0
+
+        if !a and b { 42 } else { 0 }
+    "), 42);
+}
+
+#[test]
+fn test_globals() {
+    let mut globals = Globals::new();
+    globals.define("answer", Value::Int(40), false);
+    globals.define("two", Value::Int(2), true);
+
+    assert_eq!(run_with_globals::<i64>("
+        val add = (a: Int): Int { a + answer }
+
+        val x: Int = two
+        add(x)
+    ", globals), 42);
+
+    let mut globals = Globals::new();
+    globals.define("enabled", Value::Bool(true), false);
+    globals.define("ratio", Value::Float(0.5), false);
+
+    assert_eq!(run_with_globals::<f64>("
+        if enabled { ratio * 84.0 } else { 0.0 }
+    ", globals), 42.0);
+}
+
+#[test]
+fn test_vars() {
+    assert_eq!(run::<i64>("
+        var a = 1
+        a = a + 10
+        a *= 4
+        a -= 2
+
+        a
+    "), 42);
+
+    assert_eq!(run::<i64>("
+        val clamp = (x: Int, max: Int) {
+            var result = x
+            if result > max { result = max }
+
+            result
+        }
+
+        clamp(100, 40) + clamp(2, 40)
+    "), 42);
+}
+
+#[test]
+fn test_vars_captured_by_closures() {
+    assert_eq!(run::<i64>("
+        var count = 40
+        val increment = () { count += 1 }
+
+        increment()
+        increment()
+
+        count
+    "), 42);
+
+    assert_eq!(interpret("
+        var count = 40
+        val increment = () { count += 1 }
+
+        increment()
+        increment()
+
+        count
+    "), Value::Int(42));
+}
+
+#[test]
+fn test_comptime_vars() {
+    assert_eq!(run::<i64>("
+        @var a = 20
+        @(a += 1)
+        a = a * 2
+
+        a
+    "), 42);
+}
+
+#[test]
+fn test_while_loops() {
+    assert_eq!(run::<i64>("
+        val sum = (n: Int) {
+            var i = 0
+            var total = 0
+
+            while i < n {
+                i += 1
+                total += i
+            }
+
+            total
+        }
+
+        sum(8) + sum(3)
+    "), 42);
+}
+
+#[test]
+fn test_break_and_continue() {
+    assert_eq!(run::<i64>("
+        var i = 0
+        var total = 0
+
+        while true {
+            i += 1
+            if i > 9 then break
+            if i == 3 then continue
+
+            total += i
+        }
+
+        total
+    "), 42);
+}
+
+#[test]
+fn test_nested_loops() {
+    assert_eq!(run::<i64>("
+        var count = 0
+        var i = 0
+
+        while i < 6 {
+            var j = 0
+            while true {
+                j += 1
+                if j > i then break
+
+                val add = () { count += 1 }
+                add()
+            }
+
+            i += 1
+        }
+
+        count + 27
+    "), 42);
+}
+
+#[test]
+fn test_interpreting_loops() {
+    assert_eq!(interpret("
+        var i = 0
+        var total = 0
+
+        while i < 10 {
+            i += 1
+            if i == 5 then continue
+            if i == 9 then break
+
+            total += i
+        }
+
+        total + 11
+    "), Value::Int(42));
+}
+
+#[test]
+fn test_comptime_loops() {
+    assert_eq!(run::<i64>("
+        @var i = 0
+        @var total = 0
+        @(while i < 6 {
+            i += 1
+            total += i
+        })
+
+        total * 2
+    "), 42);
+
+    assert_eq!(run::<i64>("
+        @val factorial = (n: Int) {
+            var result = 1
+            var i = n
+
+            while i > 1 {
+                result *= i
+                i -= 1
+            }
+
+            result
+        }
+
+        @factorial(4) + 18
+    "), 42);
+}
+
+fn specialize(code: &str) -> lir::Module {
     let globals = Globals::new();
 
+    let mut source_map = ast::SourceMap::new();
+    let file = source_map.add_file("<test>", code);
+
+    let ast = ast::Parser::new(source_map.lexer(file)).read_all_as_block()
+        .unwrap_or_else(|error| panic!("{}", Renderer::plain().render(&error.to_diagnostic(file), &source_map)));
+
+    let module = ir::Builder::build_module(ast, &globals)
+        .unwrap_or_else(|diagnostic| panic!("{}", Renderer::plain().render(&diagnostic, &source_map)));
+
+    ir::Interpreter::eval_comptime(&globals, &module)
+        .unwrap_or_else(|diagnostic| panic!("{}", Renderer::plain().render(&diagnostic, &source_map)))
+}
+
+fn interpret(code: &str) -> ir::Value {
+    let module = specialize(code);
+    let interpreter = lir::Interpreter { globals: &module.globals, functions: &module.functions, vtables: &module.vtables };
+
+    interpreter.eval_call(&module.main, Vec::new(), Vec::new())
+}
+
+fn run<T>(code: &str) -> T {
+    run_with_globals(code, Globals::new())
+}
+
+fn run_with_globals<T>(code: &str, globals: Globals) -> T {
+    let mut source_map = ast::SourceMap::new();
+    let file = source_map.add_file("<test>", code);
+
     let instant = Instant::now();
-    let ast = parse(code).expect("Could not parse");
+    let ast = ast::Parser::new(source_map.lexer(file)).read_all_as_block()
+        .unwrap_or_else(|error| panic!("{}", Renderer::plain().render(&error.to_diagnostic(file), &source_map)));
     println!("Parse time: {}ms", instant.elapsed().as_micros() as f64 / 1000f64);
 
     let instant = Instant::now();
-    let module = ir::Builder::build_module(ast, &globals);
+    let module = ir::Builder::build_module(ast, &globals)
+        .unwrap_or_else(|diagnostic| panic!("{}", Renderer::plain().render(&diagnostic, &source_map)));
     println!("IR compile time: {}ms", instant.elapsed().as_micros() as f64 / 1000f64);
 
     let instant = Instant::now();
-    let module = ir::Interpreter::eval_comptime(&globals, module);
+    let module = ir::Interpreter::eval_comptime(&globals, &module)
+        .unwrap_or_else(|diagnostic| panic!("{}", Renderer::plain().render(&diagnostic, &source_map)));
     println!("{:?}", module.main.body);
     println!("Comptime interpret time: {}ms", instant.elapsed().as_micros() as f64 / 1000f64);
 
@@ -64,10 +883,3 @@ fn run<T>(code: &str) -> T {
 
     result
 }
-
-fn parse(code: &str) -> Result<ast::AST, ast::ParseError> {
-    let lexer = ast::Lexer::new("<test>", code.chars());
-    let mut parser = ast::Parser::new(lexer);
-
-    parser.read_all_as_block()
-}
\ No newline at end of file
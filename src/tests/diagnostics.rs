@@ -0,0 +1,641 @@
+use crate::ast::{Location, Parser, Position, SourceMap};
+use crate::diagnostics::{Diagnostic, Renderer};
+use crate::ir::{Builder, Globals, Interpreter, Value};
+
+#[test]
+fn test_rendering_parse_errors() {
+    assert_eq!(render_parse_error("val a = 1\nval = 2"), "\
+error[E0010]: val must be followed by a name
+ --> <test>:2:5
+  |
+2 | val = 2
+  |     ^ unexpected `=`
+");
+
+    assert_eq!(render_parse_error("val s = \"a\\qb\""), "\
+error[E0003]: Invalid escape sequence
+ --> <test>:1:11
+  |
+1 | val s = \"a\\qb\"
+  |           ^ unknown escape
+  |
+  = note: supported escapes are \\r \\n \\t \\0 \\\\ \\\" \\$ \\xHH and \\u{HHHHHH}
+");
+
+    assert_eq!(render_parse_error("val s = \"\"\"\n\ta\n b\n\"\"\""), "\
+error[E0006]: Mixed indentation in a multi-line string
+ --> <test>:3:1
+  |
+3 |  b
+  | ^ this is indented differently than the other lines
+  |
+  = help: indent all lines of the string with the same spaces or tabs
+");
+}
+
+#[test]
+fn test_rendering_recovered_parse_errors() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "val a = 1\nval = 2\na");
+
+    let (ast, errors) = Parser::new(source_map.lexer(file)).read_all_recovering();
+    assert_eq!(errors.len(), 1);
+
+    let diagnostic = match Builder::build_module(ast, &Globals::new()) {
+        Ok(_) => panic!("Expected a compile error"),
+        Err(diagnostic) => diagnostic
+    };
+
+    assert_eq!(Renderer::plain().render(&diagnostic, &source_map), "\
+error[E0108]: Cannot build code with parse errors
+ --> <test>:2:1
+  |
+2 | val = 2
+  | ^^^^^^^ this could not be parsed
+");
+}
+
+#[test]
+fn test_rendering_labels() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("main.ph", "val a = 1\n\n\n\n\n\n\n\nval b = a +\n\ttrue\n");
+
+    let diagnostic = Diagnostic::error("E9999", "Cannot add a bool to an int")
+        .with_primary(location(file, (8, 8, 73), (9, 5, 84)), "this is a bool")
+        .with_secondary(location(file, (0, 4, 4), (0, 5, 5)), "defined here")
+        .with_note("ints can only be added to ints")
+        .with_help("convert the bool first");
+
+    assert_eq!(Renderer::plain().render(&diagnostic, &source_map), "\
+error[E9999]: Cannot add a bool to an int
+  --> main.ph:9:9
+   |
+ 1 | val a = 1
+   |     - defined here
+...
+ 9 | val b = a +
+   |         ^^^
+10 |     true
+   | ^^^^^^^^ this is a bool
+   |
+   = note: ints can only be added to ints
+   = help: convert the bool first
+");
+}
+
+#[test]
+fn test_rendering_with_colors() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "val a = b");
+
+    let diagnostic = build_error(&source_map, file);
+
+    let plain = Renderer::plain().render(&diagnostic, &source_map);
+    let colored = Renderer::colored().render(&diagnostic, &source_map);
+
+    assert!(!plain.contains('\x1b'));
+    assert!(colored.contains("\x1b[1;31merror[E0100]\x1b[0m"));
+    assert_eq!(strip_colors(&colored), plain);
+}
+
+#[test]
+fn test_rendering_name_errors() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "val a = 1\nval b = foo + a");
+
+    let diagnostic = build_error(&source_map, file);
+
+    assert_eq!(Renderer::plain().render(&diagnostic, &source_map), "\
+error[E0100]: Cannot find `foo`
+ --> <test>:2:9
+  |
+2 | val b = foo + a
+  |         ^^^ not found in this scope
+");
+}
+
+#[test]
+fn test_rendering_runtime_name_errors() {
+    let mut globals = Globals::new();
+    globals.define("answer", Value::Int(42), false);
+
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "@(answer)");
+
+    let ast = Parser::new(source_map.lexer(file)).read_all_as_block().unwrap();
+    let diagnostic = match Builder::build_module(ast, &globals) {
+        Ok(_) => panic!("Expected a compile error"),
+        Err(diagnostic) => diagnostic
+    };
+
+    assert_eq!(Renderer::plain().render(&diagnostic, &source_map), "\
+error[E0101]: Cannot use `answer` at compile time
+ --> <test>:1:3
+  |
+1 | @(answer)
+  |   ^^^^^^ this is only known at runtime
+  |
+  = help: declare `answer` with `@val` to make it available at compile time
+");
+}
+
+#[test]
+fn test_rendering_struct_errors() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "@val Point = Struct(x: Int, 42)");
+
+    let diagnostic = build_error(&source_map, file);
+
+    assert_eq!(Renderer::plain().render(&diagnostic, &source_map), "\
+error[E0200]: Invalid struct field
+ --> <test>:1:29
+  |
+1 | @val Point = Struct(x: Int, 42)
+  |                             ^^ expected a field definition
+  |
+  = help: struct fields are written as `name: Type`
+");
+}
+
+#[test]
+fn test_rendering_struct_field_count_errors() {
+    assert_eq!(render_specialize_error("@val P = Struct(x: Int, y: Int)\nval p = P(1)\np"), "\
+error[E0204]: Wrong number of struct fields
+ --> <test>:2:9
+  |
+2 | val p = P(1)
+  |         ^^^^ expected 2 values, found 1
+  |
+  = note: the struct is `Struct(x: Int, y: Int)`
+");
+
+    assert_eq!(render_specialize_error("@val P = Struct(x: Int)\n@val p = P(1, 2)\np"), "\
+error[E0204]: Wrong number of struct fields
+ --> <test>:2:10
+  |
+2 | @val p = P(1, 2)
+  |          ^^^^^^^ expected 1 value, found 2
+  |
+  = note: the struct is `Struct(x: Int)`
+");
+}
+
+#[test]
+fn test_rendering_interface_errors() {
+    assert_eq!(render_specialize_error("@val HasX = Interface(x: (): Int)\nval a: HasX = 1\na"), "\
+error[E0305]: `Int` does not implement the interface
+ --> <test>:2:15
+  |
+2 | val a: HasX = 1
+  |               ^ expected `Interface(x: (): Int)`
+  |
+  = note: `Int` has no method `x`
+");
+
+    assert_eq!(render_specialize_error("@val HasX = Interface(x: (): Int)\n@val P = Struct(x: Float)\nval a: HasX = P(1.5)\na"), "\
+error[E0305]: `Struct(x: Float)` does not implement the interface
+ --> <test>:3:15
+  |
+3 | val a: HasX = P(1.5)
+  |               ^^^^^^ expected `Interface(x: (): Int)`
+  |
+  = note: `x` has the type `(): Float`, expected `(): Int`
+");
+}
+
+#[test]
+fn test_rendering_call_errors() {
+    assert_eq!(render_specialize_error("val a = 1.foo()\na"), "\
+error[E0308]: Cannot find method `foo` on `Int`
+ --> <test>:1:9
+  |
+1 | val a = 1.foo()
+  |         ^^^^^^^ method not found
+");
+
+    assert_eq!(render_specialize_error("val a = 1\na(2)"), "\
+error[E0308]: Cannot call a value of type `Int`
+ --> <test>:2:1
+  |
+2 | a(2)
+  | ^^^^ this is not a function
+");
+
+    assert_eq!(render_specialize_error("val f = (a: Int) a\nf(1, 2)"), "\
+error[E0306]: Wrong number of arguments
+ --> <test>:2:1
+  |
+2 | f(1, 2)
+  | ^^^^^^^ expected 1 argument, found 2
+");
+
+    assert_eq!(render_specialize_error("@val f = (a: Int, b: Int) a\n@val a = f(1)\na"), "\
+error[E0306]: Wrong number of arguments
+ --> <test>:2:10
+  |
+2 | @val a = f(1)
+  |          ^^^^ expected 2 arguments, found 1
+");
+}
+
+#[test]
+fn test_rendering_type_errors() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "val a: Int = 1.5\na");
+
+    let diagnostic = specialize_error(&source_map, file);
+
+    assert_eq!(Renderer::plain().render(&diagnostic, &source_map), "\
+error[E0300]: Mismatched types
+ --> <test>:1:14
+  |
+1 | val a: Int = 1.5
+  |              ^^^ expected `Int`, found `Float`
+");
+}
+
+#[test]
+fn test_rendering_non_type_errors() {
+    assert_eq!(render_specialize_error("val a: 1 = 1\na"), "\
+error[E0300]: Mismatched types
+ --> <test>:1:8
+  |
+1 | val a: 1 = 1
+  |        ^ expected `Type`, found `Int`
+");
+
+    assert_eq!(render_specialize_error("@val Point = Struct(x: 1)\nPoint(1)"), "\
+error[E0300]: Mismatched types
+ --> <test>:1:24
+  |
+1 | @val Point = Struct(x: 1)
+  |                        ^ expected `Type`, found `Int`
+");
+}
+
+#[test]
+fn test_rendering_return_type_errors() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "@val IntFn = (): Int\nval f: IntFn = (): Bool { 42 }\nf()");
+
+    let diagnostic = specialize_error(&source_map, file);
+
+    assert_eq!(Renderer::plain().render(&diagnostic, &source_map), "\
+error[E0300]: Mismatched types
+ --> <test>:2:27
+  |
+2 | val f: IntFn = (): Bool { 42 }
+  |                    ---- expected because of this return type
+  |                           ^^ expected `Bool`, found `Int`
+");
+}
+
+#[test]
+fn test_rendering_function_value_errors() {
+    assert_eq!(render_specialize_error("val f: (a: Int): Int = (a: Int) true\nf(1)"), "\
+error[E0300]: Mismatched types
+ --> <test>:1:24
+  |
+1 | val f: (a: Int): Int = (a: Int) true
+  |                        ^^^^^^^^^^^^^ expected `(Int): Int`, found `(Int): Bool`
+");
+}
+
+#[test]
+fn test_rendering_generic_argument_errors() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "val first = (a: val T, b: T) a\nfirst(1, 1.5)");
+
+    let diagnostic = specialize_error(&source_map, file);
+
+    assert_eq!(Renderer::plain().render(&diagnostic, &source_map), "\
+error[E0300]: Mismatched types
+ --> <test>:2:10
+  |
+2 | first(1, 1.5)
+  |          ^^^ expected `Int`, found `Float`
+");
+}
+
+#[test]
+fn test_rendering_pattern_errors() {
+    assert_eq!(render_specialize_error("val first = (xs: List(val T)) xs[0]\nfirst(1)"), "\
+error[E0300]: Mismatched types
+ --> <test>:1:18
+  |
+1 | val first = (xs: List(val T)) xs[0]
+  |                  ^^^^^^^^^^^ expected a `List`, found `Int`
+");
+
+    assert_eq!(render_specialize_error("val apply = (f: (n: val T): T, value: T) f(value)\napply((n) n + 1, 41)"), "\
+error[E0307]: Cannot infer the parameter types of the closure
+ --> <test>:1:21
+  |
+1 | val apply = (f: (n: val T): T, value: T) f(value)
+  |                     ^^^^^ the type of this parameter is unknown
+  |
+  = help: declare the parameter types of the closure, e.g. `(n: Int) n + 1`
+");
+}
+
+#[test]
+fn test_rendering_comptime_param_closure_errors() {
+    assert_eq!(render_specialize_error("val f: (n: Int): Int = (@n: Int) n + 1\nf(1)"), "\
+error[E0311]: Cannot convert a closure with compile-time parameters to a function
+ --> <test>:1:24
+  |
+1 | val f: (n: Int): Int = (@n: Int) n + 1
+  |                        ^^^^^^^^^^^^^^^ expected `(Int): Int`
+  |
+  = note: functions are called at runtime, so their arguments can't be known at compile time
+");
+}
+
+#[test]
+fn test_rendering_comptime_type_errors() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "@val add = (a: Int, b: Int) a + b\n@val a = add(1, 1.5)\na");
+
+    let diagnostic = specialize_error(&source_map, file);
+
+    assert_eq!(Renderer::plain().render(&diagnostic, &source_map), "\
+error[E0300]: Mismatched types
+ --> <test>:2:17
+  |
+2 | @val a = add(1, 1.5)
+  |                 ^^^ expected `Int`, found `Float`
+");
+}
+
+#[test]
+fn test_rendering_if_branch_errors() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "val f = (flag: Bool) { if flag { 1 } else { 1.5 } }\nf(true)");
+
+    let diagnostic = specialize_error(&source_map, file);
+
+    assert_eq!(Renderer::plain().render(&diagnostic, &source_map), "\
+error[E0300]: Mismatched types
+ --> <test>:1:45
+  |
+1 | val f = (flag: Bool) { if flag { 1 } else { 1.5 } }
+  |                                  - expected because of this branch
+  |                                             ^^^ expected `Int`, found `Float`
+");
+}
+
+#[test]
+fn test_rendering_recursive_val_errors() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "rec val a = 1 + a\na");
+
+    let diagnostic = build_error(&source_map, file);
+
+    assert_eq!(Renderer::plain().render(&diagnostic, &source_map), "\
+error[E0102]: Recursive value `a` is not a function
+ --> <test>:1:13
+  |
+1 | rec val a = 1 + a
+  |             ^^^^^ expected a function
+  |
+  = help: only functions can be defined with `rec val`
+");
+}
+
+#[test]
+fn test_rendering_recursive_val_outside_of_block_errors() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "if true then rec val f = (n: Int): Int { n }");
+
+    let diagnostic = build_error(&source_map, file);
+
+    assert_eq!(Renderer::plain().render(&diagnostic, &source_map), "\
+error[E0107]: Cannot define the recursive value `f` here
+ --> <test>:1:14
+  |
+1 | if true then rec val f = (n: Int): Int { n }
+  |              ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^ this is not directly in a block
+  |
+  = help: recursive values can only be defined in a block, e.g. `{ rec val f = ... }`
+");
+}
+
+#[test]
+fn test_rendering_recursive_return_type_errors() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "rec val f = (n: Int) { if n == 0 { f(1) } else { f(n - 1) } }\nf(1)");
+
+    let diagnostic = specialize_error(&source_map, file);
+
+    assert_eq!(Renderer::plain().render(&diagnostic, &source_map), "\
+error[E0301]: Cannot infer the return type of a recursive function
+ --> <test>:1:50
+  |
+1 | rec val f = (n: Int) { if n == 0 { f(1) } else { f(n - 1) } }
+  |                                                  ^^^^^^^^ called recursively here
+  |
+  = note: it's only inferred from a branch of an `if` which doesn't call the function
+  = help: declare the return type of the function, e.g. `(n: Int): Int { ... }`
+");
+
+    assert_eq!(render_specialize_error("rec val f = (n: Int) { if n == 0 { 0 } else { f(n - 1) == 0 } }\nf(1)"), "\
+error[E0300]: Mismatched types
+ --> <test>:1:47
+  |
+1 | rec val f = (n: Int) { if n == 0 { 0 } else { f(n - 1) == 0 } }
+  |                                    - expected because of this branch
+  |                                               ^^^^^^^^^^^^^ expected `Int`, found `Bool`
+");
+
+    assert_eq!(render_specialize_error("rec val f = (n: Int) { (if n == 0 { 0 } else { f(n - 1) }) == 0 }\nf(1)"), "\
+error[E0300]: Mismatched types
+ --> <test>:1:25
+  |
+1 | rec val f = (n: Int) { (if n == 0 { 0 } else { f(n - 1) }) == 0 }
+  |                         ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^ expected `Int`, found `Bool`
+  |                                     - the recursive calls assume the type of this branch
+");
+}
+
+#[test]
+fn test_rendering_list_errors() {
+    assert_eq!(render_specialize_error("val a = [1, true]\na"), "\
+error[E0300]: Mismatched types
+ --> <test>:1:13
+  |
+1 | val a = [1, true]
+  |          - expected because of the first element
+  |             ^^^^ expected `Int`, found `Bool`
+");
+
+    assert_eq!(render_specialize_error("@val a = []\na"), "\
+error[E0303]: Cannot infer the type of an empty list
+ --> <test>:1:10
+  |
+1 | @val a = []
+  |          ^^ the element type is unknown
+  |
+  = note: the type of a list comes from its first element
+");
+
+    assert_eq!(render_specialize_error("@val a = [1, 2][5]\na"), "\
+error[E0304]: Index out of bounds
+ --> <test>:1:10
+  |
+1 | @val a = [1, 2][5]
+  |          ^^^^^^^^^ the length is 2 but the index is 5
+");
+}
+
+#[test]
+fn test_rendering_int_errors() {
+    assert_eq!(render_specialize_error("@val a = 1 / 0\na"), "\
+error[E0309]: Division by zero
+ --> <test>:1:10
+  |
+1 | @val a = 1 / 0
+  |          ^^^^^ the divisor is 0
+");
+
+    assert_eq!(render_specialize_error("@val a = (-9223372036854775807 - 1) / -1\na"), "\
+error[E0310]: Integer overflow
+ --> <test>:1:11
+  |
+1 | @val a = (-9223372036854775807 - 1) / -1
+  |           ^^^^^^^^^^^^^^^^^^^^^^^^^^^^^^ the result doesn't fit in an `Int`
+");
+}
+
+#[test]
+fn test_rendering_cyclic_definition_errors() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "val a = b + 1\nval b = (n: Int) { a }\na");
+
+    let diagnostic = build_error(&source_map, file);
+
+    assert_eq!(Renderer::plain().render(&diagnostic, &source_map), "\
+error[E0103]: Cyclic definitions: `a` -> `b` -> `a`
+ --> <test>:1:9
+  |
+1 | val a = b + 1
+  |         ^ `a` uses `b` here
+2 | val b = (n: Int) { a }
+  |                    - `b` uses `a` here
+");
+}
+
+#[test]
+fn test_rendering_self_referencing_function_errors() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "val f = (n: Int): Int { f(n) }\nf(1)");
+
+    let diagnostic = build_error(&source_map, file);
+
+    assert_eq!(Renderer::plain().render(&diagnostic, &source_map), "\
+error[E0103]: Cyclic definitions: `f` -> `f`
+ --> <test>:1:25
+  |
+1 | val f = (n: Int): Int { f(n) }
+  |                         ^^^^ `f` uses `f` here
+  |
+  = help: functions which call each other can be defined with `rec val`
+");
+}
+
+#[test]
+fn test_rendering_val_assignment_errors() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "val a = 1\na = 2\na");
+
+    let diagnostic = build_error(&source_map, file);
+
+    assert_eq!(Renderer::plain().render(&diagnostic, &source_map), "\
+error[E0104]: Cannot assign to `a`
+ --> <test>:2:1
+  |
+2 | a = 2
+  | ^^^^^ `a` is not a `var`
+  |
+  = help: declare `a` with `var` to be able to change it
+");
+}
+
+#[test]
+fn test_rendering_break_outside_of_loop_errors() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "while true {\n  val stop = () { break }\n  stop()\n}");
+
+    let diagnostic = build_error(&source_map, file);
+
+    assert_eq!(Renderer::plain().render(&diagnostic, &source_map), "\
+error[E0106]: Cannot use `break` outside of a loop
+ --> <test>:2:19
+  |
+2 |   val stop = () { break }
+  |                   ^^^^^ this is not in the body of a loop
+  |
+  = help: closures and compile-time code can't exit the loops they are in
+");
+}
+
+fn render_parse_error(code: &str) -> String {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", code);
+
+    let error = Parser::new(source_map.lexer(file)).read_all_as_block()
+        .expect_err("Expected a parse error");
+
+    Renderer::plain().render(&error.to_diagnostic(file), &source_map)
+}
+
+fn build_error(source_map: &SourceMap, file: crate::ast::FileId) -> Diagnostic {
+    let ast = Parser::new(source_map.lexer(file)).read_all_as_block().unwrap();
+
+    match Builder::build_module(ast, &Globals::new()) {
+        Ok(_) => panic!("Expected a compile error"),
+        Err(diagnostic) => diagnostic
+    }
+}
+
+fn specialize_error(source_map: &SourceMap, file: crate::ast::FileId) -> Diagnostic {
+    let globals = Globals::new();
+    let ast = Parser::new(source_map.lexer(file)).read_all_as_block().unwrap();
+    let module = Builder::build_module(ast, &globals).unwrap();
+
+    match Interpreter::eval_comptime(&globals, &module) {
+        Ok(_) => panic!("Expected a compile error"),
+        Err(diagnostic) => diagnostic
+    }
+}
+
+fn render_specialize_error(code: &str) -> String {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", code);
+
+    let diagnostic = specialize_error(&source_map, file);
+
+    Renderer::plain().render(&diagnostic, &source_map)
+}
+
+fn location(file: crate::ast::FileId, from: (i32, i32, u32), to: (i32, i32, u32)) -> Location {
+    Location {
+        file,
+        from: Position { line: from.0, column: from.1, offset: from.2 },
+        to: Position { line: to.0, column: to.1, offset: to.2 }
+    }
+}
+
+fn strip_colors(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|c| *c == 'm');
+        } else {
+            result.push(c);
+        }
+    }
+
+    result
+}
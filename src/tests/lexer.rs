@@ -1,5 +1,5 @@
-use crate::ast::{Lexer, TokenValue};
-use crate::ast::TokenValue::{DecimalLiteral, EOF, Equal, Name, Val};
+use crate::ast::{LexerError, SourceMap, TokenValue};
+use crate::ast::TokenValue::{DecimalLiteral, EOF, Equal, IntLiteral, Name, StringLiteral, Val};
 
 #[test]
 fn lexes_some_tokens() {
@@ -8,8 +8,192 @@ fn lexes_some_tokens() {
     ]);
 }
 
+#[test]
+fn lexes_number_literals() {
+    assert_eq!(lex("0xFF 0b1010 0o777"), vec![
+        IntLiteral("0xFF".into()), IntLiteral("0b1010".into()), IntLiteral("0o777".into())
+    ]);
+    assert_eq!(lex("1_000_000 0xFF_FF"), vec![
+        IntLiteral("1_000_000".into()), IntLiteral("0xFF_FF".into())
+    ]);
+    assert_eq!(lex("6.02e23 1e-5 1E+5 1_000.5"), vec![
+        DecimalLiteral("6.02e23".into()), DecimalLiteral("1e-5".into()),
+        DecimalLiteral("1E+5".into()), DecimalLiteral("1_000.5".into())
+    ]);
+}
+
+#[test]
+fn lexes_numbers_followed_by_names() {
+    assert_eq!(lex("1.even 1e"), vec![
+        IntLiteral("1".into()), TokenValue::Dot, Name("even".into()),
+        IntLiteral("1".into()), Name("e".into())
+    ]);
+}
+
+#[test]
+fn reports_invalid_number_literals() {
+    assert!(matches!(lex_error("0x"), LexerError::InvalidNumberLiteral(_)));
+    assert!(matches!(lex_error("0b102"), LexerError::InvalidNumberLiteral(_)));
+    assert!(matches!(lex_error("1__000"), LexerError::InvalidNumberLiteral(_)));
+    assert!(matches!(lex_error("1_"), LexerError::InvalidNumberLiteral(_)));
+    assert!(matches!(lex_error("1e+"), LexerError::InvalidNumberLiteral(_)));
+
+    match lex_error("val a = 0xFG") {
+        LexerError::InvalidNumberLiteral(position) => assert_eq!((position.line, position.column), (0, 11)),
+        error => panic!("Unexpected error {:?}", error)
+    }
+}
+
+#[test]
+fn lexes_string_escapes() {
+    assert_eq!(lex(r#""a\tb\\c\"d""#), vec![StringLiteral("a\tb\\c\"d".into())]);
+    assert_eq!(lex(r#""\x41\x7a""#), vec![StringLiteral("Az".into())]);
+    assert_eq!(lex(r#""\u{1F600} \u{e9}""#), vec![StringLiteral("\u{1F600} \u{e9}".into())]);
+}
+
+#[test]
+fn reports_invalid_string_escapes() {
+    match lex_error("val a = \"abc\\q\"") {
+        LexerError::InvalidEscapeSequence(position) => assert_eq!((position.line, position.column), (0, 12)),
+        error => panic!("Unexpected error {:?}", error)
+    }
+
+    assert!(matches!(lex_error(r#""\x4""#), LexerError::InvalidEscapeSequence(_)));
+    assert!(matches!(lex_error(r#""\xFF""#), LexerError::InvalidEscapeSequence(_)));
+    assert!(matches!(lex_error(r#""\u{}""#), LexerError::InvalidEscapeSequence(_)));
+    assert!(matches!(lex_error(r#""\u{D800}""#), LexerError::InvalidEscapeSequence(_)));
+    assert!(matches!(lex_error(r#""\u{1234567}""#), LexerError::InvalidEscapeSequence(_)));
+    assert!(matches!(lex_error(r#""\u41""#), LexerError::InvalidEscapeSequence(_)));
+    assert!(matches!(lex_error(r#""abc"#), LexerError::UnclosedStringLiteral(_)));
+}
+
+#[test]
+fn lexes_raw_strings() {
+    assert_eq!(lex(r#"r"C:\path\n""#), vec![StringLiteral(r"C:\path\n".into())]);
+    assert_eq!(lex(r##"r#"say "hi""#"##), vec![StringLiteral(r#"say "hi""#.into())]);
+    assert_eq!(lex(r###"r##"a "# b"##"###), vec![StringLiteral(r##"a "# b"##.into())]);
+    assert_eq!(lex("r + r"), vec![Name("r".into()), TokenValue::Plus, Name("r".into())]);
+}
+
+#[test]
+fn lexes_multiline_strings() {
+    let code = "val sql = \"\"\"
+        SELECT *
+          FROM users
+        WHERE id = \\\"1\\\"
+    \"\"\"";
+
+    assert_eq!(lex(code), vec![
+        Val, Name("sql".into()), Equal,
+        StringLiteral("SELECT *\n  FROM users\nWHERE id = \"1\"".into())
+    ]);
+
+    assert_eq!(lex("\"\"\"one \"quoted\" \"\" line\"\"\""), vec![StringLiteral("one \"quoted\" \"\" line".into())]);
+    assert_eq!(lex("\"\"\"\n  a\n\n    b\n  \"\"\""), vec![StringLiteral("a\n\n  b".into())]);
+    assert_eq!(lex("r\"\"\"\n  \\n\n  \"\"\""), vec![StringLiteral("\\n".into())]);
+    assert_eq!(lex("\"\" a"), vec![StringLiteral("".into()), Name("a".into())]);
+
+    // The closing quotes at the start of the line
+    assert_eq!(lex("\"\"\"\nfoo\n\"\"\""), vec![StringLiteral("foo".into())]);
+    assert_eq!(lex("\"\"\"\n  foo\n\n\"\"\""), vec![StringLiteral("foo\n".into())]);
+    assert_eq!(lex("\"\"\"\n\"\"\""), vec![StringLiteral("".into())]);
+}
+
+#[test]
+fn reports_mixed_indentation_in_multiline_strings() {
+    match lex_error("\"\"\"\n    a\n\t\tb\n\"\"\"") {
+        LexerError::MixedIndentation(position) => assert_eq!((position.line, position.column), (2, 0)),
+        error => panic!("Unexpected error {:?}", error)
+    }
+
+    assert_eq!(lex("\"\"\"\n\t a\n\t  b\n\t \"\"\""), vec![StringLiteral("a\n b".into())]);
+}
+
+#[test]
+fn lexes_string_interpolation() {
+    assert_eq!(lex("\"Hello ${name}, you have ${count + 1} items\""), vec![
+        TokenValue::StringStart("Hello ".into()),
+        Name("name".into()),
+        TokenValue::StringMiddle(", you have ".into()),
+        Name("count".into()), TokenValue::Plus, IntLiteral("1".into()),
+        TokenValue::StringEnd(" items".into())
+    ]);
+
+    assert_eq!(lex("\"${ { a } }\" b"), vec![
+        TokenValue::StringStart("".into()),
+        TokenValue::OpenBrace, Name("a".into()), TokenValue::CloseBrace,
+        TokenValue::StringEnd("".into()),
+        Name("b".into())
+    ]);
+
+    assert_eq!(lex("\"\\${a} $a ${\"b${c}\"}\""), vec![
+        TokenValue::StringStart("${a} $a ".into()),
+        TokenValue::StringStart("b".into()),
+        Name("c".into()),
+        TokenValue::StringEnd("".into()),
+        TokenValue::StringEnd("".into())
+    ]);
+
+    assert_eq!(lex("r\"${a}\""), vec![StringLiteral("${a}".into())]);
+}
+
+#[test]
+fn lexes_multiline_string_interpolation() {
+    let code = "\"\"\"
+        Dear ${name},
+          ${body}
+    \"\"\"";
+
+    assert_eq!(lex(code), vec![
+        TokenValue::StringStart("Dear ".into()),
+        Name("name".into()),
+        TokenValue::StringMiddle(",\n  ".into()),
+        Name("body".into()),
+        TokenValue::StringEnd("".into())
+    ]);
+}
+
+#[test]
+fn reports_unclosed_string_interpolation() {
+    assert!(matches!(lex_error("\"a ${b"), LexerError::UnclosedStringLiteral(_)));
+    assert!(matches!(lex_error("\"a ${b}"), LexerError::UnclosedStringLiteral(_)));
+}
+
+#[test]
+fn skips_comments() {
+    assert_eq!(lex("a # comment\nb"), vec![Name("a".into()), TokenValue::NewLine, Name("b".into())]);
+    assert_eq!(lex("a #comment"), vec![Name("a".into())]);
+    assert_eq!(lex("a #[ block ]# b"), vec![Name("a".into()), Name("b".into())]);
+    assert_eq!(lex("a #[ outer #[ inner \n ]# still outer ]# b"), vec![Name("a".into()), Name("b".into())]);
+    assert_eq!(lex("\"# not a comment\""), vec![StringLiteral("# not a comment".into())]);
+
+    assert!(matches!(lex_error("a #[ #[ ]# b"), LexerError::UnclosedBlockComment(_)));
+}
+
+#[test]
+fn attaches_doc_comments_to_the_next_token() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "## Adds numbers\n##   together\nval a");
+    let mut lexer = source_map.lexer(file);
+
+    for _ in 0..2 {
+        let newline = lexer.next().unwrap();
+
+        assert_eq!(newline.value, TokenValue::NewLine);
+        assert_eq!(newline.doc_comment, None);
+    }
+
+    let val = lexer.next().unwrap();
+
+    assert_eq!(val.value, Val);
+    assert_eq!(val.doc_comment.as_deref(), Some("Adds numbers\n  together"));
+    assert_eq!(lexer.next().unwrap().doc_comment, None);
+}
+
 fn lex(code: &str) -> Vec<TokenValue> {
-    let mut lexer = Lexer::new("<test>", code.chars());
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", code);
+    let mut lexer = source_map.lexer(file);
     let mut tokens = Vec::new();
 
     loop {
@@ -22,4 +206,18 @@ fn lex(code: &str) -> Vec<TokenValue> {
     }
 
     tokens
-}
\ No newline at end of file
+}
+
+fn lex_error(code: &str) -> LexerError {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", code);
+    let mut lexer = source_map.lexer(file);
+
+    loop {
+        match lexer.next() {
+            Ok(token) if token.value == EOF => panic!("Expected a lexer error for {}", code),
+            Ok(_) => {},
+            Err(error) => return error
+        }
+    }
+}
@@ -1,5 +1,8 @@
 mod lexer;
 mod parser;
+mod syntax_tree;
+mod source_map;
+mod diagnostics;
 mod old_scope;
 mod old_compiler;
 mod scope;
@@ -218,7 +218,9 @@ fn run<T>(code: &str) -> T {
 }
 
 fn parse(code: &str) -> Result<ast::AST, ast::ParseError> {
-    let lexer = ast::Lexer::new("<test>", code.chars());
+    let mut source_map = ast::SourceMap::new();
+    let file = source_map.add_file("<test>", code);
+    let lexer = source_map.lexer(file);
     let mut parser = ast::Parser::new(lexer);
 
     parser.read_all_as_block()
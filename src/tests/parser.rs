@@ -1,4 +1,4 @@
-use crate::ast::{Lexer, ParseError, Parser};
+use crate::ast::{LexerError, ParseError, Parser, SourceMap, Value, AST};
 
 #[test]
 fn parses_number_literals() {
@@ -6,6 +6,32 @@ fn parses_number_literals() {
     assert_parse("1234.5678 ", "1234.5678")
 }
 
+#[test]
+fn parses_extended_number_literals() {
+    assert_parse("0xFF", "255");
+    assert_parse("0b1010", "10");
+    assert_parse("0o777", "511");
+    assert_parse("1_000_000", "1000000");
+    assert_parse("0xFFFF_FFFF", "4294967295");
+    assert_parse("1.5e3", "1500");
+    assert_parse("25e-2", "0.25");
+    assert_parse("0x7FFFFFFFFFFFFFFF", "9223372036854775807");
+}
+
+#[test]
+fn test_out_of_range_number_literals() {
+    match parse("val a = 0xFFFFFFFFFFFFFFFFFF") {
+        Err(ParseError::CouldNotParseNumber(location)) => {
+            assert_eq!((location.from.line, location.from.column), (0, 8));
+            assert_eq!((location.to.line, location.to.column), (0, 28));
+        },
+        result => panic!("Expected CouldNotParseNumber, got {:?}", result)
+    }
+
+    assert!(matches!(parse("9223372036854775808"), Err(ParseError::CouldNotParseNumber(_))));
+    assert!(matches!(parse("1e999"), Err(ParseError::CouldNotParseNumber(_))));
+}
+
 #[test]
 fn test_negative_number_literals() {
     assert_parse("-1234", "(- 1234)");
@@ -27,6 +53,26 @@ fn test_negating_expressions() {
 fn test_string_literals() {
     assert_parse("\"Hello world!\"", "\"Hello world!\"");
     assert_parse("\"\\\"\\n\"", "\"\\\"\\n\"");
+    assert_parse("\"\\u{48}i\"", "\"Hi\"");
+    assert_parse("r\"\\n\"", "\"\\\\n\"");
+
+    assert_parse_error("\"\\a\"");
+}
+
+#[test]
+fn test_string_interpolation() {
+    assert_parse(
+        "\"Hello ${name}, you have ${count + 1} items\"",
+        "(+ (+ (+ (+ \"Hello \" (to_s name)) \", you have \") (to_s (+ count 1))) \" items\")"
+    );
+    assert_parse("\"${a}\"", "(to_s a)");
+    assert_parse("\"${a}${b}\"", "(+ (to_s a) (to_s b))");
+    assert_parse("\"${a.b(1)} c\".size", "(size (+ (to_s (b a 1)) \" c\"))");
+    assert_parse("print \"${a}!\", 1", "(print self (+ (to_s a) \"!\") 1)");
+    assert_parse("\"a ${\"b ${c}\"}\"", "(+ \"a \" (to_s (+ \"b \" (to_s c))))");
+
+    assert_parse_error("\"${}\"");
+    assert_parse_error("\"${a b c, }\"");
 }
 
 #[test]
@@ -52,6 +98,23 @@ fn test_recursive_assignment() {
     assert_parse("rec val a = 1; a * 2; rec val b = 2; a + b", "(let-rec a 1) (* a 2) (let-rec b 2) (+ a b)");
 }
 
+#[test]
+fn test_vars() {
+    assert_parse("var a = 15", "(let-var a 15)");
+    assert_parse("var a: Int = 5 * 5", "(let-var a (type-assert (* 5 5) Int))");
+    assert_parse("@var a = 15", "(@let-var a 15)");
+    assert_parse("a = a * 2", "(= a (* a 2))");
+    assert_parse("a = b = 1", "(= a (= b 1))");
+}
+
+#[test]
+fn test_compound_assignments() {
+    assert_parse("a += 1", "(= a (+ a 1))");
+    assert_parse("a -= 1 + 2", "(= a (- a (+ 1 2)))");
+    assert_parse("a *= 2", "(= a (* a 2))");
+    assert_parse("a /= 2", "(= a (/ a 2))");
+}
+
 #[test]
 fn test_prefix_operators() {
     assert_parse("!a", "(! a)");
@@ -172,6 +235,26 @@ fn test_method_call_priority() {
     assert_parse("one(a, b) + c", "(+ (one self a b) c)");
 }
 
+#[test]
+fn test_list_literals() {
+    assert_parse("[]", "(list)");
+    assert_parse("[1, 2, 3]", "(list 1 2 3)");
+    assert_parse("[1 + 2, a.b, [c]]", "(list (+ 1 2) (b a) (list c))");
+    assert_parse("[\n  1,\n  2,\n]", "(list 1 2)");
+    assert_parse("method [1, 2]", "(method self (list 1 2))");
+    assert_parse("a\n[1]", "a (list 1)");
+}
+
+#[test]
+fn test_list_indexing() {
+    assert_parse("a[0]", "([] a 0)");
+    assert_parse("a[1 + 2]", "([] a (+ 1 2))");
+    assert_parse("a[0][1]", "([] ([] a 0) 1)");
+    assert_parse("a.b[0].c", "(c ([] (b a) 0))");
+    assert_parse("[1, 2][0] + 1", "(+ ([] (list 1 2) 0) 1)");
+    assert_parse("a [0]", "(a self (list 0))");
+}
+
 #[test]
 fn test_fns() {
     assert_parse("{ a\n b\n }", "(fn [] { a b })");
@@ -366,6 +449,133 @@ fn test_ifs() {
     assert_parse("if a then b else if c then d else e", "(if a b (if c d e))");
 }
 
+#[test]
+fn test_while_loops() {
+    assert_parse("while a { b }", "(while a b)");
+    assert_parse("while a < 10 { a += 1 }", "(while (< a 10) (= a (+ a 1)))");
+    assert_parse("while a {}", "(while a {})");
+    assert_parse("while a {\n  b\n  c\n}", "(while a { b c })");
+    assert_parse("while a { if b then break\ncontinue }", "(while a { (if b (break)) (continue) })");
+}
+
+#[test]
+fn test_comments() {
+    assert_parse("a # comment\nb", "a b");
+    assert_parse("a #[ comment ]# + b", "(+ a b)");
+    assert_parse("## Docs\nval a = 42; a", "(let a 42) a");
+}
+
+#[test]
+fn test_doc_comments() {
+    let asts = parse_asts("
+        ## The answer
+        ## to everything
+        val answer = 42
+
+        ## Adds one
+        @val inc = (a) a + 1
+
+        # Not documentation
+        val b = 1
+
+        ## A lambda
+        (a) a
+    ").unwrap();
+
+    assert_eq!(asts[0].doc_comment(), Some("The answer\nto everything"));
+    assert_eq!(asts[1].doc_comment(), Some("Adds one"));
+    assert_eq!(asts[2].doc_comment(), None);
+    assert_eq!(asts[3].doc_comment(), Some("A lambda"));
+
+    match &asts[1].value {
+        Value::Let { value, .. } => assert_eq!(value.doc_comment(), None),
+        value => panic!("Expected a let, got {:?}", value)
+    }
+}
+
+#[test]
+fn test_empty_input() {
+    let (ast, errors) = parse_recovering("");
+
+    assert_eq!(format!("{}", ast), "{}");
+    assert!(errors.is_empty());
+
+    let (ast, errors) = parse_recovering("  # just a comment\n\n");
+
+    assert_eq!(format!("{}", ast), "{}");
+    assert!(errors.is_empty());
+}
+
+#[test]
+fn test_error_recovery() {
+    let (ast, errors) = parse_recovering("val = 1\nval b = 2\nfoo(1, )\nb + 3");
+
+    assert_eq!(format!("{}", ast), "{ (error) (let b 2) (error) (+ b 3) }");
+    assert_eq!(errors.len(), 2);
+
+    let (ast, errors) = parse_recovering(")\n}\na");
+
+    assert_eq!(format!("{}", ast), "{ (error) (error) a }");
+    assert_eq!(errors.len(), 2);
+}
+
+#[test]
+fn test_error_recovery_in_blocks() {
+    let (ast, errors) = parse_recovering("val f = () {\n  val = 1\n  a + )\n  42\n}\nf");
+
+    assert_eq!(format!("{}", ast), "{ (let f (fn [] { (error) (error) 42 })) f }");
+    assert_eq!(errors.len(), 2);
+
+    let (ast, errors) = parse_recovering("val f = () { 1 + }\nf");
+
+    assert_eq!(format!("{}", ast), "{ (let f (fn [] (error))) f }");
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn test_error_recovery_with_lexer_errors() {
+    let (ast, errors) = parse_recovering("val a = 1 ~ 2\nval b = \"\\q\"\nval c = 3");
+
+    assert_eq!(format!("{}", ast), "{ (error) (error) (let c 3) }");
+    assert!(matches!(errors[0], ParseError::LexerError(LexerError::UnexpectedToken('~', _))));
+    assert!(matches!(errors[1], ParseError::LexerError(LexerError::InvalidEscapeSequence(_))));
+}
+
+#[test]
+fn test_error_recovery_stops_at_unclosed_blocks() {
+    let (ast, errors) = parse_recovering("val a = 1\nval f = () {\n  a");
+
+    assert_eq!(format!("{}", ast), "{ (let a 1) (error) }");
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn test_locations_cover_the_tokens_of_the_node() {
+    let span = |ast: &AST| (ast.location.from.column, ast.location.to.column);
+    let asts = parse_asts("val f = (@n: Int) { n }; val a: Int = 1; val e = () {}").unwrap();
+
+    let (function, value, empty) = match asts.iter().map(|ast| &ast.value).collect::<Vec<_>>()[..] {
+        [Value::Let { value: function, .. }, Value::Let { value, .. }, Value::Let { value: empty, .. }] => (function, value, empty),
+        _ => panic!("Expected three lets")
+    };
+
+    // The function starts at its opening paren, and its params at `@`
+    assert_eq!(span(function), (8, 23));
+    match &function.value {
+        Value::Function(func) => assert_eq!((func.params[0].location.from.column, func.params[0].location.to.column), (9, 16)),
+        _ => panic!("Expected a function")
+    }
+
+    // The type assert includes the value after the type
+    assert_eq!(span(value), (32, 39));
+
+    // An empty block is empty instead of including the next token
+    match &empty.value {
+        Value::Function(func) => assert_eq!(span(&func.body), (53, 53)),
+        _ => panic!("Expected a function")
+    }
+}
+
 fn assert_parse(code: &str, expected: &str) {
     let result = parse(code).expect(format!("Could not parse code {}", code).as_str());
 
@@ -378,8 +588,24 @@ fn assert_parse_error(code: &str) {
     assert!(matches!(result, Err(_)))
 }
 
+fn parse_asts(code: &str) -> Result<Vec<AST>, ParseError> {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", code);
+    let lexer = source_map.lexer(file);
+    let mut parser = Parser::new(lexer);
+    let mut result = Vec::new();
+
+    while parser.has_next()? {
+        result.push(parser.next(false)?);
+    }
+
+    Ok(result)
+}
+
 fn parse(code: &str) -> Result<String, ParseError> {
-    let lexer = Lexer::new("<test>", code.chars());
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", code);
+    let lexer = source_map.lexer(file);
     let mut parser = Parser::new(lexer);
     // let mut result = Vec::new();
     let mut result = String::new();
@@ -396,4 +622,12 @@ fn parse(code: &str) -> Result<String, ParseError> {
     result.pop(); // Remove last space
 
     Ok(result)
-}
\ No newline at end of file
+}
+
+fn parse_recovering(code: &str) -> (AST, Vec<ParseError>) {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", code);
+    let mut parser = Parser::new(source_map.lexer(file));
+
+    parser.read_all_recovering()
+}
@@ -0,0 +1,83 @@
+use crate::ast::{FileId, Location, Parser, Position, SourceMap, TokenValue};
+
+#[test]
+fn test_file_ids() {
+    let mut source_map = SourceMap::new();
+
+    let a = source_map.add_file("a.ph", "val a = 1");
+    let b = source_map.add_file("b.ph", "val b = 2");
+
+    assert_ne!(a, b);
+    assert_eq!(source_map.file(a).name.as_ref(), "a.ph");
+    assert_eq!(source_map.file(b).source.as_ref(), "val b = 2");
+}
+
+#[test]
+fn test_token_byte_offsets() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "val ä = \"ünï\"\n  ä");
+    let mut lexer = source_map.lexer(file);
+
+    let mut texts = Vec::new();
+    loop {
+        let token = lexer.next().unwrap();
+        if token.value == TokenValue::EOF {
+            assert_eq!(token.location.from.offset as usize, source_map.file(file).source.len());
+            break;
+        }
+
+        texts.push(source_map.text(&token.location).to_string());
+    }
+
+    assert_eq!(texts, vec!["val", "ä", "=", "\"ünï\"", "\n", "ä"]);
+}
+
+#[test]
+fn test_source_text_of_ast() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "val a = 1\nval b = foo(a,  \"ä\") + 2 # comment");
+
+    let ast = Parser::new(source_map.lexer(file)).read_all_as_block().unwrap();
+
+    assert_eq!(source_map.text(&ast.location), "val a = 1\nval b = foo(a,  \"ä\") + 2");
+}
+
+#[test]
+fn test_lines_and_positions() {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", "first\nsécond\r\n\nlast");
+    let file = source_map.file(file);
+
+    assert_eq!(file.line_count(), 4);
+    assert_eq!(file.line(1), "sécond");
+    assert_eq!(file.line(2), "");
+    assert_eq!(file.line(3), "last");
+
+    let position = file.position(9);
+    assert_eq!((position.line, position.column, position.offset), (1, 2, 9));
+
+    let end = file.position(100);
+    assert_eq!((end.line, end.column, end.offset as usize), (3, 4, file.source.len()));
+}
+
+#[test]
+fn test_extend_locations() {
+    let location = |from: u32, to: u32| Location {
+        file: FileId(0),
+        from: Position { line: 0, column: from as i32, offset: from },
+        to: Position { line: 0, column: to as i32, offset: to }
+    };
+
+    let extended = location(5, 10).extend(&location(2, 7));
+    assert_eq!((extended.from.offset, extended.to.offset), (2, 10));
+
+    let extended = location(2, 4).extend(&location(6, 8));
+    assert_eq!((extended.from.offset, extended.to.offset), (2, 8));
+    assert_eq!(extended.len(), 6);
+
+    let extended = location(5, 10).extend(&Location::null(FileId(0)));
+    assert_eq!((extended.from.offset, extended.to.offset), (5, 10));
+
+    let extended = Location::null(FileId(0)).extend(&location(5, 10));
+    assert_eq!((extended.from.offset, extended.to.offset), (5, 10));
+}
@@ -0,0 +1,104 @@
+use crate::ast::{Parser, SourceMap, SyntaxElement, SyntaxKind, SyntaxNode, TokenValue, TriviaKind};
+
+#[test]
+fn test_syntax_tree_keeps_the_source() {
+    assert_round_trip("42");
+    assert_round_trip("  val a = 1 + 2  \n\n  a * 3\n");
+    assert_round_trip("val a = 1 # the answer\n# nothing here\n  a");
+    assert_round_trip("#[ block #[ nested ]# ]#\nval a = 1\n## docs\nval b = (x: Int): Int { x }\nb(a)");
+    assert_round_trip("val s = \"a ${ b + 1 } c\\n\"; r#\"raw\"#\n\"\"\"\n    multi\n    line\n    \"\"\"");
+    assert_round_trip("val f = (@t: Type, x: t) { if x then 1 else 2 }\nval g = (a: (x: Int): Int): Int a(1)");
+    assert_round_trip("val name = \"ünïcödé\"   # ✓\nname.length");
+}
+
+#[test]
+fn test_syntax_tree_trivia() {
+    let tree = syntax_tree("val a = 1 # one\n  ## two\n  #[ three ]# a");
+    let tokens = tree.tokens();
+
+    let one = tokens.iter().find(|token| token.text.as_ref() == "1").unwrap();
+    assert_eq!(trivia_kinds(&one.trailing_trivia), vec![TriviaKind::Whitespace, TriviaKind::Comment]);
+    assert_eq!(one.trailing_trivia[1].text.as_ref(), "# one");
+
+    // A line with only trivia on it belongs to its newline
+    let second_newline = tokens.iter().filter(|token| token.token.value == TokenValue::NewLine).nth(1).unwrap();
+    assert_eq!(trivia_kinds(&second_newline.leading_trivia), vec![TriviaKind::Whitespace, TriviaKind::DocComment]);
+    assert_eq!(second_newline.leading_trivia[1].text.as_ref(), "## two");
+
+    let a = tokens.iter().rev().find(|token| token.text.as_ref() == "a").unwrap();
+    assert_eq!(
+        trivia_kinds(&a.leading_trivia),
+        vec![TriviaKind::Whitespace, TriviaKind::BlockComment, TriviaKind::Whitespace]
+    );
+    assert_eq!(a.leading_trivia[1].text.as_ref(), "#[ three ]#");
+
+    assert_eq!(tokens.last().unwrap().token.value, TokenValue::EOF);
+}
+
+#[test]
+fn test_syntax_tree_structure() {
+    let tree = syntax_tree("val a = 1 + 2 # three\nfoo(a)");
+    assert_eq!(tree.kind, SyntaxKind::Module);
+
+    let nodes = child_nodes(&tree);
+    assert_eq!(nodes.iter().map(|node| node.kind).collect::<Vec<_>>(), vec![SyntaxKind::Let, SyntaxKind::Call]);
+
+    assert_eq!(nodes[0].text(), "val a = 1 + 2 # three");
+    assert_eq!(nodes[1].text(), "foo(a)");
+
+    let plus = child_nodes(nodes[0])[0];
+    assert_eq!(plus.kind, SyntaxKind::Call);
+    assert_eq!(plus.text(), "1 + 2 # three");
+    assert_eq!(
+        child_nodes(plus).iter().map(|node| node.text()).collect::<Vec<_>>(),
+        vec!["1 ", "2 # three"]
+    );
+}
+
+#[test]
+fn test_syntax_tree_to_ast() {
+    assert_to_ast("val a = 1 + 2 # three\n\n  foo(a)  ");
+    assert_to_ast("## A function\nval f = (@t: Type, x: t): t { x }\nf(Int, 42)");
+    assert_to_ast("val s = \"${a} and ${ b.c(1) }\"\nif s then s else \"\"");
+
+    let tree = syntax_tree("val a = 1\nval b = a * (2 + 3)");
+    let let_b = child_nodes(&tree)[1];
+
+    assert_eq!(format!("{}", let_b.to_ast().unwrap()), "(let b (* a (+ 2 3)))");
+}
+
+fn assert_round_trip(code: &str) {
+    assert_eq!(syntax_tree(code).text(), code);
+}
+
+fn assert_to_ast(code: &str) {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", code);
+    let mut parser = Parser::new(source_map.lexer(file));
+    let expected = parser.read_all_as_block().unwrap();
+
+    let actual = syntax_tree(code).to_ast().unwrap();
+
+    assert_eq!(format!("{}", actual), format!("{}", expected));
+}
+
+fn syntax_tree(code: &str) -> SyntaxNode {
+    let mut source_map = SourceMap::new();
+    let file = source_map.add_file("<test>", code);
+    let mut parser = Parser::new(source_map.lossless_lexer(file));
+
+    parser.read_all_as_syntax_tree().unwrap()
+}
+
+fn child_nodes(node: &SyntaxNode) -> Vec<&SyntaxNode> {
+    node.children.iter()
+        .filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None
+        })
+        .collect()
+}
+
+fn trivia_kinds(trivia: &[crate::ast::Trivia]) -> Vec<TriviaKind> {
+    trivia.iter().map(|trivia| trivia.kind).collect()
+}
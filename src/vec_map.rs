@@ -37,6 +37,11 @@ impl <K: Sized+PartialEq, V: Sized> VecMap<K, V> {
         self.values.push((key, value))
     }
 
+    /// Removes the entries inserted after the map had `len` entries
+    pub fn truncate(&mut self, len: usize) {
+        self.values.truncate(len)
+    }
+
     pub fn iter(&self) -> Iter<(K, V)> {
         self.values.iter()
     }